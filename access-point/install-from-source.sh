@@ -86,7 +86,7 @@ fi
 
 step "Checking required commands"
 
-REQUIRED_CMDS=(hostapd dnsmasq iw ip iptables systemctl sha256sum xxd)
+REQUIRED_CMDS=(hostapd dnsmasq iw ip iptables systemctl)
 MISSING=()
 for cmd in "${REQUIRED_CMDS[@]}"; do
     command -v "$cmd" &>/dev/null || MISSING+=("$cmd")
@@ -95,14 +95,11 @@ if [[ ${#MISSING[@]} -gt 0 ]]; then
     err "Missing required commands: ${MISSING[*]}"
     cat <<HINT
 Install them with your distro's package manager, e.g.:
-  Arch:    pacman -S hostapd dnsmasq iw iproute2 iptables coreutils xxd
-  Fedora:  dnf install hostapd dnsmasq iw iproute iptables iptables-services coreutils vim-common
-  openSUSE: zypper install hostapd dnsmasq iw iproute2 iptables coreutils xxd
+  Arch:    pacman -S hostapd dnsmasq iw iproute2 iptables
+  Fedora:  dnf install hostapd dnsmasq iw iproute iptables iptables-services
+  openSUSE: zypper install hostapd dnsmasq iw iproute2 iptables
   Debian:  apt install hostapd dnsmasq iw iproute2 iptables \\
-                       netfilter-persistent iptables-persistent xxd
-
-(sha256sum ships in coreutils; xxd ships in the 'xxd' package on
-Debian/Ubuntu/Arch and in 'vim-common' on Fedora/RHEL.)
+                       netfilter-persistent iptables-persistent
 HINT
     exit 1
 fi
@@ -126,6 +123,8 @@ if [[ ! -d "$SITE_SRC" ]]; then
     exit 1
 fi
 ok "Found binary and frontend assets"
+# The admin password is hashed before the binary is installed.
+SHAREBOXX_BIN="$BIN_SRC"
 
 # ── Setup flow ──────────────────────────────────────────────────────────────
 
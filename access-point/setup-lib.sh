@@ -92,6 +92,9 @@ CLEANUP_TIMER="/etc/systemd/system/shareboxx-cleanup.timer"
 SHAREBOXX_HOME="/var/lib/shareboxx"
 SHAREBOXX_FILES_DIR="$SHAREBOXX_HOME/files"
 SHAREBOXX_CONFIG_FILE="$SHAREBOXX_HOME/config.json"
+# Used to hash the admin password; the source installer points this at the
+# freshly built binary before it is installed.
+SHAREBOXX_BIN="${SHAREBOXX_BIN:-/usr/bin/shareboxx}"
 TEMPFILE_MAX_AGE_MINUTES="2880"  # 48h — generous enough not to kill long uploads
 
 # Legacy HTTPS paths — only referenced by cleanup_legacy_https() below for
@@ -430,7 +433,7 @@ prompt_config() {
 
 # ── Admin / expiration prompts ──────────────────────────────────────────────
 # Sets: EXPIRATION_ENABLED ("true"/"false"), EXPIRATION_DAYS,
-#       ADMIN_HASH (hex sha256), ADMIN_SALT (hex)
+#       ADMIN_HASH (from `shareboxx hash-password`)
 #
 # Honours $KEEP_CONFIG=1 — when set and a config.json already exists, we
 # leave the existing values in place and skip the prompts entirely. This is
@@ -445,8 +448,8 @@ prompt_admin_config() {
         EXPIRATION_ENABLED=""
         EXPIRATION_DAYS=""
         ADMIN_HASH=""
-        ADMIN_SALT=""
         CHAT_ENABLED=""
+        STRIP_METADATA=""
         return
     fi
 
@@ -473,6 +476,13 @@ prompt_admin_config() {
         CHAT_ENABLED="false"
     fi
 
+    # Metadata stripping (default no — uploads are stored byte-for-byte).
+    if confirm "Strip EXIF/GPS metadata from uploaded photos?" n; then
+        STRIP_METADATA="true"
+    else
+        STRIP_METADATA="false"
+    fi
+
     # Admin password (twice). Echo suppressed.
     local pw1 pw2
     while true; do
@@ -491,26 +501,16 @@ prompt_admin_config() {
         break
     done
 
-    # Generate a 16-byte hex salt and compute sha256(salt_bytes || password).
-    # We avoid putting the password on a command line by piping it into
-    # sha256sum on stdin alongside the (binary-decoded) salt.
-    if ! command -v sha256sum &>/dev/null; then
-        err "sha256sum not found — required to set the admin password."
+    # Let the server hash it, so the format always matches what it
+    # verifies. The password goes over stdin, never on a command line.
+    if [[ ! -x "$SHAREBOXX_BIN" ]]; then
+        err "$SHAREBOXX_BIN not found — required to set the admin password."
         exit 1
     fi
-    if ! command -v xxd &>/dev/null; then
-        err "xxd not found — required to set the admin password (install via the 'xxd' or 'vim-common' package)."
-        exit 1
-    fi
-    ADMIN_SALT=$(head -c 16 /dev/urandom | xxd -p -c 256)
-    # Concatenate salt bytes + password bytes, hash, take first column.
-    ADMIN_HASH=$(
-        { printf '%s' "$ADMIN_SALT" | xxd -r -p; printf '%s' "$pw1"; } \
-            | sha256sum | awk '{print $1}'
-    )
+    ADMIN_HASH=$(printf '%s' "$pw1" | "$SHAREBOXX_BIN" hash-password) || ADMIN_HASH=""
     unset pw1 pw2
 
-    if [[ -z "$ADMIN_HASH" || ${#ADMIN_HASH} -ne 64 ]]; then
+    if [[ "$ADMIN_HASH" != '$argon2'* ]]; then
         err "Failed to compute password hash."
         exit 1
     fi
@@ -526,6 +526,11 @@ prompt_admin_config() {
     else
         info "Chat panel: DISABLED"
     fi
+    if [[ "$STRIP_METADATA" == "true" ]]; then
+        info "Photo metadata stripping: ENABLED"
+    else
+        info "Photo metadata stripping: DISABLED"
+    fi
     info "Admin password: set"
 }
 
@@ -542,9 +547,10 @@ write_config_json() {
 
     install -d -m 755 "$SHAREBOXX_HOME"
 
-    local enabled chat_enabled_json
+    local enabled chat_enabled_json strip_metadata_json
     if [[ "$EXPIRATION_ENABLED" == "true" ]]; then enabled="true"; else enabled="false"; fi
     if [[ "$CHAT_ENABLED" == "false" ]]; then chat_enabled_json="false"; else chat_enabled_json="true"; fi
+    if [[ "${STRIP_METADATA:-}" == "true" ]]; then strip_metadata_json="true"; else strip_metadata_json="false"; fi
 
     # Use a heredoc so we don't shell-out to jq or python.
     cat > "$SHAREBOXX_CONFIG_FILE" <<JSON
@@ -552,8 +558,8 @@ write_config_json() {
   "expiration_enabled": $enabled,
   "expiration_days": $EXPIRATION_DAYS,
   "admin_password_hash": "$ADMIN_HASH",
-  "admin_salt": "$ADMIN_SALT",
-  "chat_enabled": $chat_enabled_json
+  "chat_enabled": $chat_enabled_json,
+  "strip_metadata": $strip_metadata_json
 }
 JSON
 
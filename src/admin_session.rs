@@ -1,62 +1,174 @@
-use lazy_static::lazy_static;
+//! Admin sessions, kept in `uploads.db` so a restart doesn't log the admin
+//! out. Only a SHA-256 of each token is stored: a copy of the database is
+//! no use for taking over a session.
+
 use rand::RngCore;
-use std::collections::HashMap;
-use std::sync::Mutex;
-use std::time::{SystemTime, UNIX_EPOCH};
+use sha2::{Digest, Sha256};
 
 const SESSION_TTL_SECS: u64 = 8 * 60 * 60; // 8h
 
-lazy_static! {
-    static ref SESSIONS: Mutex<HashMap<String, u64>> = Mutex::new(HashMap::new());
+fn now_secs() -> u64 {
+    crate::uploads::now_secs()
 }
 
-fn now_secs() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0)
+fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
 }
 
-pub fn create_token() -> String {
+/// Start a session for `account_id` (`None` for the config.json admin) and
+/// return its token, or `None` if it couldn't be stored.
+pub fn create_token(account_id: Option<i64>) -> Option<String> {
     let mut buf = [0u8; 32];
     rand::rngs::OsRng.fill_bytes(&mut buf);
     let token = hex::encode(buf);
-    if let Ok(mut sessions) = SESSIONS.lock() {
-        // Opportunistic GC of expired tokens.
-        let now = now_secs();
-        sessions.retain(|_, exp| *exp > now);
-        sessions.insert(token.clone(), now + SESSION_TTL_SECS);
+    let now = now_secs();
+    let stored = crate::db::open().and_then(|conn| {
+        crate::db::create_session(&conn, &token_hash(&token), account_id, now, now + SESSION_TTL_SECS)
+    });
+    match stored {
+        Ok(()) => Some(token),
+        Err(e) => {
+            eprintln!("failed to store admin session: {}", e);
+            None
+        }
     }
-    token
 }
 
-pub fn validate(token: &str) -> bool {
+/// The session behind `token`, if it is still live.
+pub fn validate(token: &str) -> Option<crate::db::AdminSession> {
     if token.is_empty() {
-        return false;
+        return None;
     }
     let now = now_secs();
-    let Ok(mut sessions) = SESSIONS.lock() else {
-        return false;
-    };
-    match sessions.get(token).copied() {
-        Some(exp) if exp > now => {
-            // Sliding window — refresh expiry on every successful check.
-            sessions.insert(token.to_string(), now + SESSION_TTL_SECS);
-            true
-        }
-        Some(_) => {
-            sessions.remove(token);
-            false
-        }
-        None => false,
-    }
+    // Sliding window — refresh expiry on every successful check.
+    crate::db::open()
+        .and_then(|conn| crate::db::touch_session(&conn, &token_hash(token), now, now + SESSION_TTL_SECS))
+        .ok()
+        .flatten()
 }
 
 pub fn revoke(token: &str) {
-    if let Ok(mut sessions) = SESSIONS.lock() {
-        sessions.remove(token);
+    if let Err(e) = crate::db::open().and_then(|conn| crate::db::delete_session(&conn, &token_hash(token))) {
+        eprintln!("failed to revoke admin session: {}", e);
+    }
+}
+
+/// End every session, including the caller's. Returns how many there were.
+pub fn revoke_all() -> rusqlite::Result<usize> {
+    crate::db::delete_all_sessions(&crate::db::open()?)
+}
+
+/// Sessions that haven't expired.
+pub fn active_count() -> rusqlite::Result<u64> {
+    crate::db::count_sessions(&crate::db::open()?, now_secs())
+}
+
+/// Drop expired sessions; run from the periodic sweeps in `main`.
+pub fn expire_stale() {
+    match crate::db::open().and_then(|conn| crate::db::delete_expired_sessions(&conn, now_secs())) {
+        Ok(0) => {}
+        Ok(n) => println!("session sweep: removed {} expired admin session(s)", n),
+        Err(e) => eprintln!("session sweep: {}", e),
     }
 }
 
 pub const COOKIE_NAME: &str = "shareboxx_admin";
 pub const COOKIE_MAX_AGE: u64 = SESSION_TTL_SECS;
+
+/// Set-Cookie value carrying `token`. HttpOnly keeps it away from scripts
+/// (an uploaded HTML file is served from the same origin); SameSite=Strict
+/// keeps other sites from sending it. No `Secure`, the box is HTTP-only.
+pub fn session_cookie(token: &str) -> String {
+    format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict",
+        COOKIE_NAME, token, COOKIE_MAX_AGE
+    )
+}
+
+/// Set-Cookie value that removes the session cookie.
+pub fn expired_cookie() -> String {
+    format!("{}=; Path=/; Max-Age=0; HttpOnly; SameSite=Strict", COOKIE_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::{params, Connection};
+
+    fn expires_at(conn: &Connection, token: &str) -> Option<u64> {
+        conn.query_row(
+            "SELECT expires_at FROM admin_sessions WHERE token_hash = ?1",
+            params![token_hash(token)],
+            |r| r.get::<_, i64>(0),
+        )
+        .ok()
+        .map(|t| t as u64)
+    }
+
+    fn set_expires_at(conn: &Connection, token: &str, at: u64) {
+        conn.execute(
+            "UPDATE admin_sessions SET expires_at = ?2 WHERE token_hash = ?1",
+            params![token_hash(token), at as i64],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn only_the_token_hash_is_stored() {
+        let _lock = crate::settings::lock_for_tests();
+        let conn = crate::db::open_for_tests();
+        let token = create_token(Some(7)).unwrap();
+        let rows: Vec<String> = conn
+            .prepare("SELECT token_hash || ' ' || IFNULL(account_id, '') FROM admin_sessions")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert!(rows.contains(&format!("{} 7", token_hash(&token))));
+        assert!(rows.iter().all(|row| !row.contains(&token)));
+        assert_eq!(validate(&token).unwrap().account_id, Some(7));
+        assert!(validate(&token_hash(&token)).is_none());
+        assert!(validate("").is_none());
+        revoke(&token);
+        assert!(validate(&token).is_none());
+    }
+
+    #[test]
+    fn expiry_slides_on_use_and_then_runs_out() {
+        let _lock = crate::settings::lock_for_tests();
+        let conn = crate::db::open_for_tests();
+        let token = create_token(None).unwrap();
+        let now = now_secs();
+
+        set_expires_at(&conn, &token, now + 60);
+        assert_eq!(validate(&token).unwrap().account_id, None);
+        assert!(expires_at(&conn, &token).unwrap() >= now + SESSION_TTL_SECS);
+
+        // Unused for the whole window.
+        set_expires_at(&conn, &token, now - 1);
+        assert!(validate(&token).is_none());
+        assert_eq!(expires_at(&conn, &token), Some(now - 1));
+        revoke(&token);
+    }
+
+    #[test]
+    fn sweep_and_log_out_all() {
+        let _lock = crate::settings::lock_for_tests();
+        let conn = crate::db::open_for_tests();
+        let stale = create_token(None).unwrap();
+        let live = create_token(Some(1)).unwrap();
+        set_expires_at(&conn, &stale, now_secs() - 1);
+
+        expire_stale();
+        assert_eq!(expires_at(&conn, &stale), None);
+        assert!(expires_at(&conn, &live).is_some());
+
+        let other = create_token(Some(2)).unwrap();
+        assert!(active_count().unwrap() >= 2);
+        assert!(revoke_all().unwrap() >= 2);
+        assert!(validate(&live).is_none());
+        assert!(validate(&other).is_none());
+        assert_eq!(active_count().unwrap(), 0);
+    }
+}
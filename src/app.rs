@@ -32,7 +32,21 @@ fn encode_uri_component(s: &str) -> String {
     result
 }
 
-fn format_bytes(bytes: u64) -> String {
+/// URL for `name` inside the share directory `dir`, under `prefix`
+/// (e.g. "/files/"), with every path segment percent-encoded.
+pub(crate) fn share_url(prefix: &str, dir: &str, name: &str) -> String {
+    let mut encoded = String::from(prefix);
+    for seg in dir.split('/') {
+        if !seg.is_empty() {
+            encoded.push_str(&encode_uri_component(seg));
+            encoded.push('/');
+        }
+    }
+    encoded.push_str(&encode_uri_component(name));
+    encoded
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
     if bytes >= 1_073_741_824 {
         format!("{:.1} GB", bytes as f64 / 1_073_741_824.0)
     } else if bytes >= 1_048_576 {
@@ -126,6 +140,188 @@ mod notification {
     }
 }
 
+#[cfg(not(feature = "ssr"))]
+mod resumable {
+    use gloo_net::http::Request;
+
+    /// Bytes sent per PATCH. Small enough that a dropped connection only
+    /// costs a few seconds of re-sending on a slow phone link.
+    const CHUNK_SIZE: f64 = 4.0 * 1024.0 * 1024.0;
+    const MAX_RETRIES: u32 = 10;
+
+    #[derive(serde::Serialize)]
+    struct CreateUpload<'a> {
+        upload_path: &'a str,
+        file_name: &'a str,
+        size: u64,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct UploadStatus {
+        id: String,
+        offset: u64,
+    }
+
+    /// Where the server put a completed upload.
+    #[derive(Default, serde::Deserialize)]
+    pub struct Finalized {
+        pub duplicate_of: Option<String>,
+        /// Held in quarantine until the malware scan finishes.
+        #[serde(default)]
+        pub pending_scan: bool,
+    }
+
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok().flatten()
+    }
+
+    /// Remembers the server-side upload id per file, so picking the same
+    /// file again after a reload resumes instead of starting over.
+    fn storage_key(upload_path: &str, file: &web_sys::File) -> String {
+        format!(
+            "shareboxx_upload:{}{}:{}:{}",
+            upload_path,
+            file.name(),
+            file.size(),
+            file.last_modified()
+        )
+    }
+
+    async fn sleep(ms: i32) {
+        let promise = js_sys::Promise::new(&mut |resolve, _| {
+            if let Some(window) = web_sys::window() {
+                let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, ms);
+            }
+        });
+        let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+    }
+
+    async fn fetch_status(url: &str) -> Option<UploadStatus> {
+        let resp = Request::get(url).send().await.ok()?;
+        if !resp.ok() {
+            return None;
+        }
+        resp.json::<UploadStatus>().await.ok()
+    }
+
+    async fn resume_or_create(
+        upload_path: &str,
+        file: &web_sys::File,
+        key: &str,
+    ) -> Result<UploadStatus, String> {
+        let previous = local_storage().and_then(|s| s.get_item(key).ok().flatten());
+        if let Some(id) = previous
+            && let Some(status) = fetch_status(&format!("/upload/resumable/{}", id)).await
+        {
+            return Ok(status);
+        }
+
+        let resp = Request::post("/upload/resumable")
+            .json(&CreateUpload {
+                upload_path,
+                file_name: &file.name(),
+                size: file.size() as u64,
+            })
+            .map_err(|e| e.to_string())?
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !resp.ok() {
+            return Err(resp.text().await.unwrap_or_else(|_| resp.status_text()));
+        }
+        let status: UploadStatus = resp.json().await.map_err(|e| e.to_string())?;
+        if let Some(storage) = local_storage() {
+            let _ = storage.set_item(key, &status.id);
+        }
+        Ok(status)
+    }
+
+    /// Upload `file` into `upload_path` in chunks, retrying with backoff and
+    /// resuming from the server's last acknowledged byte after a failure.
+    /// `on_progress` receives the number of bytes the server has confirmed.
+    /// Returns the path of the existing copy when the server already had
+    /// identical content.
+    pub async fn upload(
+        file: &web_sys::File,
+        upload_path: &str,
+        on_progress: impl Fn(u64),
+    ) -> Result<Finalized, String> {
+        let key = storage_key(upload_path, file);
+        let size = file.size() as u64;
+        let status = resume_or_create(upload_path, file, &key).await?;
+        let url = format!("/upload/resumable/{}", status.id);
+        let mut offset = status.offset;
+        let mut retries = 0;
+        let mut finalized = Finalized::default();
+        on_progress(offset);
+
+        // Always send at least one PATCH so empty files complete too.
+        loop {
+            let end = (offset as f64 + CHUNK_SIZE).min(size as f64);
+            let chunk = file
+                .slice_with_f64_and_f64(offset as f64, end)
+                .map_err(|_| "could not read file".to_string())?;
+            let sent = Request::patch(&url)
+                .header("Upload-Offset", &offset.to_string())
+                .body(chunk)
+                .map_err(|e| e.to_string())?
+                .send()
+                .await;
+
+            match sent {
+                // 409 means we were out of sync; either way the server says
+                // where to carry on from.
+                Ok(resp) if resp.ok() || resp.status() == 409 => {
+                    if let Some(o) = resp
+                        .headers()
+                        .get("Upload-Offset")
+                        .and_then(|v| v.parse::<u64>().ok())
+                    {
+                        offset = o;
+                        retries = 0;
+                        on_progress(offset);
+                        if offset >= size {
+                            // The completing chunk answers with where the
+                            // file ended up.
+                            if let Ok(done) = resp.json::<Finalized>().await {
+                                finalized = done;
+                            }
+                            break;
+                        }
+                        continue;
+                    }
+                }
+                Ok(resp) if resp.status() == 404 => {
+                    if let Some(storage) = local_storage() {
+                        let _ = storage.remove_item(&key);
+                    }
+                    return Err("upload expired on the server".to_string());
+                }
+                Ok(resp) if (400..500).contains(&resp.status()) => {
+                    return Err(resp.text().await.unwrap_or_else(|_| resp.status_text()));
+                }
+                _ => {}
+            }
+
+            retries += 1;
+            if retries > MAX_RETRIES {
+                return Err("connection lost".to_string());
+            }
+            sleep(500 << retries.min(5)).await;
+            // Ask the server what actually arrived before sending more.
+            if let Some(status) = fetch_status(&url).await {
+                offset = status.offset;
+                on_progress(offset);
+            }
+        }
+
+        if let Some(storage) = local_storage() {
+            let _ = storage.remove_item(&key);
+        }
+        Ok(finalized)
+    }
+}
+
 #[cfg(not(feature = "ssr"))]
 mod random_name {
     use wasm_bindgen::prelude::*;
@@ -187,11 +383,207 @@ pub struct StatsData {
     pub uptime_seconds: u64,
 }
 
+/// Messages per page of chat history.
+pub const CHAT_PAGE_SIZE: u32 = 50;
+
+/// The channel that always exists, and where chat from before channels
+/// ended up.
+pub const DEFAULT_CHANNEL: &str = "general";
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ChatMessage {
+    pub id: i64,
+    pub name: String,
+    pub text: String,
+    pub timestamp: u64,
+    pub channel: String,
+    /// `text` rendered and sanitised by the server, filled in on the way
+    /// out; never stored.
+    #[serde(default)]
+    pub html: String,
+}
+
+/// Flood protection for the chat, applied per client address.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ChatLimits {
+    /// Messages a client may send back to back.
+    pub burst: u32,
+    /// Sustained rate once the burst is used up.
+    pub per_minute: u32,
+    /// Longest message accepted, in characters.
+    pub max_length: u32,
+    /// A client can't repeat its last message within this many seconds.
+    pub duplicate_window_secs: u64,
+}
+
+/// Words the admin doesn't want in the chat.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ChatFilter {
+    /// Matched case-insensitively against whole words.
+    pub words: Vec<String>,
+    pub mode: FilterMode,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterMode {
+    /// Replace matching words with asterisks.
+    #[default]
+    Mask,
+    /// Refuse the whole message.
+    Reject,
+}
+
+impl Default for ChatLimits {
+    fn default() -> Self {
+        Self {
+            burst: 5,
+            per_minute: 12,
+            max_length: 1000,
+            duplicate_window_secs: 30,
+        }
+    }
+}
+
+/// What an admin account is for. The box's own admin password (from
+/// `config.json`) always signs in as an owner named "admin".
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Owner,
+    FileModerator,
+    ChatModerator,
+}
+
+/// Something an admin call needs permission for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    /// Chat on/off and flood limits.
+    ManageSettings,
+    /// Accounts, sessions and the login log.
+    ManageAccounts,
+    /// Approving, moving and deleting shared files; the quarantine.
+    ManageFiles,
+    /// Channels, deleting messages or the whole history, mutes and the
+    /// word filter.
+    ModerateChat,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Owner, Role::FileModerator, Role::ChatModerator];
+
+    pub fn can(self, capability: Capability) -> bool {
+        match self {
+            Role::Owner => true,
+            Role::FileModerator => capability == Capability::ManageFiles,
+            Role::ChatModerator => capability == Capability::ModerateChat,
+        }
+    }
+
+    /// Name used in the database and in forms.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::FileModerator => "file_moderator",
+            Role::ChatModerator => "chat_moderator",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|r| r.as_str() == s)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Role::Owner => "Owner",
+            Role::FileModerator => "File moderator",
+            Role::ChatModerator => "Chat moderator",
+        }
+    }
+}
+
+/// Who is signed in to the admin page.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AdminIdentity {
+    pub name: String,
+    pub role: Role,
+}
+
+/// An admin account as listed for the owner.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AccountInfo {
+    pub id: i64,
+    pub name: String,
+    pub role: Role,
+    pub created_at: u64,
+}
+
+/// A chat update, pushed to browsers as an SSE `chat` event.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ChatEvent {
+    Message(ChatMessage),
+    /// The admin removed a single message.
+    Deleted { id: i64 },
+    /// A channel was created or deleted.
+    Channels,
+    /// The admin wiped the history.
+    Cleared,
+}
+
+/// What happened in a share directory, as pushed in SSE `files` events.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileChange {
+    Created,
+    Deleted,
+    /// Something changed out-of-band; the watcher can't say what.
+    Changed,
+    /// The whole share was re-read; every directory may be affected.
+    Rescan,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FileEvent {
+    pub change: FileChange,
+    /// The directory whose listing changed, in the same form as the file
+    /// browser's path: "" for the root, otherwise "a/b/".
+    pub dir: String,
+}
+
+impl FileEvent {
+    pub fn in_dir(dir: &str, change: FileChange) -> Self {
+        let dir = dir.trim_matches('/');
+        FileEvent {
+            change,
+            dir: if dir.is_empty() { String::new() } else { format!("{}/", dir) },
+        }
+    }
+
+    /// The event for a change to `rel_path` (a file or directory), which
+    /// shows up in its parent's listing.
+    pub fn for_path(rel_path: &str, change: FileChange) -> Self {
+        let parent = rel_path.trim_end_matches('/').rsplit_once('/').map(|(d, _)| d);
+        Self::in_dir(parent.unwrap_or(""), change)
+    }
+
+    /// Whether a listing of `dir` needs refetching.
+    pub fn affects(&self, dir: &str) -> bool {
+        self.change == FileChange::Rescan || self.dir == dir
+    }
+}
+
 #[cfg(feature = "ssr")]
-fn resolve_safe_path(
+pub fn resolve_safe_path(
     base: &std::path::Path,
     user_path: &str,
 ) -> Result<std::path::PathBuf, ServerFnError> {
+    // Dot-entries are server-internal (temp files); clients never name them.
+    if user_path.split('/').any(|seg| seg.starts_with('.')) {
+        return Err(sfn_err("Access denied".to_string()));
+    }
     let target = base.join(user_path);
     let canonical = if target.exists() {
         target.canonicalize()
@@ -275,8 +667,17 @@ fn HomePage() -> impl IntoView {
     let (active_tab, set_active_tab) = signal(ActiveTab::Files);
     let (user_count, set_user_count) = signal(0u32);
     let (chat_version, set_chat_version) = signal(0u32);
+    // Messages pushed over SSE since the chat history was loaded.
+    let live_messages = RwSignal::new(Vec::<ChatMessage>::new());
+    // Ids of messages the admin removed while the page was open.
+    let deleted_messages = RwSignal::new(std::collections::HashSet::<i64>::new());
+    let (chat_channel, set_chat_channel) = signal(DEFAULT_CHANNEL.to_string());
+    // Channels with messages the user hasn't looked at yet.
+    let unread_channels = RwSignal::new(std::collections::HashSet::<String>::new());
+    let (channels_version, set_channels_version) = signal(0u32);
     let (has_unread, set_has_unread) = signal(false);
     let (file_list_version, set_file_list_version) = signal(0u32);
+    let (file_event, set_file_event) = signal(None::<FileEvent>);
 
     let runtime_settings = Resource::new(|| (), |_| get_runtime_settings());
     let chat_enabled = move || {
@@ -298,20 +699,72 @@ fn HomePage() -> impl IntoView {
 
             let mut chat_stream = source.subscribe("chat").unwrap();
             let mut users_stream = source.subscribe("users").unwrap();
+            let mut files_stream = source.subscribe("files").unwrap();
 
-            // Handle chat updates in a separate task
+            // Handle chat updates in a separate task. The browser resends
+            // the last event id when it reconnects, and the server replays
+            // whatever was missed in between.
             spawn_local(async move {
-                while let Some(_) = chat_stream.next().await {
-                    set_chat_version.update(|v| *v += 1);
-                    if active_tab.get_untracked() != ActiveTab::Chat {
-                        set_has_unread.set(true);
-                        notification::play();
+                while let Some(Ok((_, msg))) = chat_stream.next().await {
+                    let Some(event) = msg
+                        .data()
+                        .as_string()
+                        .and_then(|d| serde_json::from_str::<ChatEvent>(&d).ok())
+                    else {
+                        continue;
+                    };
+                    match event {
+                        ChatEvent::Message(m) => {
+                            let is_new = live_messages.with_untracked(|l| l.last().is_none_or(|last| m.id > last.id));
+                            if m.channel != chat_channel.get_untracked() {
+                                unread_channels.update(|u| { u.insert(m.channel.clone()); });
+                            }
+                            if is_new {
+                                live_messages.update(|l| l.push(m));
+                            }
+                            if active_tab.get_untracked() != ActiveTab::Chat {
+                                set_has_unread.set(true);
+                                notification::play();
+                            }
+                        }
+                        ChatEvent::Channels => set_channels_version.update(|v| *v += 1),
+                        ChatEvent::Deleted { id } => {
+                            live_messages.update(|l| l.retain(|m| m.id != id));
+                            deleted_messages.update(|d| { d.insert(id); });
+                        }
+                        ChatEvent::Cleared => {
+                            live_messages.set(Vec::new());
+                            deleted_messages.update(|d| d.clear());
+                            set_chat_version.update(|v| *v += 1);
+                        }
+                    }
+                }
+            });
+
+            // Something on the share changed; listings decide for
+            // themselves whether it concerns them.
+            spawn_local(async move {
+                while let Some(Ok((_, msg))) = files_stream.next().await {
+                    if let Some(event) = msg
+                        .data()
+                        .as_string()
+                        .and_then(|d| serde_json::from_str::<FileEvent>(&d).ok())
+                    {
+                        set_file_event.set(Some(event));
                     }
                 }
             });
 
             // Handle user count updates (keeps EventSource alive)
+            let mut subscribed = false;
             while let Some(Ok((_event_type, msg))) = users_stream.next().await {
+                // The server sends the count once it is subscribed to the
+                // chat. History loaded before that (during SSR) can have
+                // missed a message, so load it once more.
+                if !subscribed {
+                    subscribed = true;
+                    set_chat_version.update(|v| *v += 1);
+                }
                 if let Some(data_str) = msg.data().as_string() {
                     if let Ok(count) = data_str.parse::<u32>() {
                         set_user_count.set(count);
@@ -323,7 +776,7 @@ fn HomePage() -> impl IntoView {
     #[cfg(feature = "ssr")]
     {
         // These writers are only driven from the SSE stream on the client.
-        let _ = (set_user_count, set_chat_version);
+        let _ = (set_user_count, set_chat_version, set_file_event, set_channels_version);
     }
 
     view! {
@@ -389,19 +842,30 @@ fn HomePage() -> impl IntoView {
 
                     <FileUploadComponent path=path set_file_list_version=set_file_list_version file_list_version=file_list_version/>
 
+                    <FileSearchComponent/>
+
                     <div class="card">
                         <div class="card-header">
                             <h2>"Download Files"</h2>
                         </div>
                         <div class="card-body">
-                            <FileListComponent path=path set_path=set_path file_list_version=file_list_version set_file_list_version=set_file_list_version/>
+                            <FileListComponent path=path set_path=set_path file_list_version=file_list_version set_file_list_version=set_file_list_version file_event=file_event/>
                         </div>
                     </div>
                 </div>
 
                 <div class="panel panel-chat" class:active=move || chat_enabled() && active_tab.get() == ActiveTab::Chat>
                     <Show when=chat_enabled fallback=|| ()>
-                        <ChatComponent chat_version=chat_version active_tab=active_tab/>
+                        <ChatComponent
+                            chat_version=chat_version
+                            live_messages=live_messages
+                            deleted_messages=deleted_messages
+                            channel=chat_channel
+                            set_channel=set_chat_channel
+                            unread_channels=unread_channels
+                            channels_version=channels_version
+                            active_tab=active_tab
+                        />
                     </Show>
                 </div>
             </div>
@@ -425,9 +889,7 @@ fn NotFound() -> impl IntoView {
 
 #[server(GetFileList)]
 pub async fn get_file_list(path: String) -> Result<Vec<(String, String, u64)>, ServerFnError> {
-    let base_path = std::env::current_dir()
-        .map_err(|e| sfn_err(format!("Error getting current directory: {:?}", e)))?;
-    let base = base_path.join("files");
+    let base = crate::settings::share_root().to_path_buf();
 
     let safe_path = resolve_safe_path(&base, &path)?;
     logging::log!("Listing directory: {:?}", safe_path);
@@ -439,6 +901,11 @@ pub async fn get_file_list(path: String) -> Result<Vec<(String, String, u64)>, S
         .filter_map(|entry| match entry {
             Ok(entry) => {
                 let name = entry.file_name().into_string().ok()?;
+                // Dot-entries are the server's own staging areas (partial
+                // uploads, multipart temp files) and never shown.
+                if name.starts_with('.') {
+                    return None;
+                }
                 if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
                     Some(("d".to_string(), name, 0))
                 } else {
@@ -473,25 +940,20 @@ pub async fn get_file_list(path: String) -> Result<Vec<(String, String, u64)>, S
     Ok(file_entries)
 }
 
+/// Search the whole share by file name. Returns up to 100 (rel_path, size)
+/// pairs, best matches first.
+#[server]
+pub async fn search_files(query: String) -> Result<Vec<(String, u64)>, ServerFnError> {
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    crate::db::search_file_index(&conn, &query, 100)
+        .map_err(|e| sfn_err(format!("search failed: {}", e)))
+}
+
 #[server]
 pub async fn get_disk_space() -> Result<(u64, u64), ServerFnError> {
-    let output = std::process::Command::new("df")
-        .args(["--output=size,avail", "-B1", "./files"])
-        .output()
+    let (total, avail) = crate::uploads::disk_space(crate::settings::share_root())
         .map_err(|e| sfn_err(format!("df failed: {}", e)))?;
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let line = stdout
-        .lines()
-        .nth(1)
-        .ok_or_else(|| sfn_err("Failed to parse df".to_string()))?;
-    let parts: Vec<&str> = line.split_whitespace().collect();
-    if parts.len() < 2 {
-        return Err(sfn_err(
-            "Invalid df output".to_string(),
-        ));
-    }
-    let total: u64 = parts[0].parse().unwrap_or(0);
-    let avail: u64 = parts[1].parse().unwrap_or(0);
     Ok((total.saturating_sub(avail), total))
 }
 
@@ -503,14 +965,13 @@ pub async fn create_directory(path: String, name: String) -> Result<(), ServerFn
             "Invalid directory name".to_string(),
         ));
     }
-    let base = std::env::current_dir()
-        .map_err(|e| sfn_err(e.to_string()))?
-        .join("files");
+    let base = crate::settings::share_root().to_path_buf();
     let combined = format!("{}{}", path, name);
     resolve_safe_path(&base, &combined)?;
     let target = base.join(&path).join(&name);
     std::fs::create_dir_all(&target)
         .map_err(|e| sfn_err(format!("Failed: {}", e)))?;
+    ssr_imports::broadcast_file_event(FileEvent::in_dir(&path, FileChange::Created));
     Ok(())
 }
 
@@ -529,7 +990,7 @@ pub async fn get_stats() -> Result<StatsData, ServerFnError> {
         .iter()
         .map(|(k, v)| (k.clone(), *v))
         .collect();
-    top.sort_by(|a, b| b.1.cmp(&a.1));
+    top.sort_by_key(|a| std::cmp::Reverse(a.1));
     top.truncate(10);
     Ok(StatsData {
         total_connections: stats.total_connections,
@@ -553,6 +1014,10 @@ pub fn FileUploadComponent(
     let (has_file, set_has_file) = signal(false);
     let (progress, set_progress) = signal(-1.0f64); // -1 = idle, 0..1 = uploading
     let (upload_status, set_upload_status) = signal(String::new());
+    // (uploaded name, path of the identical file already on the share)
+    let (duplicates, set_duplicates) = signal(Vec::<(String, String)>::new());
+    // Uploaded names still waiting on the malware scan.
+    let (scanning, set_scanning) = signal(Vec::<String>::new());
 
     let disk_space = Resource::new(
         move || file_list_version.get(),
@@ -562,61 +1027,67 @@ pub fn FileUploadComponent(
     let on_upload_click = move |_| {
         #[cfg(not(feature = "ssr"))]
         {
-            use wasm_bindgen::prelude::*;
             use wasm_bindgen::JsCast;
 
             let Some(input) = file_input_ref.get() else { return };
             let html_input: &web_sys::HtmlInputElement = input.unchecked_ref();
-            let Some(files) = html_input.files() else { return };
-            if files.length() == 0 { return; }
-
-            let form_data = web_sys::FormData::new().unwrap();
-            form_data.append_with_str("upload_path", &path.get_untracked()).unwrap();
-            for i in 0..files.length() {
-                let file = files.get(i).unwrap();
-                form_data.append_with_blob_and_filename("file", &file, &file.name()).unwrap();
-            }
-
-            let xhr = web_sys::XmlHttpRequest::new().unwrap();
-            xhr.open("POST", "/upload").unwrap();
+            let Some(file_list) = html_input.files() else { return };
+            let files: Vec<web_sys::File> = (0..file_list.length())
+                .filter_map(|i| file_list.get(i))
+                .collect();
+            if files.is_empty() { return; }
 
-            // Progress handler
-            {
-                let closure = Closure::<dyn FnMut(_)>::new(move |event: web_sys::ProgressEvent| {
-                    if event.length_computable() && event.total() > 0.0 {
-                        set_progress.set(event.loaded() / event.total());
-                    }
-                });
-                xhr.upload().unwrap().set_onprogress(Some(closure.as_ref().unchecked_ref()));
-                closure.forget();
-            }
+            let upload_path = path.get_untracked();
+            let total: f64 = files.iter().map(|f| f.size()).sum();
+            let input_ref = file_input_ref;
 
-            // Completion handler
-            {
-                let xhr2 = xhr.clone();
-                let input_ref = file_input_ref;
-                let closure = Closure::<dyn FnMut()>::new(move || {
-                    if xhr2.ready_state() == 4 {
-                        if xhr2.status().unwrap_or(0) == 200 {
-                            set_upload_status.set("done".to_string());
-                            set_file_list_version.update(|v| *v += 1);
-                            if let Some(el) = input_ref.get() {
-                                el.set_value("");
+            set_upload_status.set(String::new());
+            set_duplicates.set(Vec::new());
+            set_scanning.set(Vec::new());
+            set_progress.set(0.0);
+            spawn_local(async move {
+                let mut completed = 0.0;
+                let mut result = Ok(());
+                for file in &files {
+                    let base = completed;
+                    let sent = resumable::upload(file, &upload_path, move |sent| {
+                        if total > 0.0 {
+                            set_progress.set((base + sent as f64) / total);
+                        }
+                    })
+                    .await;
+                    match sent {
+                        Ok(done) => {
+                            if done.pending_scan {
+                                set_scanning.update(|s| s.push(file.name()));
+                            }
+                            if let Some(existing) = done.duplicate_of {
+                                set_duplicates.update(|d| d.push((file.name(), existing)));
                             }
-                            set_has_file.set(false);
-                        } else {
-                            set_upload_status.set("error".to_string());
                         }
-                        set_progress.set(-1.0);
+                        Err(e) => {
+                            result = Err(e);
+                            break;
+                        }
                     }
-                });
-                xhr.set_onreadystatechange(Some(closure.as_ref().unchecked_ref()));
-                closure.forget();
-            }
-
-            set_upload_status.set(String::new());
-            set_progress.set(0.0);
-            xhr.send_with_opt_form_data(Some(&form_data)).unwrap();
+                    completed += file.size();
+                }
+                match result {
+                    Ok(()) => {
+                        set_upload_status.set("done".to_string());
+                        set_file_list_version.update(|v| *v += 1);
+                        if let Some(el) = input_ref.get() {
+                            el.set_value("");
+                        }
+                        set_has_file.set(false);
+                    }
+                    Err(e) => {
+                        logging::error!("upload failed: {}", e);
+                        set_upload_status.set("error".to_string());
+                    }
+                }
+                set_progress.set(-1.0);
+            });
         }
     };
 
@@ -624,7 +1095,7 @@ pub fn FileUploadComponent(
     {
         // The upload XHR (and its progress/path/refresh wiring) only runs
         // on the client; reference the inputs so the SSR build stays clean.
-        let _ = (path, set_file_list_version, set_progress);
+        let _ = (path, set_file_list_version, set_progress, set_duplicates, set_scanning);
     }
 
     view! {
@@ -672,9 +1143,19 @@ pub fn FileUploadComponent(
                 </Show>
                 <Show when=move || upload_status.get() == "done" fallback=|| ()>
                     <div class="upload-success">"Upload complete!"</div>
+                    {move || duplicates.get().into_iter().map(|(name, existing)| view! {
+                        <div class="upload-duplicate">
+                            {name} " was already shared as " <code>{format!("/{}", existing)}</code>
+                        </div>
+                    }).collect::<Vec<_>>()}
+                    {move || scanning.get().into_iter().map(|name| view! {
+                        <div class="upload-scanning">
+                            {name} " is being scanned and will appear once it is cleared."
+                        </div>
+                    }).collect::<Vec<_>>()}
                 </Show>
                 <Show when=move || upload_status.get() == "error" fallback=|| ()>
-                    <div class="upload-error">"Upload failed. Please try again."</div>
+                    <div class="upload-error">"Upload interrupted. Select the same file again to resume where it stopped."</div>
                 </Show>
             </div>
         </div>
@@ -687,13 +1168,25 @@ pub fn FileListComponent(
     set_path: WriteSignal<String>,
     file_list_version: ReadSignal<u32>,
     set_file_list_version: WriteSignal<u32>,
+    /// Latest change pushed by the server; only changes to the directory
+    /// being viewed trigger a refetch.
+    file_event: ReadSignal<Option<FileEvent>>,
 ) -> impl IntoView {
     let folder_input_ref: NodeRef<Input> = NodeRef::new();
     let (folder_name, set_folder_name) = signal(String::new());
+    let (remote_version, set_remote_version) = signal(0u32);
+
+    Effect::new(move |_| {
+        if let Some(event) = file_event.get()
+            && event.affects(&path.get_untracked())
+        {
+            set_remote_version.update(|v| *v += 1);
+        }
+    });
 
     let directory_listing = Resource::new(
-        move || (path.get(), file_list_version.get()),
-        |(p, _)| get_file_list(p),
+        move || (path.get(), file_list_version.get(), remote_version.get()),
+        |(p, _, _)| get_file_list(p),
     );
 
     view! {
@@ -752,22 +1245,22 @@ pub fn FileListComponent(
                             children=move |n| {
                                 let (file_type, file_name, file_size) = n;
                                 let link_target = if file_type == "f" {
-                                    let p = path.get_untracked();
-                                    let mut encoded = String::from("/files/");
-                                    for seg in p.split('/') {
-                                        if !seg.is_empty() {
-                                            encoded.push_str(&encode_uri_component(seg));
-                                            encoded.push('/');
-                                        }
-                                    }
-                                    encoded.push_str(&encode_uri_component(&file_name));
-                                    encoded
+                                    share_url("/files/", &path.get_untracked(), &file_name)
                                 } else { "#".to_string() };
+                                let zip_link = (file_type == "d" && file_name != "..")
+                                    .then(|| format!("{}/", share_url("/zip/", &path.get_untracked(), &file_name)));
 
-                                let preview_link = link_target.clone();
+                                // Vector images are already small; everything else is
+                                // previewed through the server-side thumbnail cache.
+                                let preview_link = if file_name.to_lowercase().ends_with(".svg") {
+                                    link_target.clone()
+                                } else {
+                                    share_url("/thumb/", &path.get_untracked(), &file_name)
+                                };
                                 let is_img = is_image_file(&file_name) && file_type == "f";
 
                                 view! {
+                                    <div class="file-row">
                                     <a
                                         href=link_target
                                         rel="external"
@@ -797,7 +1290,7 @@ pub fn FileListComponent(
                                             {if file_type == "d" { format!("{}/", file_name) } else { file_name.clone() }}
                                         </span>
                                         {if is_img {
-                                            Some(view! { <img src=preview_link class="file-preview"/> })
+                                            Some(view! { <img src=preview_link class="file-preview" loading="lazy"/> })
                                         } else {
                                             None
                                         }}
@@ -805,6 +1298,10 @@ pub fn FileListComponent(
                                             {if file_type == "f" { file_size.fmt_size(Conventional).to_string() } else { "".to_string() }}
                                         </span>
                                     </a>
+                                    {zip_link.map(|href| view! {
+                                        <a href=href rel="external" class="file-zip" title="Download all as ZIP">"ZIP"</a>
+                                    })}
+                                    </div>
                                 }.into_any()
                             }
                         />
@@ -815,41 +1312,113 @@ pub fn FileListComponent(
     }.into_any()
 }
 
-#[server]
-pub async fn get_chat_messages() -> Result<Vec<(String, String, u64)>, ServerFnError> {
-    let base_path = std::env::current_dir()
-        .map_err(|e| sfn_err(format!("Error getting current directory: {:?}", e)))?;
-    let chat_file_path = base_path.join("chat.json");
+/// Filename search across every directory of the share.
+#[component]
+fn FileSearchComponent() -> impl IntoView {
+    let (query, set_query) = signal(String::new());
+    let results = Resource::new(
+        move || query.get(),
+        |q| async move {
+            // Single characters match nearly everything; wait for more.
+            if q.trim().chars().count() < 2 {
+                Ok(Vec::new())
+            } else {
+                search_files(q).await
+            }
+        },
+    );
 
-    if !chat_file_path.exists() {
-        return Ok(Vec::new());
+    view! {
+        <div class="card">
+            <div class="card-header">
+                <h2>"Search"</h2>
+            </div>
+            <div class="card-body">
+                <input type="search" class="search-input" placeholder="Search all files by name..."
+                    on:input=move |ev| set_query.set(event_target_value(&ev))
+                />
+                <Show when={move || query.get().trim().chars().count() >= 2} fallback=|| ()>
+                    <Suspense fallback=|| view! { <p class="loading">"Searching..."</p> }>
+                        {move || results.get().map(|res| match res {
+                            Ok(hits) if hits.is_empty() => {
+                                view! { <p class="text-muted">"No matching files."</p> }.into_any()
+                            }
+                            Ok(hits) => view! {
+                                <div class="file-list search-results">
+                                    {hits.into_iter().map(|(rel, size)| {
+                                        let (dir, name) = rel.rsplit_once('/').unwrap_or(("", &rel));
+                                        let href = share_url("/files/", dir, name);
+                                        let dir_label = format!("/{}", if dir.is_empty() { String::new() } else { format!("{}/", dir) });
+                                        view! {
+                                            <div class="file-row">
+                                                <a href=href rel="external" class="file-item">
+                                                    <img src="/assets/file.png" class="file-icon"/>
+                                                    <span class="file-info">
+                                                        <span class="file-name">{name.to_string()}</span>
+                                                        <span class="search-dir">{dir_label}</span>
+                                                    </span>
+                                                    <span class="file-size">{format_bytes(size)}</span>
+                                                </a>
+                                            </div>
+                                        }
+                                    }).collect::<Vec<_>>()}
+                                </div>
+                            }.into_any(),
+                            Err(e) => view! { <p>"ERROR: " {e.to_string()}</p> }.into_any(),
+                        })}
+                    </Suspense>
+                </Show>
+            </div>
+        </div>
     }
+}
 
-    let chat_file = std::fs::read_to_string(&chat_file_path)
-        .map_err(|e| sfn_err(format!("Error reading chat file: {:?}", e)))?;
-
-    let chat_messages: Vec<(String, String, u64)> = if !chat_file.is_empty() {
-        serde_json::from_str(&chat_file).unwrap_or_default()
-    } else {
-        Vec::new()
-    };
+/// One page of a channel's history, oldest first: the newest messages,
+/// or those just before `before_id` when scrolling back. An empty channel
+/// means the default one.
+#[server]
+pub async fn get_chat_messages(
+    #[server(default)] channel: String,
+    before_id: Option<i64>,
+) -> Result<Vec<ChatMessage>, ServerFnError> {
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    let channel = existing_channel(&conn, &channel)?;
+    let mut page = crate::db::messages_before(&conn, &channel, before_id, CHAT_PAGE_SIZE)
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    crate::chat_render::fill(&mut page);
+    Ok(page)
+}
 
-    // Only return the last 50 chat messages
-    let chat_messages = chat_messages.iter().rev().take(50).cloned().rev().collect();
+#[server]
+pub async fn get_chat_channels() -> Result<Vec<String>, ServerFnError> {
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    crate::db::list_channels(&conn)
+        .map_err(|e| sfn_err(format!("db error: {}", e)))
+}
 
-    Ok(chat_messages)
+/// `channel`, or the default channel if empty; an error if it doesn't
+/// exist.
+#[cfg(feature = "ssr")]
+fn existing_channel(conn: &rusqlite::Connection, channel: &str) -> Result<String, ServerFnError> {
+    let channel = if channel.is_empty() { DEFAULT_CHANNEL } else { channel };
+    if !crate::db::channel_exists(conn, channel).map_err(|e| sfn_err(format!("db error: {}", e)))? {
+        return Err(sfn_err("no such channel"));
+    }
+    Ok(channel.to_string())
 }
 
 #[server]
 pub async fn get_runtime_settings() -> Result<(bool, bool), ServerFnError> {
-    let cfg = crate::config::load();
-    Ok((cfg.chat_enabled, cfg.is_admin_configured()))
+    Ok(crate::config::load().runtime_settings())
 }
 
 #[server(SendChatMessage, "/api")]
 pub async fn send_chat_message(
     chat_name: String,
     chat_message: String,
+    #[server(default)] channel: String,
 ) -> Result<(), ServerFnError> {
     use crate::app::ssr_imports::*;
 
@@ -858,81 +1427,199 @@ pub async fn send_chat_message(
         return Err(sfn_err("chat is disabled"));
     }
 
-    logging::log!("Chat message received: {:?}", chat_message);
+    let text = chat_message.trim();
+    if text.is_empty() {
+        return Err(sfn_err("Message is empty."));
+    }
+    let limits = &cfg.chat_limits;
+    if text.chars().count() > limits.max_length as usize {
+        return Err(sfn_err(format!(
+            "Message is too long (at most {} characters).",
+            limits.max_length
+        )));
+    }
     let chat_name_clean = if chat_name.is_empty() {
         "Anonymous".to_string()
     } else {
         chat_name
     };
 
-    let base_path = std::env::current_dir()
-        .map_err(|e| sfn_err(format!("Error getting current directory: {:?}", e)))?;
-    let chat_file_path = base_path.join("chat.json");
-    let chat_tmp_path = base_path.join("chat.json.tmp");
-
-    // Read existing messages or start fresh
-    let mut chat_messages: Vec<(String, String, u64)> = if chat_file_path.exists() {
-        let chat_file = std::fs::read_to_string(&chat_file_path)
-            .map_err(|e| sfn_err(format!("Error reading chat file: {:?}", e)))?;
-        if !chat_file.is_empty() {
-            serde_json::from_str(&chat_file).unwrap_or_default()
-        } else {
-            Vec::new()
-        }
-    } else {
-        Vec::new()
-    };
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let req: actix_web::HttpRequest = leptos_actix::extract().await?;
+    let peer = req.peer_addr().map(|a| a.ip());
+    let client = peer.map(|ip| ip.to_string()).unwrap_or_default();
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    let channel = existing_channel(&conn, &channel)?;
 
-    // Append the new chat message
-    if !chat_message.is_empty() && chat_message.len() < 1000 {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        chat_messages.push((chat_name_clean, chat_message.clone(), timestamp));
-
-        // Atomic write: write to tmp then rename
-        let data = serde_json::to_string(&chat_messages)
-            .map_err(|e| sfn_err(format!("Error serializing chat: {:?}", e)))?;
-        std::fs::write(&chat_tmp_path, &data)
-            .map_err(|e| sfn_err(format!("Error writing chat tmp: {:?}", e)))?;
-        std::fs::rename(&chat_tmp_path, &chat_file_path)
-            .map_err(|e| sfn_err(format!("Error renaming chat file: {:?}", e)))?;
-
-        // Track stats
-        if let Ok(mut stats) = STATS.write() {
-            stats.total_chat_messages += 1;
-        }
-        save_stats();
+    if let Some(until) = crate::db::muted_until(&conn, &chat_name_clean, &client, timestamp)
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?
+    {
+        let minutes = until.saturating_sub(timestamp).div_ceil(60);
+        return Err(sfn_err(format!(
+            "You are muted for another {} minute{}.",
+            minutes,
+            if minutes == 1 { "" } else { "s" }
+        )));
+    }
+    let refused = || sfn_err("Your message contains a blocked word.");
+    let chat_name_clean = crate::chat_filter::apply(&cfg.chat_filter, &chat_name_clean).ok_or_else(refused)?;
+    let text = crate::chat_filter::apply(&cfg.chat_filter, text).ok_or_else(refused)?;
+
+    if let Some(ip) = peer {
+        crate::chat_limit::check(ip, &text, limits).map_err(|e| sfn_err(e.to_string()))?;
+    }
+    logging::log!("Chat message received: {:?}", text);
+
+    let id = crate::db::insert_message(&conn, &channel, &chat_name_clean, &text, &client, timestamp)
+        .map_err(|e| sfn_err(format!("Error saving chat message: {}", e)))?;
 
-        // Send signal to trigger SSE update
-        _ = CHAT_CHANNEL.send(1);
+    // Track stats
+    if let Ok(mut stats) = STATS.write() {
+        stats.total_chat_messages += 1;
     }
+    save_stats();
+
+    // Push the message itself to every connected client
+    _ = CHAT_CHANNEL.send(ChatEvent::Message(ChatMessage {
+        id,
+        name: chat_name_clean,
+        html: crate::chat_render::render(&text),
+        text,
+        timestamp,
+        channel,
+    }));
     Ok(())
 }
 
 #[component]
 pub fn ChatComponent(
+    /// Bumped when the history was cleared and must be reloaded.
     chat_version: ReadSignal<u32>,
+    /// Messages pushed over SSE, appended by the page as they arrive.
+    live_messages: RwSignal<Vec<ChatMessage>>,
+    /// Messages removed by the admin, hidden wherever they came from.
+    deleted_messages: RwSignal<std::collections::HashSet<i64>>,
+    /// The channel being shown.
+    channel: ReadSignal<String>,
+    set_channel: WriteSignal<String>,
+    unread_channels: RwSignal<std::collections::HashSet<String>>,
+    /// Bumped when channels are created or deleted.
+    channels_version: ReadSignal<u32>,
     active_tab: ReadSignal<ActiveTab>,
 ) -> impl IntoView {
-    let chat_input_ref: NodeRef<Input> = NodeRef::new();
+    let chat_input_ref: NodeRef<leptos::html::Textarea> = NodeRef::new();
     let name_ref: NodeRef<Input> = NodeRef::new();
     let messages_ref: NodeRef<Div> = NodeRef::new();
     let send_chat_message = ServerAction::<SendChatMessage>::new();
     let (saved_name, set_saved_name) = signal(String::new());
 
-    // Resource refetches when action completes or SSE chat update arrives
+    // The newest page of history; later messages arrive over SSE, so this
+    // only reloads after the admin clears the chat.
     let chat_messages_resource = Resource::new(
-        move || (chat_version.get(), send_chat_message.version().get()),
-        |_| get_chat_messages(),
+        move || (chat_version.get(), channel.get()),
+        |(_, channel)| get_chat_messages(channel, None),
     );
+    let channels = Resource::new(move || channels_version.get(), |_| get_chat_channels());
+    // History loaded by scrolling up, always older than the newest page.
+    let (older, set_older) = signal(Vec::<ChatMessage>::new());
+    let (history_done, set_history_done) = signal(false);
+    let (loading_older, set_loading_older) = signal(false);
+
+    Effect::new(move |_| {
+        chat_version.track();
+        channel.track();
+        set_older.set(Vec::new());
+        set_history_done.set(false);
+    });
+
+    // Fall back to the default channel if the one being shown is deleted.
+    Effect::new(move |_| {
+        if let Some(Ok(list)) = channels.get()
+            && !list.contains(&channel.get_untracked())
+        {
+            set_channel.set(DEFAULT_CHANNEL.to_string());
+        }
+    });
+
+    let switch_channel = move |name: String| {
+        unread_channels.update(|u| { u.remove(&name); });
+        set_channel.set(name);
+    };
+
+    let all_messages = move || {
+        let latest = chat_messages_resource
+            .get()
+            .and_then(|r| r.ok())
+            .unwrap_or_default();
+        let first_id = latest.first().map(|m| m.id).unwrap_or(i64::MAX);
+        let mut all: Vec<ChatMessage> = older
+            .get()
+            .into_iter()
+            .filter(|m| m.id < first_id)
+            .collect();
+        all.extend(latest);
+        let last_id = all.last().map(|m| m.id).unwrap_or(i64::MIN);
+        let current = channel.get();
+        live_messages.with(|live| {
+            all.extend(live.iter().filter(|m| m.id > last_id && m.channel == current).cloned())
+        });
+        deleted_messages.with(|d| all.retain(|m| !d.contains(&m.id)));
+        all
+    };
+
+    let load_older = move || {
+        if loading_older.get_untracked() || history_done.get_untracked() {
+            return;
+        }
+        let Some(oldest) = all_messages().first().map(|m| m.id) else { return };
+        set_loading_older.set(true);
+        spawn_local(async move {
+            #[cfg(not(feature = "ssr"))]
+            let height_before = messages_ref.get_untracked().map(|d| d.scroll_height());
+            match get_chat_messages(channel.get_untracked(), Some(oldest)).await {
+                Ok(page) => {
+                    if page.len() < CHAT_PAGE_SIZE as usize {
+                        set_history_done.set(true);
+                    }
+                    set_older.update(|o| {
+                        let mut merged = page;
+                        merged.append(o);
+                        *o = merged;
+                    });
+                    // Keep the message the user was looking at in place
+                    // once the older ones have been rendered above it.
+                    #[cfg(not(feature = "ssr"))]
+                    request_animation_frame(move || {
+                        if let (Some(div), Some(before)) = (messages_ref.get_untracked(), height_before) {
+                            div.set_scroll_top(div.scroll_top() + div.scroll_height() - before);
+                        }
+                    });
+                }
+                Err(e) => logging::error!("loading chat history failed: {}", e),
+            }
+            set_loading_older.set(false);
+        });
+    };
+
+    // Why the last message was refused (rate limit, length, ...).
+    let send_error = move || match send_chat_message.value().get() {
+        Some(Err(ServerFnError::ServerError(msg))) => msg,
+        Some(Err(e)) => e.to_string(),
+        _ => String::new(),
+    };
 
-    // After action completes, clear message input and restore name
+    // After action completes, clear message input and restore name. A
+    // refused message stays in the input so it can be sent again.
     Effect::new(move |prev: Option<usize>| {
         let v = send_chat_message.version().get();
         if prev.is_some() && v > 0 {
-            if let Some(input) = chat_input_ref.get() {
+            if let Some(input) = chat_input_ref.get()
+                && matches!(send_chat_message.value().get_untracked(), Some(Ok(_)))
+            {
                 input.set_value("");
             }
             // Restore name in case ActionForm reset the form
@@ -946,6 +1633,8 @@ pub fn ChatComponent(
     // Auto-scroll chat to bottom when messages update or tab switches to chat
     Effect::new(move |_: Option<()>| {
         let _tab = active_tab.get(); // Track tab changes so switching to Chat triggers scroll
+        live_messages.track();
+        channel.track();
         if let Some(Ok(_)) = chat_messages_resource.get() {
             #[cfg(not(feature = "ssr"))]
             {
@@ -981,21 +1670,52 @@ pub fn ChatComponent(
             <div class="card-header">
                 <h2>"Chat"</h2>
             </div>
-            <div class="chat-messages" node_ref=messages_ref>
+            <Suspense fallback=|| ()>
+                {move || channels.get().and_then(|r| r.ok()).filter(|list| list.len() > 1).map(|list| view! {
+                    <div class="chat-channels">
+                        {list.into_iter().map(|name| {
+                            let label = format!("#{}", name);
+                            let is_current = {
+                                let name = name.clone();
+                                move || channel.get() == name
+                            };
+                            let is_unread = {
+                                let name = name.clone();
+                                move || unread_channels.with(|u| u.contains(&name))
+                            };
+                            view! {
+                                <button type="button" class="chat-channel" class:active=is_current
+                                    on:click=move |_| switch_channel(name.clone())
+                                >
+                                    {label}
+                                    <Show when=is_unread.clone() fallback=|| ()>
+                                        <span class="unread-dot"></span>
+                                    </Show>
+                                </button>
+                            }
+                        }).collect::<Vec<_>>()}
+                    </div>
+                })}
+            </Suspense>
+            <div class="chat-messages" node_ref=messages_ref
+                on:scroll=move |_| {
+                    if messages_ref.get_untracked().is_some_and(|d| d.scroll_top() < 40) {
+                        load_older();
+                    }
+                }
+            >
+                <Show when=move || loading_older.get() fallback=|| ()>
+                    <p class="loading">"Loading older messages..."</p>
+                </Show>
                 <Suspense fallback=|| view! { <p class="loading">"Loading..."</p> }>
                     <Show
-                        when=move || chat_messages_resource.get()
-                            .map(|res| res.is_ok())
-                            .unwrap_or(false)
+                        when=move || !all_messages().is_empty()
                         fallback=|| view! { <p class="chat-empty">"No messages yet. Start the conversation!"</p> }
                     >
                         <For
-                            each=move || chat_messages_resource
-                                .get()
-                                .and_then(|res| res.ok())
-                                .unwrap_or_default()
-                            key=|msg| msg.2
-                            children=move |(user, message, timestamp)| {
+                            each=all_messages
+                            key=|msg| msg.id
+                            children=move |ChatMessage { name: user, html, timestamp, .. }| {
                                 let time_str = {
                                     #[cfg(not(feature = "ssr"))]
                                     {
@@ -1023,7 +1743,7 @@ pub fn ChatComponent(
                                             <span class="chat-author">{user}</span>
                                             <span class="chat-time">{time_str}</span>
                                         </div>
-                                        <span class="chat-text">{message}</span>
+                                        <span class="chat-text" inner_html=html></span>
                                     </div>
                                 }
                             }
@@ -1033,6 +1753,7 @@ pub fn ChatComponent(
             </div>
             <div class="chat-input-form">
                 <ActionForm action=send_chat_message>
+                    <input type="hidden" name="channel" prop:value=move || channel.get()/>
                     <div class="chat-input-group">
                         <input type="text" class="name-input" placeholder="Name" name="chat_name"
                             node_ref=name_ref
@@ -1042,15 +1763,29 @@ pub fn ChatComponent(
                                 }
                             }
                         />
-                        <input
-                            type="text"
+                        <textarea
+                            class="chat-message-input"
+                            rows="1"
                             placeholder="Type a message..."
                             name="chat_message"
                             node_ref=chat_input_ref
-                        />
-                        <button class="btn-send" type="submit">"Send"</button>
+                            on:keydown=move |ev| {
+                                // Enter sends, Shift+Enter starts a new line.
+                                if ev.key() == "Enter" && !ev.shift_key() {
+                                    ev.prevent_default();
+                                    #[cfg(not(feature = "ssr"))]
+                                    if let Some(form) = chat_input_ref.get_untracked().and_then(|t| t.form()) {
+                                        let _ = form.request_submit();
+                                    }
+                                }
+                            }
+                        ></textarea>
+                        <button class="btn-send" type="submit">"Send"</button>
                     </div>
                 </ActionForm>
+                <Show when=move || !send_error().is_empty() fallback=|| ()>
+                    <div class="upload-error">{send_error}</div>
+                </Show>
             </div>
         </div>
     }.into_any()
@@ -1183,55 +1918,331 @@ fn StatsPage() -> impl IntoView {
 
 // ── Admin mode ──────────────────────────────────────────────────────────────
 
+/// Name of the admin session cookie in `req`, if any.
+#[cfg(feature = "ssr")]
+fn session_token(req: &actix_web::HttpRequest) -> Option<String> {
+    req.cookie(crate::admin_session::COOKIE_NAME)
+        .map(|c| c.value().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+/// Whether `req` was sent by a page of this site. Browsers attach Origin to
+/// every POST (and Referer where they don't), so anything else is another
+/// site trying to act with the admin's cookie.
+#[cfg(feature = "ssr")]
+fn same_origin(req: &actix_web::HttpRequest) -> bool {
+    use actix_web::http::header::{ORIGIN, REFERER};
+    let source = req
+        .headers()
+        .get(ORIGIN)
+        .or_else(|| req.headers().get(REFERER))
+        .and_then(|v| v.to_str().ok());
+    let Some((_, rest)) = source.and_then(|s| s.split_once("://")) else {
+        return false;
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    authority.eq_ignore_ascii_case(req.connection_info().host())
+}
+
+/// Admin calls need the session cookie set by `admin_login`, must come
+/// from this site, and the signed-in role must allow `capability`. The
+/// cookie is also SameSite=Strict, so the origin check is a second line
+/// against cross-site requests.
 #[cfg(feature = "ssr")]
-fn require_admin(token: &str) -> Result<(), ServerFnError> {
-    if !crate::admin_session::validate(token) {
+async fn require_admin(capability: Capability) -> Result<AdminIdentity, ServerFnError> {
+    let req: actix_web::HttpRequest = leptos_actix::extract().await?;
+    if !same_origin(&req) {
+        return Err(sfn_err("cross-site request refused"));
+    }
+    let Some((token, identity)) = session_token(&req)
+        .and_then(|t| signed_in(&t).map(|identity| (t, identity)))
+    else {
         return Err(sfn_err("unauthorized"));
+    };
+    if !identity.role.can(capability) {
+        return Err(sfn_err("forbidden"));
     }
+    // The session slides on use; keep the cookie alive as long.
+    set_cookie(crate::admin_session::session_cookie(&token))?;
+    Ok(identity)
+}
+
+/// Who holds the session `token`. Sessions without an account belong to
+/// the config.json admin, who is an owner.
+#[cfg(feature = "ssr")]
+fn signed_in(token: &str) -> Option<AdminIdentity> {
+    let session = crate::admin_session::validate(token)?;
+    let Some(id) = session.account_id else {
+        return Some(AdminIdentity { name: "admin".to_string(), role: Role::Owner });
+    };
+    let (_, name, role, _, _) = crate::db::open()
+        .and_then(|conn| crate::db::get_account(&conn, id))
+        .ok()
+        .flatten()?;
+    Some(AdminIdentity { name, role: Role::parse(&role)? })
+}
+
+/// Attach a Set-Cookie header to the server function's response.
+#[cfg(feature = "ssr")]
+fn set_cookie(cookie: String) -> Result<(), ServerFnError> {
+    use actix_web::http::header::{HeaderValue, SET_COOKIE};
+    let value = HeaderValue::from_str(&cookie).map_err(|e| sfn_err(e.to_string()))?;
+    expect_context::<leptos_actix::ResponseOptions>().append_header(SET_COOKIE, value);
     Ok(())
 }
 
 #[server]
 pub async fn admin_status() -> Result<bool, ServerFnError> {
     let cfg = crate::config::load();
-    Ok(cfg.is_admin_configured())
+    let accounts = crate::db::open()
+        .and_then(|conn| crate::db::has_accounts(&conn))
+        .unwrap_or(false);
+    Ok(cfg.is_admin_configured() || accounts)
 }
 
+/// Sign in. An empty `name` is the config.json admin password; otherwise
+/// `name` is one of the accounts created in the admin panel.
 #[server]
-pub async fn admin_login(password: String) -> Result<String, ServerFnError> {
-    let cfg = crate::config::load();
-    if !cfg.is_admin_configured() {
+pub async fn admin_login(
+    password: String,
+    #[server(default)] name: String,
+) -> Result<AdminIdentity, ServerFnError> {
+    let mut cfg = crate::config::load();
+    let name = name.trim().to_string();
+    if name.is_empty() && !cfg.is_admin_configured() {
         return Err(sfn_err("admin not configured"));
     }
-    if !cfg.verify_password(&password) {
-        return Err(sfn_err("invalid password"));
+    let req: actix_web::HttpRequest = leptos_actix::extract().await?;
+    if !same_origin(&req) {
+        return Err(sfn_err("cross-site request refused"));
+    }
+    let client = req
+        .peer_addr()
+        .map(|a| a.ip())
+        .unwrap_or(std::net::IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED));
+    crate::login_guard::check(client).map_err(|blocked| sfn_err(blocked.to_string()))?;
+    // Hashing is deliberately slow; keep it off the async workers.
+    let account = actix_web::web::block(move || -> Option<Option<i64>> {
+        if !name.is_empty() {
+            let found = crate::db::open()
+                .and_then(|conn| crate::db::find_account(&conn, &name))
+                .unwrap_or_else(|e| {
+                    eprintln!("failed to look up account: {}", e);
+                    None
+                });
+            return match found {
+                Some((id, _, _, hash, _)) => {
+                    crate::password::verify(&hash, "", &password).then_some(Some(id))
+                }
+                None => {
+                    // Take as long as a wrong password would, so names
+                    // can't be probed by timing.
+                    let _ = crate::password::hash(&password);
+                    None
+                }
+            };
+        }
+        let ok = cfg.verify_password(&password);
+        // Replace an outdated hash now that the password is known.
+        if ok && cfg.password_needs_upgrade() {
+            match cfg.set_password(&password) {
+                Ok(()) => {
+                    if let Err(e) = crate::config::save(&cfg) {
+                        eprintln!("failed to save upgraded password hash: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("failed to upgrade password hash: {}", e),
+            }
+        }
+        ok.then_some(None)
+    })
+    .await
+    .map_err(|e| {
+        crate::login_guard::abandoned(client);
+        sfn_err(format!("login failed: {}", e))
+    })?;
+    let Some(account_id) = account else {
+        let failure = crate::login_guard::failed(client);
+        if failure.locked_out {
+            let now = crate::uploads::now_secs();
+            println!(
+                "admin login: {} locked out for {} s after {} failed attempts",
+                client, failure.wait_secs, failure.failures
+            );
+            let logged = crate::db::open().and_then(|conn| {
+                crate::db::log_lockout(&conn, now, &client.to_string(), failure.failures, now + failure.wait_secs)
+            });
+            if let Err(e) = logged {
+                eprintln!("failed to log lockout: {}", e);
+            }
+        }
+        if failure.wait_secs > 0 {
+            return Err(sfn_err(format!(
+                "Invalid password. {}",
+                crate::login_guard::Blocked(failure.wait_secs)
+            )));
+        }
+        return Err(sfn_err("Invalid password."));
+    };
+    crate::login_guard::succeeded(client);
+    let token = crate::admin_session::create_token(account_id).ok_or_else(|| sfn_err("could not start a session"))?;
+    let identity = signed_in(&token).ok_or_else(|| sfn_err("could not start a session"))?;
+    set_cookie(crate::admin_session::session_cookie(&token))?;
+    Ok(identity)
+}
+
+#[server]
+pub async fn admin_logout() -> Result<(), ServerFnError> {
+    let req: actix_web::HttpRequest = leptos_actix::extract().await?;
+    if !same_origin(&req) {
+        return Err(sfn_err("cross-site request refused"));
+    }
+    if let Some(token) = session_token(&req) {
+        crate::admin_session::revoke(&token);
+    }
+    set_cookie(crate::admin_session::expired_cookie())
+}
+
+/// Number of signed-in admin sessions, this one included.
+#[server]
+pub async fn admin_session_count() -> Result<u64, ServerFnError> {
+    require_admin(Capability::ManageAccounts).await?;
+    crate::admin_session::active_count().map_err(|e| sfn_err(format!("db error: {}", e)))
+}
+
+/// End every admin session, on every device, including this one.
+#[server]
+pub async fn admin_logout_all() -> Result<(), ServerFnError> {
+    require_admin(Capability::ManageAccounts).await?;
+    let n = crate::admin_session::revoke_all().map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    println!("admin: logged out all {} session(s)", n);
+    set_cookie(crate::admin_session::expired_cookie())
+}
+
+/// Who the browser's admin session belongs to, if it holds a valid one.
+#[server]
+pub async fn admin_check() -> Result<Option<AdminIdentity>, ServerFnError> {
+    let req: actix_web::HttpRequest = leptos_actix::extract().await?;
+    Ok(session_token(&req).and_then(|t| signed_in(&t)))
+}
+
+/// Shortest password accepted for an account.
+#[cfg(feature = "ssr")]
+const MIN_ACCOUNT_PASSWORD: usize = 8;
+
+#[cfg(feature = "ssr")]
+fn check_account_password(password: &str) -> Result<(), ServerFnError> {
+    if password.chars().count() < MIN_ACCOUNT_PASSWORD {
+        return Err(sfn_err(format!(
+            "passwords need at least {} characters",
+            MIN_ACCOUNT_PASSWORD
+        )));
+    }
+    Ok(())
+}
+
+#[server]
+pub async fn admin_list_accounts() -> Result<Vec<AccountInfo>, ServerFnError> {
+    require_admin(Capability::ManageAccounts).await?;
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    let rows = crate::db::list_accounts(&conn)
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(id, name, role, _, created_at)| {
+            Some(AccountInfo { id, name, role: Role::parse(&role)?, created_at })
+        })
+        .collect())
+}
+
+/// `role` is one of `Role::as_str`.
+#[server]
+pub async fn admin_create_account(
+    name: String,
+    role: String,
+    password: String,
+) -> Result<(), ServerFnError> {
+    let me = require_admin(Capability::ManageAccounts).await?;
+    let name = name.trim().to_string();
+    let valid = !name.is_empty()
+        && name.len() <= 32
+        && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if !valid {
+        return Err(sfn_err("account names are up to 32 letters, digits, '-', '_' or '.'"));
+    }
+    // Reserved for the config.json admin.
+    if name.eq_ignore_ascii_case("admin") {
+        return Err(sfn_err("that name is reserved"));
+    }
+    let role = Role::parse(&role).ok_or_else(|| sfn_err("unknown role"))?;
+    check_account_password(&password)?;
+    let hash = actix_web::web::block(move || crate::password::hash(&password))
+        .await
+        .map_err(|e| sfn_err(e.to_string()))?
+        .map_err(|e| sfn_err(format!("hash password: {}", e)))?;
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    if !crate::db::create_account(&conn, &name, role.as_str(), &hash, crate::uploads::now_secs())
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?
+    {
+        return Err(sfn_err("that account already exists"));
     }
-    Ok(crate::admin_session::create_token())
+    println!("admin: {} created {} account {}", me.name, role.as_str(), name);
+    Ok(())
 }
 
+/// Remove an account and end its sessions.
 #[server]
-pub async fn admin_logout(token: String) -> Result<(), ServerFnError> {
-    crate::admin_session::revoke(&token);
+pub async fn admin_delete_account(id: i64) -> Result<(), ServerFnError> {
+    let me = require_admin(Capability::ManageAccounts).await?;
+    let mut conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    let Some((_, name, _, _, _)) = crate::db::get_account(&conn, id)
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?
+    else {
+        return Err(sfn_err("no such account"));
+    };
+    if name.eq_ignore_ascii_case(&me.name) {
+        return Err(sfn_err("you can't revoke your own account"));
+    }
+    crate::db::delete_account(&mut conn, id)
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    println!("admin: {} revoked account {}", me.name, name);
     Ok(())
 }
 
+/// Set a new password for an account and end its sessions.
 #[server]
-pub async fn admin_check(token: String) -> Result<bool, ServerFnError> {
-    Ok(crate::admin_session::validate(&token))
+pub async fn admin_reset_account_password(id: i64, password: String) -> Result<(), ServerFnError> {
+    let me = require_admin(Capability::ManageAccounts).await?;
+    check_account_password(&password)?;
+    let hash = actix_web::web::block(move || crate::password::hash(&password))
+        .await
+        .map_err(|e| sfn_err(e.to_string()))?
+        .map_err(|e| sfn_err(format!("hash password: {}", e)))?;
+    let mut conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    if !crate::db::reset_account_password(&mut conn, id, &hash)
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?
+    {
+        return Err(sfn_err("no such account"));
+    }
+    println!("admin: {} reset the password of account {}", me.name, id);
+    Ok(())
 }
 
 #[server]
-pub async fn admin_get_chat_status(token: String) -> Result<bool, ServerFnError> {
-    require_admin(&token)?;
+pub async fn admin_get_chat_status() -> Result<bool, ServerFnError> {
+    require_admin(Capability::ManageSettings).await?;
     Ok(crate::config::load().chat_enabled)
 }
 
 #[server]
 pub async fn admin_set_chat_enabled(
-    token: String,
     enabled: bool,
 ) -> Result<(), ServerFnError> {
-    require_admin(&token)?;
+    require_admin(Capability::ManageSettings).await?;
     let mut cfg = crate::config::load();
     cfg.chat_enabled = enabled;
     crate::config::save(&cfg)
@@ -1240,34 +2251,177 @@ pub async fn admin_set_chat_enabled(
 }
 
 #[server]
-pub async fn admin_clear_chat(token: String) -> Result<(), ServerFnError> {
+pub async fn admin_get_chat_limits() -> Result<ChatLimits, ServerFnError> {
+    require_admin(Capability::ManageSettings).await?;
+    Ok(crate::config::load().chat_limits)
+}
+
+#[server]
+pub async fn admin_set_chat_limits(limits: ChatLimits) -> Result<(), ServerFnError> {
+    require_admin(Capability::ManageSettings).await?;
+    if limits.burst == 0 || limits.per_minute == 0 || limits.max_length == 0 {
+        return Err(sfn_err("limits must be at least 1"));
+    }
+    let mut cfg = crate::config::load();
+    cfg.chat_limits = limits;
+    crate::config::save(&cfg)
+        .map_err(|e| sfn_err(format!("save config: {}", e)))?;
+    Ok(())
+}
+
+#[server]
+pub async fn admin_clear_chat() -> Result<(), ServerFnError> {
+    use crate::app::ssr_imports::*;
+
+    require_admin(Capability::ModerateChat).await?;
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    crate::db::clear_messages(&conn)
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+
+    // Notify connected clients to drop what they are showing.
+    _ = CHAT_CHANNEL.send(ChatEvent::Cleared);
+    Ok(())
+}
+
+#[server]
+pub async fn admin_create_channel(name: String) -> Result<(), ServerFnError> {
+    use crate::app::ssr_imports::*;
+
+    require_admin(Capability::ModerateChat).await?;
+    let name = name.trim().to_lowercase();
+    let valid = !name.is_empty()
+        && name.len() <= 32
+        && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(sfn_err("channel names are up to 32 letters, digits, '-' or '_'"));
+    }
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    if !crate::db::create_channel(&conn, &name, crate::uploads::now_secs())
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?
+    {
+        return Err(sfn_err("that channel already exists"));
+    }
+    _ = CHAT_CHANNEL.send(ChatEvent::Channels);
+    Ok(())
+}
+
+/// Delete a channel and everything said in it.
+#[server]
+pub async fn admin_delete_channel(name: String) -> Result<(), ServerFnError> {
+    use crate::app::ssr_imports::*;
+
+    require_admin(Capability::ModerateChat).await?;
+    if name == DEFAULT_CHANNEL {
+        return Err(sfn_err("the default channel cannot be deleted"));
+    }
+    let mut conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    if !crate::db::delete_channel(&mut conn, &name).map_err(|e| sfn_err(format!("db error: {}", e)))? {
+        return Err(sfn_err("no such channel"));
+    }
+    _ = CHAT_CHANNEL.send(ChatEvent::Channels);
+    Ok(())
+}
+
+/// The newest chat messages with the address each was sent from, newest
+/// first, for moderation.
+#[server]
+pub async fn admin_list_chat() -> Result<Vec<(ChatMessage, String)>, ServerFnError> {
+    require_admin(Capability::ModerateChat).await?;
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    crate::db::recent_messages_with_client(&conn, CHAT_PAGE_SIZE)
+        .map_err(|e| sfn_err(format!("db error: {}", e)))
+}
+
+#[server]
+pub async fn admin_delete_chat_message(id: i64) -> Result<(), ServerFnError> {
     use crate::app::ssr_imports::*;
 
-    require_admin(&token)?;
-    let base_path = std::env::current_dir()
-        .map_err(|e| sfn_err(format!("Error getting current directory: {:?}", e)))?;
-    let chat_file_path = base_path.join("chat.json");
-    let chat_tmp_path = base_path.join("chat.json.tmp");
-
-    let empty: Vec<(String, String, u64)> = Vec::new();
-    let data = serde_json::to_string(&empty)
-        .map_err(|e| sfn_err(format!("Error serializing chat: {:?}", e)))?;
-    std::fs::write(&chat_tmp_path, &data)
-        .map_err(|e| sfn_err(format!("Error writing chat tmp: {:?}", e)))?;
-    std::fs::rename(&chat_tmp_path, &chat_file_path)
-        .map_err(|e| sfn_err(format!("Error renaming chat file: {:?}", e)))?;
-
-    // Notify connected clients to reload — they'll see an empty chat.
-    _ = CHAT_CHANNEL.send(1);
+    require_admin(Capability::ModerateChat).await?;
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    if !crate::db::delete_message(&conn, id).map_err(|e| sfn_err(format!("db error: {}", e)))? {
+        return Err(sfn_err("no such message"));
+    }
+    _ = CHAT_CHANNEL.send(ChatEvent::Deleted { id });
+    Ok(())
+}
+
+/// Logged login lockouts, newest first: (at, client, failures, until).
+#[server]
+pub async fn admin_list_lockouts() -> Result<Vec<(u64, String, u32, u64)>, ServerFnError> {
+    require_admin(Capability::ManageAccounts).await?;
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    crate::db::recent_lockouts(&conn)
+        .map_err(|e| sfn_err(format!("db error: {}", e)))
+}
+
+/// Active mutes: (id, kind, value, until_secs). `kind` is "name" or
+/// "client".
+#[server]
+pub async fn admin_list_mutes() -> Result<Vec<(i64, String, String, u64)>, ServerFnError> {
+    require_admin(Capability::ModerateChat).await?;
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    crate::db::active_mutes(&conn, crate::uploads::now_secs())
+        .map_err(|e| sfn_err(format!("db error: {}", e)))
+}
+
+#[server]
+pub async fn admin_mute(
+    kind: String,
+    value: String,
+    minutes: u32,
+) -> Result<(), ServerFnError> {
+    require_admin(Capability::ModerateChat).await?;
+    if kind != crate::db::MUTE_NAME && kind != crate::db::MUTE_CLIENT {
+        return Err(sfn_err("unknown mute kind"));
+    }
+    if value.is_empty() || minutes == 0 {
+        return Err(sfn_err("nothing to mute"));
+    }
+    let until = crate::uploads::now_secs() + minutes as u64 * 60;
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    crate::db::add_mute(&conn, &kind, &value, until)
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    Ok(())
+}
+
+#[server]
+pub async fn admin_unmute(id: i64) -> Result<(), ServerFnError> {
+    require_admin(Capability::ModerateChat).await?;
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    crate::db::remove_mute(&conn, id)
+        .map_err(|e| sfn_err(format!("db error: {}", e)))
+}
+
+#[server]
+pub async fn admin_get_chat_filter() -> Result<ChatFilter, ServerFnError> {
+    require_admin(Capability::ModerateChat).await?;
+    Ok(crate::config::load().chat_filter)
+}
+
+#[server]
+pub async fn admin_set_chat_filter(filter: ChatFilter) -> Result<(), ServerFnError> {
+    require_admin(Capability::ModerateChat).await?;
+    let mut cfg = crate::config::load();
+    cfg.chat_filter = filter;
+    crate::config::save(&cfg)
+        .map_err(|e| sfn_err(format!("save config: {}", e)))?;
     Ok(())
 }
 
 /// (id, rel_path, uploaded_at_secs, expires_at_secs_opt)
 #[server]
 pub async fn admin_list_expiring(
-    token: String,
 ) -> Result<(bool, u32, Vec<(i64, String, u64, Option<u64>)>), ServerFnError> {
-    require_admin(&token)?;
+    require_admin(Capability::ManageFiles).await?;
     let cfg = crate::config::load();
     let conn = crate::db::open()
         .map_err(|e| sfn_err(format!("db error: {}", e)))?;
@@ -1290,25 +2444,22 @@ pub async fn admin_list_expiring(
 }
 
 #[server]
-pub async fn admin_approve(token: String, id: i64) -> Result<(), ServerFnError> {
-    require_admin(&token)?;
+pub async fn admin_approve(id: i64) -> Result<(), ServerFnError> {
+    require_admin(Capability::ManageFiles).await?;
     let conn = crate::db::open()
         .map_err(|e| sfn_err(format!("db error: {}", e)))?;
-    crate::db::delete_by_id(&conn, id)
+    crate::db::pin_upload(&conn, id)
         .map_err(|e| sfn_err(format!("db error: {}", e)))?;
     Ok(())
 }
 
 #[server]
 pub async fn admin_move_file(
-    token: String,
     src_rel: String,
     dst_dir_rel: String,
 ) -> Result<(), ServerFnError> {
-    require_admin(&token)?;
-    let base_path = std::env::current_dir()
-        .map_err(|e| sfn_err(format!("Error getting current directory: {:?}", e)))?;
-    let base = base_path.join("files");
+    require_admin(Capability::ManageFiles).await?;
+    let base = crate::settings::share_root().to_path_buf();
 
     let src_canon = resolve_safe_path(&base, &src_rel)?;
     if src_canon.is_dir() {
@@ -1346,16 +2497,19 @@ pub async fn admin_move_file(
         .to_string();
     if let Ok(conn) = crate::db::open() {
         let _ = crate::db::update_path(&conn, &old_rel, &new_rel);
+        let size = std::fs::metadata(&dst_canon).map(|m| m.len()).unwrap_or(0);
+        let _ = crate::db::unindex_file(&conn, &old_rel);
+        let _ = crate::db::index_file(&conn, &new_rel, size);
     }
+    ssr_imports::broadcast_file_event(FileEvent::for_path(&old_rel, FileChange::Deleted));
+    ssr_imports::broadcast_file_event(FileEvent::for_path(&new_rel, FileChange::Created));
     Ok(())
 }
 
 #[server]
-pub async fn admin_delete_file(token: String, rel_path: String) -> Result<(), ServerFnError> {
-    require_admin(&token)?;
-    let base_path = std::env::current_dir()
-        .map_err(|e| sfn_err(format!("Error getting current directory: {:?}", e)))?;
-    let base = base_path.join("files");
+pub async fn admin_delete_file(rel_path: String) -> Result<(), ServerFnError> {
+    require_admin(Capability::ManageFiles).await?;
+    let base = crate::settings::share_root().to_path_buf();
     let target = resolve_safe_path(&base, &rel_path)?;
     if target.is_dir() {
         return Err(sfn_err("target is a directory"));
@@ -1372,16 +2526,16 @@ pub async fn admin_delete_file(token: String, rel_path: String) -> Result<(), Se
         .to_string();
     if let Ok(conn) = crate::db::open() {
         let _ = crate::db::delete_by_path(&conn, &rel);
+        let _ = crate::db::unindex_file(&conn, &rel);
     }
+    ssr_imports::broadcast_file_event(FileEvent::for_path(&rel, FileChange::Deleted));
     Ok(())
 }
 
 #[server]
-pub async fn admin_delete_directory(token: String, rel_path: String) -> Result<(), ServerFnError> {
-    require_admin(&token)?;
-    let base_path = std::env::current_dir()
-        .map_err(|e| sfn_err(format!("Error getting current directory: {:?}", e)))?;
-    let base = base_path.join("files");
+pub async fn admin_delete_directory(rel_path: String) -> Result<(), ServerFnError> {
+    require_admin(Capability::ManageFiles).await?;
+    let base = crate::settings::share_root().to_path_buf();
     let target = resolve_safe_path(&base, &rel_path)?;
     if !target.is_dir() {
         return Err(sfn_err("target is not a directory"));
@@ -1401,45 +2555,111 @@ pub async fn admin_delete_directory(token: String, rel_path: String) -> Result<(
     }
     std::fs::remove_dir(&target)
         .map_err(|e| sfn_err(format!("delete failed: {}", e)))?;
+    let rel = target
+        .strip_prefix(&canonical_base)
+        .map_err(|_| sfn_err("path escape"))?
+        .to_string_lossy()
+        .to_string();
+    ssr_imports::broadcast_file_event(FileEvent::for_path(&rel, FileChange::Deleted));
     Ok(())
 }
 
+/// Uploads held back by the malware scanner:
+/// (id, destination, status, detail, created_at_secs). Pending scans are
+/// included so the admin can see the queue draining.
+#[server]
+pub async fn admin_list_quarantine(
+) -> Result<Vec<(i64, String, String, String, u64)>, ServerFnError> {
+    require_admin(Capability::ManageFiles).await?;
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    let mut out = Vec::new();
+    for status in [crate::db::SCAN_INFECTED, crate::db::SCAN_ERROR, crate::db::SCAN_PENDING] {
+        let rows = crate::db::list_scans(&conn, status)
+            .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+        out.extend(rows.into_iter().map(|r| {
+            let dest = format!("{}/{}", r.upload_path.trim_matches('/'), r.file_name);
+            (r.id, dest.trim_start_matches('/').to_string(), r.status, r.detail, r.created_at)
+        }));
+    }
+    Ok(out)
+}
+
+#[cfg(feature = "ssr")]
+async fn quarantined(id: i64) -> Result<(rusqlite::Connection, crate::db::ScanRecord), ServerFnError> {
+    require_admin(Capability::ManageFiles).await?;
+    let conn = crate::db::open()
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?;
+    let rec = crate::db::get_scan(&conn, id)
+        .map_err(|e| sfn_err(format!("db error: {}", e)))?
+        .filter(|r| r.status != crate::db::SCAN_CLEAN)
+        .ok_or_else(|| sfn_err("no such quarantined upload"))?;
+    Ok((conn, rec))
+}
+
+/// Publish a quarantined upload despite its verdict (false positives).
+#[server]
+pub async fn admin_quarantine_release(id: i64) -> Result<String, ServerFnError> {
+    let (conn, rec) = quarantined(id).await?;
+    if rec.status == crate::db::SCAN_PENDING {
+        return Err(sfn_err("scan still in progress"));
+    }
+    let done = crate::quarantine::release(&conn, &rec)
+        .map_err(|e| sfn_err(format!("release failed: {}", e)))?;
+    Ok(done.rel_path)
+}
+
+#[server]
+pub async fn admin_quarantine_rescan(id: i64) -> Result<(), ServerFnError> {
+    let (conn, rec) = quarantined(id).await?;
+    if rec.status == crate::db::SCAN_PENDING {
+        return Err(sfn_err("scan still in progress"));
+    }
+    if rec.status == crate::db::SCAN_INFECTED {
+        return Err(sfn_err("only failed scans can be retried"));
+    }
+    crate::quarantine::rescan(&conn, &rec)
+        .map_err(|e| sfn_err(format!("rescan failed: {}", e)))
+}
+
+#[server]
+pub async fn admin_quarantine_delete(id: i64) -> Result<(), ServerFnError> {
+    let (conn, rec) = quarantined(id).await?;
+    if rec.status == crate::db::SCAN_PENDING {
+        return Err(sfn_err("scan still in progress"));
+    }
+    crate::quarantine::discard(&conn, &rec)
+        .map_err(|e| sfn_err(format!("delete failed: {}", e)))
+}
+
 #[component]
 fn AdminPage() -> impl IntoView {
-    let (token, set_token) = signal::<Option<String>>(None);
+    let (identity, set_identity) = signal(None::<AdminIdentity>);
     let (login_error, set_login_error) = signal(String::new());
     let (action_msg, set_action_msg) = signal(String::new());
     let (menu_open, set_menu_open) = signal(false);
     let password_ref: NodeRef<Input> = NodeRef::new();
+    let name_ref: NodeRef<Input> = NodeRef::new();
     let admin_status = Resource::new(|| (), |_| admin_status());
 
-    // Restore token from localStorage on mount and validate it.
+    // Pick up a session cookie from an earlier visit on mount.
     #[cfg(not(feature = "ssr"))]
     {
         Effect::new(move |prev: Option<bool>| {
             if prev.unwrap_or(false) {
                 return true;
             }
+            // Sessions used to be kept in localStorage; drop the leftover.
             if let Some(window) = web_sys::window() {
                 if let Ok(Some(storage)) = window.local_storage() {
-                    if let Ok(Some(t)) = storage.get_item("shareboxx_admin_token") {
-                        if !t.is_empty() {
-                            spawn_local(async move {
-                                match admin_check(t.clone()).await {
-                                    Ok(true) => set_token.set(Some(t)),
-                                    _ => {
-                                        if let Some(window) = web_sys::window() {
-                                            if let Ok(Some(storage)) = window.local_storage() {
-                                                let _ = storage.remove_item("shareboxx_admin_token");
-                                            }
-                                        }
-                                    }
-                                }
-                            });
-                        }
-                    }
+                    let _ = storage.remove_item("shareboxx_admin_token");
                 }
             }
+            spawn_local(async move {
+                if let Ok(Some(who)) = admin_check().await {
+                    set_identity.set(Some(who));
+                }
+            });
             true
         });
     }
@@ -1449,44 +2669,29 @@ fn AdminPage() -> impl IntoView {
             .get()
             .map(|i| i.value())
             .unwrap_or_default();
+        let name = name_ref
+            .get()
+            .map(|i| i.value())
+            .unwrap_or_default();
         if pw.is_empty() {
             set_login_error.set("Please enter the password.".to_string());
             return;
         }
         set_login_error.set(String::new());
         spawn_local(async move {
-            match admin_login(pw).await {
-                Ok(t) => {
-                    #[cfg(not(feature = "ssr"))]
-                    {
-                        if let Some(window) = web_sys::window() {
-                            if let Ok(Some(storage)) = window.local_storage() {
-                                let _ = storage.set_item("shareboxx_admin_token", &t);
-                            }
-                        }
-                    }
-                    set_token.set(Some(t));
-                }
+            match admin_login(pw, name).await {
+                Ok(who) => set_identity.set(Some(who)),
+                Err(ServerFnError::ServerError(msg)) => set_login_error.set(msg),
                 Err(e) => set_login_error.set(format!("Login failed: {}", e)),
             }
         });
     };
 
     let on_logout = move |_| {
-        if let Some(t) = token.get_untracked() {
-            spawn_local(async move {
-                let _ = admin_logout(t).await;
-            });
-        }
-        #[cfg(not(feature = "ssr"))]
-        {
-            if let Some(window) = web_sys::window() {
-                if let Ok(Some(storage)) = window.local_storage() {
-                    let _ = storage.remove_item("shareboxx_admin_token");
-                }
-            }
-        }
-        set_token.set(None);
+        spawn_local(async move {
+            let _ = admin_logout().await;
+        });
+        set_identity.set(None);
     };
 
     view! {
@@ -1506,7 +2711,7 @@ fn AdminPage() -> impl IntoView {
                     <nav class="nav-links" on:click=move |_| set_menu_open.set(false)>
                         <a href="/" rel="external" class="header-link">"Home"</a>
                         <a href="/stats" rel="external" class="header-link">"Stats"</a>
-                        <Show when=move || token.get().is_some() fallback=|| ()>
+                        <Show when=move || identity.get().is_some() fallback=|| ()>
                             <button class="header-link header-button" type="button" on:click=on_logout>"Log out"</button>
                         </Show>
                     </nav>
@@ -1527,13 +2732,22 @@ fn AdminPage() -> impl IntoView {
                                 </div>
                             </div>
                         }.into_any()
-                    } else if token.get().is_none() {
+                    } else if let Some(who) = identity.get() {
+                        view! {
+                            <AdminPanel identity=who set_identity=set_identity action_msg=action_msg set_action_msg=set_action_msg/>
+                        }.into_any()
+                    } else {
                         view! {
                             <div class="card admin-login-card">
                                 <div class="card-header"><h2>"Sign in"</h2></div>
                                 <div class="card-body">
                                     <div class="admin-login-row">
-                                        <input type="password" placeholder="Admin password"
+                                        <input type="text" placeholder="Name (blank for admin)"
+                                            class="admin-password-input"
+                                            autocomplete="username"
+                                            node_ref=name_ref
+                                        />
+                                        <input type="password" placeholder="Password"
                                             class="admin-password-input"
                                             node_ref=password_ref
                                             on:keydown=move |ev| {
@@ -1550,10 +2764,6 @@ fn AdminPage() -> impl IntoView {
                                 </div>
                             </div>
                         }.into_any()
-                    } else {
-                        view! {
-                            <AdminPanel token=token set_token=set_token action_msg=action_msg set_action_msg=set_action_msg/>
-                        }.into_any()
                     }
                 }}
                 </Suspense>
@@ -1564,8 +2774,8 @@ fn AdminPage() -> impl IntoView {
 
 #[component]
 fn AdminPanel(
-    token: ReadSignal<Option<String>>,
-    set_token: WriteSignal<Option<String>>,
+    identity: AdminIdentity,
+    set_identity: WriteSignal<Option<AdminIdentity>>,
     action_msg: ReadSignal<String>,
     set_action_msg: WriteSignal<String>,
 ) -> impl IntoView {
@@ -1576,30 +2786,19 @@ fn AdminPanel(
     let folder_input_ref: NodeRef<Input> = NodeRef::new();
     let (folder_name, set_folder_name) = signal(String::new());
 
-    let expiring = Resource::new(
-        move || (data_version.get(), token.get()),
-        |(_, t)| async move {
-            match t {
-                Some(t) => admin_list_expiring(t).await,
-                None => Err(ServerFnError::ServerError("no token".to_string())),
-            }
-        },
-    );
+    let can = move |capability| identity.role.can(capability);
+    let files = can(Capability::ManageFiles);
+    let settings = can(Capability::ManageSettings);
+    let moderate = can(Capability::ModerateChat);
+    let accounts_admin = can(Capability::ManageAccounts);
 
-    let chat_status = Resource::new(
-        move || (chat_version.get(), token.get()),
-        |(_, t)| async move {
-            match t {
-                Some(t) => admin_get_chat_status(t).await,
-                None => Err(ServerFnError::ServerError("no token".to_string())),
-            }
-        },
-    );
+    let expiring = Resource::new(move || data_version.get(), move |_| if_allowed(files, admin_list_expiring()));
+
+    let chat_status = Resource::new(move || chat_version.get(), move |_| if_allowed(settings, admin_get_chat_status()));
 
     let toggle_chat = move |new_state: bool| {
-        let Some(t) = token.get_untracked() else { return };
         spawn_local(async move {
-            match admin_set_chat_enabled(t, new_state).await {
+            match admin_set_chat_enabled(new_state).await {
                 Ok(_) => {
                     set_action_msg.set(
                         if new_state { "Chat enabled.".to_string() }
@@ -1612,18 +2811,143 @@ fn AdminPanel(
         });
     };
 
-    let clear_chat = move |_| {
-        let Some(t) = token.get_untracked() else { return };
-        #[cfg(not(feature = "ssr"))]
-        {
-            let confirmed = web_sys::window()
-                .and_then(|w| w.confirm_with_message("Clear all chat messages?").ok())
-                .unwrap_or(false);
+    let chat_limits = Resource::new(move || chat_version.get(), move |_| if_allowed(settings, admin_get_chat_limits()));
+    // Edited limits, starting from the loaded ones on the first keystroke.
+    let limits_draft = RwSignal::new(None::<ChatLimits>);
+
+    let save_limits = move |_| {
+        let Some(limits) = limits_draft.get_untracked() else { return };
+        spawn_local(async move {
+            match admin_set_chat_limits(limits).await {
+                Ok(_) => {
+                    set_action_msg.set("Chat limits saved.".to_string());
+                    limits_draft.set(None);
+                    set_chat_version.update(|v| *v += 1);
+                }
+                Err(e) => set_action_msg.set(format!("Error: {}", e)),
+            }
+        });
+    };
+
+    let (moderation_version, set_moderation_version) = signal(0u32);
+    let recent_chat = Resource::new(move || moderation_version.get(), move |_| if_allowed(moderate, admin_list_chat()));
+    let mutes = Resource::new(move || moderation_version.get(), move |_| if_allowed(moderate, admin_list_mutes()));
+    let chat_filter = Resource::new(|| (), move |_| if_allowed(moderate, admin_get_chat_filter()));
+    let admin_channels = Resource::new(move || moderation_version.get(), |_| get_chat_channels());
+    let (new_channel, set_new_channel) = signal(String::new());
+
+    let create_channel = move |_| {
+        let name = new_channel.get_untracked();
+        if name.trim().is_empty() {
+            return;
+        }
+        spawn_local(async move {
+            match admin_create_channel(name).await {
+                Ok(_) => {
+                    set_action_msg.set("Channel created.".to_string());
+                    set_new_channel.set(String::new());
+                    set_moderation_version.update(|v| *v += 1);
+                }
+                Err(e) => set_action_msg.set(format!("Error: {}", e)),
+            }
+        });
+    };
+
+    let delete_channel = move |name: String| {
+        #[cfg(not(feature = "ssr"))]
+        {
+            let confirmed = web_sys::window()
+                .and_then(|w| w.confirm_with_message(&format!("Delete #{} and all of its messages?", name)).ok())
+                .unwrap_or(false);
             if !confirmed { return; }
         }
         spawn_local(async move {
-            match admin_clear_chat(t).await {
-                Ok(_) => set_action_msg.set("Chat cleared.".to_string()),
+            match admin_delete_channel(name).await {
+                Ok(_) => {
+                    set_action_msg.set("Channel deleted.".to_string());
+                    set_moderation_version.update(|v| *v += 1);
+                }
+                Err(e) => set_action_msg.set(format!("Error: {}", e)),
+            }
+        });
+    };
+
+    let (mute_minutes, set_mute_minutes) = signal(60u32);
+    let filter_words_ref: NodeRef<leptos::html::Textarea> = NodeRef::new();
+    let filter_mode_ref: NodeRef<leptos::html::Select> = NodeRef::new();
+
+    let delete_message = move |id: i64| {
+        spawn_local(async move {
+            match admin_delete_chat_message(id).await {
+                Ok(_) => {
+                    set_action_msg.set("Message deleted.".to_string());
+                    set_moderation_version.update(|v| *v += 1);
+                }
+                Err(e) => set_action_msg.set(format!("Error: {}", e)),
+            }
+        });
+    };
+
+    let mute = move |kind: &'static str, value: String| {
+        let minutes = mute_minutes.get_untracked();
+        spawn_local(async move {
+            match admin_mute(kind.to_string(), value.clone(), minutes).await {
+                Ok(_) => {
+                    set_action_msg.set(format!("Muted {} for {} minutes.", value, minutes));
+                    set_moderation_version.update(|v| *v += 1);
+                }
+                Err(e) => set_action_msg.set(format!("Error: {}", e)),
+            }
+        });
+    };
+
+    let unmute = move |id: i64| {
+        spawn_local(async move {
+            match admin_unmute(id).await {
+                Ok(_) => {
+                    set_action_msg.set("Mute lifted.".to_string());
+                    set_moderation_version.update(|v| *v += 1);
+                }
+                Err(e) => set_action_msg.set(format!("Error: {}", e)),
+            }
+        });
+    };
+
+    let save_filter = move |_| {
+        let words = filter_words_ref
+            .get_untracked()
+            .map(|el| el.value())
+            .unwrap_or_default()
+            .split([',', '\n'])
+            .map(|w| w.trim().to_string())
+            .filter(|w| !w.is_empty())
+            .collect();
+        let mode = match filter_mode_ref.get_untracked().map(|el| el.value()).as_deref() {
+            Some("reject") => FilterMode::Reject,
+            _ => FilterMode::Mask,
+        };
+        spawn_local(async move {
+            match admin_set_chat_filter(ChatFilter { words, mode }).await {
+                Ok(_) => set_action_msg.set("Word filter saved.".to_string()),
+                Err(e) => set_action_msg.set(format!("Error: {}", e)),
+            }
+        });
+    };
+
+    let clear_chat = move |_| {
+        #[cfg(not(feature = "ssr"))]
+        {
+            let confirmed = web_sys::window()
+                .and_then(|w| w.confirm_with_message("Clear all chat messages?").ok())
+                .unwrap_or(false);
+            if !confirmed { return; }
+        }
+        spawn_local(async move {
+            match admin_clear_chat().await {
+                Ok(_) => {
+                    set_action_msg.set("Chat cleared.".to_string());
+                    set_moderation_version.update(|v| *v += 1);
+                }
                 Err(e) => set_action_msg.set(format!("Error: {}", e)),
             }
         });
@@ -1634,17 +2958,72 @@ fn AdminPanel(
         |(p, _)| get_file_list(p),
     );
 
+    let (quarantine_version, set_quarantine_version) = signal(0u32);
+    let quarantine = Resource::new(move || quarantine_version.get(), move |_| if_allowed(files, admin_list_quarantine()));
+
+    let (lockouts_version, set_lockouts_version) = signal(0u32);
+    let session_count = Resource::new(move || lockouts_version.get(), move |_| if_allowed(accounts_admin, admin_session_count()));
+    let logout_all = move |_| {
+        #[cfg(not(feature = "ssr"))]
+        {
+            let confirmed = web_sys::window()
+                .and_then(|w| w.confirm_with_message("Log out every admin session, including this one?").ok())
+                .unwrap_or(false);
+            if !confirmed { return; }
+        }
+        spawn_local(async move {
+            match admin_logout_all().await {
+                Ok(_) => set_identity.set(None),
+                Err(e) => set_action_msg.set(format!("Error: {}", e)),
+            }
+        });
+    };
+    let lockouts = Resource::new(move || lockouts_version.get(), move |_| if_allowed(accounts_admin, admin_list_lockouts()));
+
+    let quarantine_action = move |id: i64, action: &'static str| {
+        #[cfg(not(feature = "ssr"))]
+        if action != "rescan" {
+            let question = if action == "release" {
+                "Publish this file even though it was flagged?"
+            } else {
+                "Delete this quarantined file?"
+            };
+            let confirmed = web_sys::window()
+                .and_then(|w| w.confirm_with_message(question).ok())
+                .unwrap_or(false);
+            if !confirmed { return; }
+        }
+        spawn_local(async move {
+            let result = match action {
+                "release" => admin_quarantine_release(id).await
+                    .map(|rel| format!("Published as {}.", rel)),
+                "rescan" => admin_quarantine_rescan(id).await
+                    .map(|_| "Queued for another scan.".to_string()),
+                _ => admin_quarantine_delete(id).await
+                    .map(|_| "Quarantined file deleted.".to_string()),
+            };
+            match result {
+                Ok(msg) => {
+                    set_action_msg.set(msg);
+                    set_quarantine_version.update(|v| *v += 1);
+                    set_browser_version.update(|v| *v += 1);
+                    set_data_version.update(|v| *v += 1);
+                }
+                Err(e) => set_action_msg.set(format!("Error: {}", e)),
+            }
+        });
+    };
+
     let approve = move |id: i64| {
-        let Some(t) = token.get_untracked() else { return };
         spawn_local(async move {
-            match admin_approve(t, id).await {
+            match admin_approve(id).await {
                 Ok(_) => {
                     set_action_msg.set("Approved (file will not expire).".to_string());
                     set_data_version.update(|v| *v += 1);
                 }
                 Err(e) => {
                     if e.to_string().contains("unauthorized") {
-                        set_token.set(None);
+                        set_identity.set(None);
                     } else {
                         set_action_msg.set(format!("Error: {}", e));
                     }
@@ -1675,7 +3054,6 @@ fn AdminPanel(
     };
 
     let delete_file = move |rel: String| {
-        let Some(t) = token.get_untracked() else { return };
         #[cfg(not(feature = "ssr"))]
         {
             let confirmed = web_sys::window()
@@ -1685,7 +3063,7 @@ fn AdminPanel(
         }
         let _ = &rel;
         spawn_local(async move {
-            match admin_delete_file(t, rel).await {
+            match admin_delete_file(rel).await {
                 Ok(_) => {
                     set_action_msg.set("File deleted.".to_string());
                     set_browser_version.update(|v| *v += 1);
@@ -1697,7 +3075,6 @@ fn AdminPanel(
     };
 
     let delete_dir = move |rel: String| {
-        let Some(t) = token.get_untracked() else { return };
         #[cfg(not(feature = "ssr"))]
         {
             let confirmed = web_sys::window()
@@ -1706,7 +3083,7 @@ fn AdminPanel(
             if !confirmed { return; }
         }
         spawn_local(async move {
-            match admin_delete_directory(t, rel).await {
+            match admin_delete_directory(rel).await {
                 Ok(_) => {
                     set_action_msg.set("Directory deleted.".to_string());
                     set_browser_version.update(|v| *v += 1);
@@ -1717,7 +3094,6 @@ fn AdminPanel(
     };
 
     let move_file = move |rel: String| {
-        let Some(t) = token.get_untracked() else { return };
         #[cfg(not(feature = "ssr"))]
         {
             let dst = web_sys::window()
@@ -1732,7 +3108,7 @@ fn AdminPanel(
             }
             let dst_clone = dst.clone();
             spawn_local(async move {
-                match admin_move_file(t, rel, dst_clone).await {
+                match admin_move_file(rel, dst_clone).await {
                     Ok(_) => {
                         set_action_msg.set("File moved.".to_string());
                         set_browser_version.update(|v| *v += 1);
@@ -1744,7 +3120,80 @@ fn AdminPanel(
         }
         #[cfg(feature = "ssr")]
         {
-            let _ = (rel, t);
+            let _ = rel;
+        }
+    };
+
+    let (accounts_version, set_accounts_version) = signal(0u32);
+    let accounts = Resource::new(move || accounts_version.get(), move |_| if_allowed(accounts_admin, admin_list_accounts()));
+    let (new_account_name, set_new_account_name) = signal(String::new());
+    let (new_account_password, set_new_account_password) = signal(String::new());
+    let new_account_role_ref: NodeRef<leptos::html::Select> = NodeRef::new();
+
+    let create_account = move |_| {
+        let name = new_account_name.get_untracked();
+        let password = new_account_password.get_untracked();
+        let role = new_account_role_ref
+            .get_untracked()
+            .map(|el| el.value())
+            .unwrap_or_default();
+        if name.trim().is_empty() || password.is_empty() {
+            return;
+        }
+        spawn_local(async move {
+            match admin_create_account(name, role, password).await {
+                Ok(_) => {
+                    set_action_msg.set("Account created.".to_string());
+                    set_new_account_name.set(String::new());
+                    set_new_account_password.set(String::new());
+                    set_accounts_version.update(|v| *v += 1);
+                }
+                Err(e) => set_action_msg.set(format!("Error: {}", e)),
+            }
+        });
+    };
+
+    let revoke_account = move |id: i64, name: String| {
+        #[cfg(not(feature = "ssr"))]
+        {
+            let confirmed = web_sys::window()
+                .and_then(|w| w.confirm_with_message(&format!("Revoke the account {}? Its sessions end at once.", name)).ok())
+                .unwrap_or(false);
+            if !confirmed { return; }
+        }
+        let _ = name;
+        spawn_local(async move {
+            match admin_delete_account(id).await {
+                Ok(_) => {
+                    set_action_msg.set("Account revoked.".to_string());
+                    set_accounts_version.update(|v| *v += 1);
+                    set_lockouts_version.update(|v| *v += 1);
+                }
+                Err(e) => set_action_msg.set(format!("Error: {}", e)),
+            }
+        });
+    };
+
+    let reset_account = move |id: i64, name: String| {
+        #[cfg(not(feature = "ssr"))]
+        {
+            let password = web_sys::window()
+                .and_then(|w| w.prompt_with_message(&format!("New password for {} (at least 8 characters):", name)).ok())
+                .flatten();
+            let Some(password) = password.filter(|p| !p.is_empty()) else { return };
+            spawn_local(async move {
+                match admin_reset_account_password(id, password).await {
+                    Ok(_) => {
+                        set_action_msg.set(format!("Password of {} reset; their sessions were ended.", name));
+                        set_lockouts_version.update(|v| *v += 1);
+                    }
+                    Err(e) => set_action_msg.set(format!("Error: {}", e)),
+                }
+            });
+        }
+        #[cfg(feature = "ssr")]
+        {
+            let _ = (id, name);
         }
     };
 
@@ -1753,204 +3202,635 @@ fn AdminPanel(
             <div class="admin-action-msg">{move || action_msg.get()}</div>
         </Show>
 
-        <div class="card">
-            <div class="card-header"><h2>"Chat controls"</h2></div>
-            <div class="card-body">
-                <Suspense fallback=|| view! { <p class="loading">"Loading..."</p> }>
-                {move || chat_status.get().map(|res| match res {
-                    Ok(enabled) => {
-                        let label = if enabled { "Chat is currently ENABLED." } else { "Chat is currently DISABLED." };
+        <p class="text-muted">{format!("Signed in as {} ({}).", identity.name, identity.role.label())}</p>
+
+        {settings.then(|| view! {
+            <div class="card">
+                <div class="card-header"><h2>"Chat controls"</h2></div>
+                <div class="card-body">
+                    <Suspense fallback=|| view! { <p class="loading">"Loading..."</p> }>
+                    {move || chat_status.get().map(|res| match res {
+                        Ok(enabled) => {
+                            let label = if enabled { "Chat is currently ENABLED." } else { "Chat is currently DISABLED." };
+                            view! {
+                                <p class="text-muted">{label}</p>
+                                <div class="admin-button-row">
+                                    {if enabled {
+                                        view! {
+                                            <button class="btn-secondary" type="button"
+                                                on:click=move |_| toggle_chat(false)
+                                            >"Disable chat"</button>
+                                        }.into_any()
+                                    } else {
+                                        view! {
+                                            <button class="btn-primary" type="button"
+                                                on:click=move |_| toggle_chat(true)
+                                            >"Enable chat"</button>
+                                        }.into_any()
+                                    }}
+                                </div>
+                            }.into_any()
+                        }
+                        Err(e) => {
+                            if e.to_string().contains("unauthorized") {
+                                set_identity.set(None);
+                            }
+                            view! { <p>"Error: " {e.to_string()}</p> }.into_any()
+                        }
+                    })}
+                    </Suspense>
+                    <Suspense fallback=|| ()>
+                    {move || chat_limits.get().and_then(|res| res.ok()).map(|loaded| {
+                        type Setter = fn(&mut ChatLimits, u64);
+                        let fields: [(&str, u64, Setter); 4] = [
+                            ("Burst (messages)", loaded.burst as u64, |l, v| l.burst = v as u32),
+                            ("Then per minute", loaded.per_minute as u64, |l, v| l.per_minute = v as u32),
+                            ("Max length (chars)", loaded.max_length as u64, |l, v| l.max_length = v as u32),
+                            ("Repeat window (s)", loaded.duplicate_window_secs, |l, v| l.duplicate_window_secs = v),
+                        ];
                         view! {
-                            <p class="text-muted">{label}</p>
-                            <div class="admin-button-row">
-                                {if enabled {
+                            <p class="text-muted">"Flood protection, per client address:"</p>
+                            <div class="admin-limits">
+                                {fields.into_iter().map(|(label, value, set)| {
+                                    let base = loaded.clone();
                                     view! {
-                                        <button class="btn-secondary" type="button"
-                                            on:click=move |_| toggle_chat(false)
-                                        >"Disable chat"</button>
-                                    }.into_any()
-                                } else {
-                                    view! {
-                                        <button class="btn-primary" type="button"
-                                            on:click=move |_| toggle_chat(true)
-                                        >"Enable chat"</button>
-                                    }.into_any()
-                                }}
-                                <button class="btn-danger" type="button"
-                                    on:click=clear_chat
-                                >"Clear chat history"</button>
+                                        <label>
+                                            <span>{label}</span>
+                                            <input type="number" min="0" class="new-folder-input"
+                                                value=value.to_string()
+                                                on:input=move |ev| {
+                                                    let Ok(v) = event_target_value(&ev).parse::<u64>() else { return };
+                                                    limits_draft.update(|d| set(d.get_or_insert_with(|| base.clone()), v));
+                                                }
+                                            />
+                                        </label>
+                                    }
+                                }).collect_view()}
+                            </div>
+                            <div class="admin-button-row">
+                                <button class="btn-primary" type="button"
+                                    disabled=move || limits_draft.with(|d| d.is_none())
+                                    on:click=save_limits
+                                >"Save limits"</button>
                             </div>
-                        }.into_any()
-                    }
-                    Err(e) => {
-                        if e.to_string().contains("unauthorized") {
-                            set_token.set(None);
                         }
-                        view! { <p>"Error: " {e.to_string()}</p> }.into_any()
-                    }
-                })}
-                </Suspense>
+                    })}
+                    </Suspense>
+                </div>
+            </div>
+        })}
+
+        {moderate.then(|| view! {
+            <div class="card">
+                <div class="card-header"><h2>"Chat channels"</h2></div>
+                <div class="card-body">
+                    <div class="new-folder-row">
+                        <input type="text" class="new-folder-input" placeholder="New channel name..."
+                            prop:value=move || new_channel.get()
+                            on:input=move |ev| set_new_channel.set(event_target_value(&ev))
+                        />
+                        <button class="btn-primary" type="button" on:click=create_channel>"Create"</button>
+                    </div>
+                    <Suspense fallback=|| view! { <p class="loading">"Loading..."</p> }>
+                    {move || admin_channels.get().and_then(|r| r.ok()).map(|list| view! {
+                        <table class="admin-table">
+                            <tbody>
+                                {list.into_iter().map(|name| {
+                                    let is_default = name == DEFAULT_CHANNEL;
+                                    let label = format!("#{}", name);
+                                    view! {
+                                        <tr>
+                                            <td>{label}</td>
+                                            <td>
+                                                {if is_default {
+                                                    view! { <span class="text-muted">"default"</span> }.into_any()
+                                                } else {
+                                                    view! {
+                                                        <button class="btn-danger" type="button"
+                                                            on:click=move |_| delete_channel(name.clone())
+                                                        >"Delete"</button>
+                                                    }.into_any()
+                                                }}
+                                            </td>
+                                        </tr>
+                                    }
+                                }).collect::<Vec<_>>()}
+                            </tbody>
+                        </table>
+                    })}
+                    </Suspense>
+                </div>
             </div>
-        </div>
 
-        <div class="card">
-            <div class="card-header"><h2>"Tracked uploads"</h2></div>
-            <div class="card-body">
-                <Suspense fallback=|| view! { <p class="loading">"Loading..."</p> }>
-                {move || expiring.get().map(|res| match res {
-                    Ok((enabled, days, items)) => {
-                        let header = if enabled {
-                            format!("Expiration is ON — files older than {} days will be deleted.", days)
-                        } else {
-                            "Expiration is OFF — these files are tracked but will not be deleted.".to_string()
-                        };
-                        let has_items = !items.is_empty();
-                        view! {
-                            <p class="text-muted">{header}</p>
-                            {if has_items {
-                                Some(view! {
-                                    <table class="admin-table">
-                                        <thead>
+            <div class="card">
+                <div class="card-header"><h2>"Chat moderation"</h2></div>
+                <div class="card-body">
+                    <div class="admin-button-row">
+                        <button class="btn-secondary" type="button"
+                            on:click=move |_| set_moderation_version.update(|v| *v += 1)
+                        >"Refresh"</button>
+                        <label class="text-muted">
+                            "Mute for "
+                            <select on:change=move |ev| {
+                                if let Ok(m) = event_target_value(&ev).parse() {
+                                    set_mute_minutes.set(m);
+                                }
+                            }>
+                                <option value="10">"10 minutes"</option>
+                                <option value="60" selected>"1 hour"</option>
+                                <option value="1440">"1 day"</option>
+                            </select>
+                        </label>
+                        <button class="btn-danger" type="button"
+                            on:click=clear_chat
+                        >"Clear chat history"</button>
+                    </div>
+                    <Suspense fallback=|| view! { <p class="loading">"Loading..."</p> }>
+                    {move || recent_chat.get().map(|res| match res {
+                        Ok(items) if items.is_empty() => {
+                            view! { <p class="text-muted">"No chat messages."</p> }.into_any()
+                        }
+                        Ok(items) => view! {
+                            <table class="admin-table">
+                                <thead>
+                                    <tr>
+                                        <th>"From"</th>
+                                        <th>"Message"</th>
+                                        <th>"Sent"</th>
+                                        <th></th>
+                                    </tr>
+                                </thead>
+                                <tbody>
+                                    {items.into_iter().map(|(msg, client)| {
+                                        let id = msg.id;
+                                        let name = msg.name.clone();
+                                        let from = if client.is_empty() {
+                                            format!("{} in #{}", msg.name, msg.channel)
+                                        } else {
+                                            format!("{} ({}) in #{}", msg.name, client, msg.channel)
+                                        };
+                                        let has_client = !client.is_empty();
+                                        view! {
                                             <tr>
-                                                <th>"Path"</th>
-                                                <th>"Uploaded"</th>
-                                                <th>"Expires"</th>
-                                                <th></th>
+                                                <td>{from}</td>
+                                                <td class="admin-path">{msg.text}</td>
+                                                <td>{format_epoch(msg.timestamp)}</td>
+                                                <td>
+                                                    <div class="admin-button-row">
+                                                        <button class="btn-secondary" type="button"
+                                                            on:click=move |_| mute("name", name.clone())
+                                                        >"Mute name"</button>
+                                                        {has_client.then(|| view! {
+                                                            <button class="btn-secondary" type="button"
+                                                                on:click=move |_| mute("client", client.clone())
+                                                            >"Mute client"</button>
+                                                        })}
+                                                        <button class="btn-danger" type="button"
+                                                            on:click=move |_| delete_message(id)
+                                                        >"Delete"</button>
+                                                    </div>
+                                                </td>
                                             </tr>
-                                        </thead>
-                                        <tbody>
-                                            {items.into_iter().map(|(id, path, uploaded, expires)| {
-                                                let upl = format_epoch(uploaded);
-                                                let exp = expires.map(format_epoch).unwrap_or_else(|| "—".to_string());
-                                                let path_clone = path.clone();
-                                                view! {
-                                                    <tr>
-                                                        <td class="admin-path">{path_clone}</td>
-                                                        <td>{upl}</td>
-                                                        <td>{exp}</td>
-                                                        <td><button class="btn-secondary" type="button"
-                                                            on:click=move |_| approve(id)
-                                                        >"Approve"</button></td>
-                                                    </tr>
-                                                }
-                                            }).collect::<Vec<_>>()}
-                                        </tbody>
-                                    </table>
-                                })
+                                        }
+                                    }).collect::<Vec<_>>()}
+                                </tbody>
+                            </table>
+                        }.into_any(),
+                        Err(e) => {
+                            if e.to_string().contains("unauthorized") {
+                                set_identity.set(None);
+                            }
+                            view! { <p>"Error: " {e.to_string()}</p> }.into_any()
+                        }
+                    })}
+                    </Suspense>
+                    <Suspense fallback=|| ()>
+                    {move || mutes.get().and_then(|res| res.ok()).filter(|m| !m.is_empty()).map(|items| view! {
+                        <p class="text-muted">"Active mutes:"</p>
+                        <table class="admin-table">
+                            <tbody>
+                                {items.into_iter().map(|(id, kind, value, until)| view! {
+                                    <tr>
+                                        <td>{kind}</td>
+                                        <td class="admin-path">{value}</td>
+                                        <td>"until " {format_epoch(until)}</td>
+                                        <td><button class="btn-secondary" type="button"
+                                            on:click=move |_| unmute(id)
+                                        >"Unmute"</button></td>
+                                    </tr>
+                                }).collect::<Vec<_>>()}
+                            </tbody>
+                        </table>
+                    })}
+                    </Suspense>
+                    <Suspense fallback=|| ()>
+                    {move || chat_filter.get().and_then(|res| res.ok()).map(|filter| {
+                        let reject = filter.mode == FilterMode::Reject;
+                        view! {
+                            <p class="text-muted">"Word filter (one word per line, whole words, any case):"</p>
+                            <textarea class="new-folder-input admin-word-filter" rows="4"
+                                node_ref=filter_words_ref
+                                prop:value=filter.words.join("\n")
+                            ></textarea>
+                            <div class="admin-button-row">
+                                <select node_ref=filter_mode_ref>
+                                    <option value="mask" selected=!reject>"Mask matching words"</option>
+                                    <option value="reject" selected=reject>"Reject the message"</option>
+                                </select>
+                                <button class="btn-primary" type="button" on:click=save_filter>"Save filter"</button>
+                            </div>
+                        }
+                    })}
+                    </Suspense>
+                </div>
+            </div>
+        })}
+
+        {files.then(|| view! {
+            <div class="card">
+                <div class="card-header"><h2>"Tracked uploads"</h2></div>
+                <div class="card-body">
+                    <Suspense fallback=|| view! { <p class="loading">"Loading..."</p> }>
+                    {move || expiring.get().map(|res| match res {
+                        Ok((enabled, days, items)) => {
+                            let header = if enabled {
+                                format!("Expiration is ON — files older than {} days will be deleted.", days)
                             } else {
-                                None
-                            }}
-                            {if !has_items {
-                                Some(view! { <p class="text-muted">"No tracked uploads yet."</p> })
-                            } else { None }}
-                        }.into_any()
-                    }
-                    Err(e) => {
-                        if e.to_string().contains("unauthorized") {
-                            set_token.set(None);
+                                "Expiration is OFF — these files are tracked but will not be deleted.".to_string()
+                            };
+                            let has_items = !items.is_empty();
+                            view! {
+                                <p class="text-muted">{header}</p>
+                                {if has_items {
+                                    Some(view! {
+                                        <table class="admin-table">
+                                            <thead>
+                                                <tr>
+                                                    <th>"Path"</th>
+                                                    <th>"Uploaded"</th>
+                                                    <th>"Expires"</th>
+                                                    <th></th>
+                                                </tr>
+                                            </thead>
+                                            <tbody>
+                                                {items.into_iter().map(|(id, path, uploaded, expires)| {
+                                                    let upl = format_epoch(uploaded);
+                                                    let exp = expires.map(format_epoch).unwrap_or_else(|| "—".to_string());
+                                                    let path_clone = path.clone();
+                                                    view! {
+                                                        <tr>
+                                                            <td class="admin-path">{path_clone}</td>
+                                                            <td>{upl}</td>
+                                                            <td>{exp}</td>
+                                                            <td><button class="btn-secondary" type="button"
+                                                                on:click=move |_| approve(id)
+                                                            >"Approve"</button></td>
+                                                        </tr>
+                                                    }
+                                                }).collect::<Vec<_>>()}
+                                            </tbody>
+                                        </table>
+                                    })
+                                } else {
+                                    None
+                                }}
+                                {if !has_items {
+                                    Some(view! { <p class="text-muted">"No tracked uploads yet."</p> })
+                                } else { None }}
+                            }.into_any()
                         }
-                        view! { <p>"Error: " {e.to_string()}</p> }.into_any()
-                    }
-                })}
-                </Suspense>
+                        Err(e) => {
+                            if e.to_string().contains("unauthorized") {
+                                set_identity.set(None);
+                            }
+                            view! { <p>"Error: " {e.to_string()}</p> }.into_any()
+                        }
+                    })}
+                    </Suspense>
+                </div>
             </div>
-        </div>
 
-        <div class="card">
-            <div class="card-header"><h2>"File browser"</h2></div>
-            <div class="card-body">
-                <div class="current-dir">
-                    {move || {
-                        let p = browser_path.get();
-                        if p.is_empty() { "/".to_string() } else { format!("/{}", p) }
-                    }}
+            <div class="card">
+                <div class="card-header"><h2>"Quarantine"</h2></div>
+                <div class="card-body">
+                    <Suspense fallback=|| view! { <p class="loading">"Loading..."</p> }>
+                    {move || quarantine.get().map(|res| match res {
+                        Ok(items) if items.is_empty() => {
+                            view! { <p class="text-muted">"Nothing in quarantine."</p> }.into_any()
+                        }
+                        Ok(items) => view! {
+                            <div class="admin-button-row">
+                                <button class="btn-secondary" type="button"
+                                    on:click=move |_| set_quarantine_version.update(|v| *v += 1)
+                                >"Refresh"</button>
+                            </div>
+                            <table class="admin-table">
+                                <thead>
+                                    <tr>
+                                        <th>"Destination"</th>
+                                        <th>"Status"</th>
+                                        <th>"Uploaded"</th>
+                                        <th></th>
+                                    </tr>
+                                </thead>
+                                <tbody>
+                                    {items.into_iter().map(|(id, dest, status, detail, created)| {
+                                        let pending = status == "pending";
+                                        let failed = status == "error";
+                                        let label = if detail.is_empty() {
+                                            status
+                                        } else {
+                                            format!("{}: {}", status, detail)
+                                        };
+                                        view! {
+                                            <tr>
+                                                <td class="admin-path">{dest}</td>
+                                                <td>{label}</td>
+                                                <td>{format_epoch(created)}</td>
+                                                <td>
+                                                    {(!pending).then(|| view! {
+                                                        <div class="admin-button-row">
+                                                            {failed.then(|| view! {
+                                                                <button class="btn-secondary" type="button"
+                                                                    on:click=move |_| quarantine_action(id, "rescan")
+                                                                >"Rescan"</button>
+                                                            })}
+                                                            <button class="btn-secondary" type="button"
+                                                                on:click=move |_| quarantine_action(id, "release")
+                                                            >"Release"</button>
+                                                            <button class="btn-danger" type="button"
+                                                                on:click=move |_| quarantine_action(id, "delete")
+                                                            >"Delete"</button>
+                                                        </div>
+                                                    })}
+                                                </td>
+                                            </tr>
+                                        }
+                                    }).collect::<Vec<_>>()}
+                                </tbody>
+                            </table>
+                        }.into_any(),
+                        Err(e) => {
+                            if e.to_string().contains("unauthorized") {
+                                set_identity.set(None);
+                            }
+                            view! { <p>"Error: " {e.to_string()}</p> }.into_any()
+                        }
+                    })}
+                    </Suspense>
                 </div>
-                <div class="new-folder-row">
-                    <input type="text" class="new-folder-input" placeholder="New folder name..."
-                        node_ref=folder_input_ref
-                        on:input=move |_| {
-                            if let Some(input) = folder_input_ref.get() {
-                                set_folder_name.set(input.value());
+            </div>
+        })}
+
+        {accounts_admin.then(|| view! {
+            <div class="card">
+                <div class="card-header"><h2>"Admin sessions"</h2></div>
+                <div class="card-body">
+                    <Suspense fallback=|| view! { <p class="loading">"Loading..."</p> }>
+                    {move || session_count.get().map(|res| match res {
+                        Ok(n) => view! {
+                            <p class="text-muted">
+                                {format!("{} signed-in session{}, this one included. Sessions end after 8 hours without use.", n, if n == 1 { "" } else { "s" })}
+                            </p>
+                        }.into_any(),
+                        Err(e) => {
+                            if e.to_string().contains("unauthorized") {
+                                set_identity.set(None);
                             }
+                            view! { <p>"Error: " {e.to_string()}</p> }.into_any()
                         }
-                    />
-                    <button class="btn-primary" type="button"
-                        disabled=move || folder_name.get().is_empty()
-                        on:click=create_folder
-                    >"Create"</button>
+                    })}
+                    </Suspense>
+                    <div class="admin-button-row">
+                        <button class="btn-danger" type="button" on:click=logout_all>"Log out all sessions"</button>
+                    </div>
                 </div>
+            </div>
 
-                <Suspense fallback=|| view! { <p class="loading">"Loading..."</p> }>
-                    {move || listing.get().map(|res| match res {
-                        Ok(entries) => view! {
-                            <div class="file-list admin-file-list">
-                                {entries.into_iter().map(|(file_type, file_name, _size)| {
-                                    let p = browser_path.get_untracked();
-                                    let rel = if file_name == ".." {
-                                        String::new()
-                                    } else {
-                                        format!("{}{}", p, file_name)
-                                    };
-                                    let is_parent = file_name == "..";
-                                    let is_dir = file_type == "d";
-                                    let display_name = if is_dir { format!("{}/", file_name) } else { file_name.clone() };
-                                    let rel_for_open = rel.clone();
-                                    let rel_for_delete = rel.clone();
-                                    let rel_for_move = rel.clone();
-                                    let rel_for_dir_delete = rel.clone();
-
-                                    view! {
-                                        <div class="admin-file-row">
-                                            <img src={if is_dir { "/assets/folder.png" } else { "/assets/file.png" }} class="file-icon"/>
-                                            <span class="file-name">{display_name}</span>
-                                            <div class="admin-file-actions">
-                                                {if is_parent {
-                                                    view! {
-                                                        <button class="btn-secondary" type="button"
-                                                            on:click=move |_| {
-                                                                let cur = browser_path.get_untracked();
-                                                                let mut parts: Vec<&str> = cur.trim_end_matches('/').split('/').collect();
-                                                                parts.pop();
-                                                                let np = parts.join("/");
-                                                                set_browser_path.set(if np.is_empty() { String::new() } else { format!("{}/", np) });
-                                                            }
-                                                        >"Open"</button>
-                                                    }.into_any()
-                                                } else if is_dir {
-                                                    view! {
-                                                        <button class="btn-secondary" type="button"
-                                                            on:click=move |_| {
-                                                                set_browser_path.update(|p| { p.push_str(&rel_for_open); p.push('/'); });
-                                                            }
-                                                        >"Open"</button>
-                                                        <button class="btn-danger" type="button"
-                                                            on:click=move |_| delete_dir(rel_for_dir_delete.clone())
-                                                        >"Delete"</button>
-                                                    }.into_any()
-                                                } else {
-                                                    view! {
-                                                        <button class="btn-secondary" type="button"
-                                                            on:click=move |_| move_file(rel_for_move.clone())
-                                                        >"Move"</button>
-                                                        <button class="btn-danger" type="button"
-                                                            on:click=move |_| delete_file(rel_for_delete.clone())
-                                                        >"Delete"</button>
-                                                    }.into_any()
-                                                }}
-                                            </div>
-                                        </div>
-                                    }
-                                }).collect::<Vec<_>>()}
+            <div class="card">
+                <div class="card-header"><h2>"Login lockouts"</h2></div>
+                <div class="card-body">
+                    <Suspense fallback=|| view! { <p class="loading">"Loading..."</p> }>
+                    {move || lockouts.get().map(|res| match res {
+                        Ok(items) if items.is_empty() => {
+                            view! { <p class="text-muted">"No client has been locked out."</p> }.into_any()
+                        }
+                        Ok(items) => view! {
+                            <div class="admin-button-row">
+                                <button class="btn-secondary" type="button"
+                                    on:click=move |_| set_lockouts_version.update(|v| *v += 1)
+                                >"Refresh"</button>
                             </div>
+                            <table class="admin-table">
+                                <thead>
+                                    <tr>
+                                        <th>"When"</th>
+                                        <th>"Client"</th>
+                                        <th>"Failed attempts"</th>
+                                        <th>"Lockout ends"</th>
+                                    </tr>
+                                </thead>
+                                <tbody>
+                                    {items.into_iter().map(|(at, client, failures, until)| view! {
+                                        <tr>
+                                            <td>{format_epoch(at)}</td>
+                                            <td>{client}</td>
+                                            <td>{failures}</td>
+                                            <td>{format_epoch(until)}</td>
+                                        </tr>
+                                    }).collect::<Vec<_>>()}
+                                </tbody>
+                            </table>
                         }.into_any(),
-                        Err(e) => view! { <p>"Error: " {e.to_string()}</p> }.into_any(),
+                        Err(e) => {
+                            if e.to_string().contains("unauthorized") {
+                                set_identity.set(None);
+                            }
+                            view! { <p>"Error: " {e.to_string()}</p> }.into_any()
+                        }
                     })}
-                </Suspense>
+                    </Suspense>
+                </div>
             </div>
-        </div>
+
+            <div class="card">
+                <div class="card-header"><h2>"Accounts"</h2></div>
+                <div class="card-body">
+                    <p class="text-muted">"Named sign-ins for helpers. File moderators manage shared files and the quarantine; chat moderators manage channels, messages, mutes and the word filter."</p>
+                    <div class="new-folder-row">
+                        <input type="text" class="new-folder-input" placeholder="Name"
+                            prop:value=move || new_account_name.get()
+                            on:input=move |ev| set_new_account_name.set(event_target_value(&ev))
+                        />
+                        <select node_ref=new_account_role_ref>
+                            {Role::ALL.into_iter().rev().map(|role| view! {
+                                <option value=role.as_str()>{role.label()}</option>
+                            }).collect::<Vec<_>>()}
+                        </select>
+                        <input type="password" class="new-folder-input" placeholder="Password"
+                            autocomplete="new-password"
+                            prop:value=move || new_account_password.get()
+                            on:input=move |ev| set_new_account_password.set(event_target_value(&ev))
+                        />
+                        <button class="btn-primary" type="button" on:click=create_account>"Create"</button>
+                    </div>
+                    <Suspense fallback=|| view! { <p class="loading">"Loading..."</p> }>
+                    {move || accounts.get().map(|res| match res {
+                        Ok(items) if items.is_empty() => {
+                            view! { <p class="text-muted">"No accounts yet; only the admin password signs in."</p> }.into_any()
+                        }
+                        Ok(items) => view! {
+                            <table class="admin-table">
+                                <thead>
+                                    <tr>
+                                        <th>"Name"</th>
+                                        <th>"Role"</th>
+                                        <th>"Created"</th>
+                                        <th></th>
+                                    </tr>
+                                </thead>
+                                <tbody>
+                                    {items.into_iter().map(|account| {
+                                        let AccountInfo { id, name, role, created_at } = account;
+                                        let name_for_reset = name.clone();
+                                        let name_for_revoke = name.clone();
+                                        view! {
+                                            <tr>
+                                                <td>{name}</td>
+                                                <td>{role.label()}</td>
+                                                <td>{format_epoch(created_at)}</td>
+                                                <td>
+                                                    <button class="btn-secondary" type="button"
+                                                        on:click=move |_| reset_account(id, name_for_reset.clone())
+                                                    >"Reset password"</button>
+                                                    <button class="btn-danger" type="button"
+                                                        on:click=move |_| revoke_account(id, name_for_revoke.clone())
+                                                    >"Revoke"</button>
+                                                </td>
+                                            </tr>
+                                        }
+                                    }).collect::<Vec<_>>()}
+                                </tbody>
+                            </table>
+                        }.into_any(),
+                        Err(e) => {
+                            if e.to_string().contains("unauthorized") {
+                                set_identity.set(None);
+                            }
+                            view! { <p>"Error: " {e.to_string()}</p> }.into_any()
+                        }
+                    })}
+                    </Suspense>
+                </div>
+            </div>
+        })}
+
+        {files.then(|| view! {
+            <div class="card">
+                <div class="card-header"><h2>"File browser"</h2></div>
+                <div class="card-body">
+                    <div class="current-dir">
+                        {move || {
+                            let p = browser_path.get();
+                            if p.is_empty() { "/".to_string() } else { format!("/{}", p) }
+                        }}
+                    </div>
+                    <div class="new-folder-row">
+                        <input type="text" class="new-folder-input" placeholder="New folder name..."
+                            node_ref=folder_input_ref
+                            on:input=move |_| {
+                                if let Some(input) = folder_input_ref.get() {
+                                    set_folder_name.set(input.value());
+                                }
+                            }
+                        />
+                        <button class="btn-primary" type="button"
+                            disabled=move || folder_name.get().is_empty()
+                            on:click=create_folder
+                        >"Create"</button>
+                    </div>
+
+                    <Suspense fallback=|| view! { <p class="loading">"Loading..."</p> }>
+                        {move || listing.get().map(|res| match res {
+                            Ok(entries) => view! {
+                                <div class="file-list admin-file-list">
+                                    {entries.into_iter().map(|(file_type, file_name, _size)| {
+                                        let p = browser_path.get_untracked();
+                                        let rel = if file_name == ".." {
+                                            String::new()
+                                        } else {
+                                            format!("{}{}", p, file_name)
+                                        };
+                                        let is_parent = file_name == "..";
+                                        let is_dir = file_type == "d";
+                                        let display_name = if is_dir { format!("{}/", file_name) } else { file_name.clone() };
+                                        let rel_for_open = rel.clone();
+                                        let rel_for_delete = rel.clone();
+                                        let rel_for_move = rel.clone();
+                                        let rel_for_dir_delete = rel.clone();
+
+                                        view! {
+                                            <div class="admin-file-row">
+                                                <img src={if is_dir { "/assets/folder.png" } else { "/assets/file.png" }} class="file-icon"/>
+                                                <span class="file-name">{display_name}</span>
+                                                <div class="admin-file-actions">
+                                                    {if is_parent {
+                                                        view! {
+                                                            <button class="btn-secondary" type="button"
+                                                                on:click=move |_| {
+                                                                    let cur = browser_path.get_untracked();
+                                                                    let mut parts: Vec<&str> = cur.trim_end_matches('/').split('/').collect();
+                                                                    parts.pop();
+                                                                    let np = parts.join("/");
+                                                                    set_browser_path.set(if np.is_empty() { String::new() } else { format!("{}/", np) });
+                                                                }
+                                                            >"Open"</button>
+                                                        }.into_any()
+                                                    } else if is_dir {
+                                                        view! {
+                                                            <button class="btn-secondary" type="button"
+                                                                on:click=move |_| {
+                                                                    set_browser_path.update(|p| { p.push_str(&rel_for_open); p.push('/'); });
+                                                                }
+                                                            >"Open"</button>
+                                                            <button class="btn-danger" type="button"
+                                                                on:click=move |_| delete_dir(rel_for_dir_delete.clone())
+                                                            >"Delete"</button>
+                                                        }.into_any()
+                                                    } else {
+                                                        view! {
+                                                            <button class="btn-secondary" type="button"
+                                                                on:click=move |_| move_file(rel_for_move.clone())
+                                                            >"Move"</button>
+                                                            <button class="btn-danger" type="button"
+                                                                on:click=move |_| delete_file(rel_for_delete.clone())
+                                                            >"Delete"</button>
+                                                        }.into_any()
+                                                    }}
+                                                </div>
+                                            </div>
+                                        }
+                                    }).collect::<Vec<_>>()}
+                                </div>
+                            }.into_any(),
+                            Err(e) => view! { <p>"Error: " {e.to_string()}</p> }.into_any(),
+                        })}
+                    </Suspense>
+                </div>
+            </div>
+        })}
     }.into_any()
 }
 
+/// Runs `fetch` only if the signed-in role may see its card, so the panel
+/// doesn't ask for data it would be refused.
+async fn if_allowed<T: Default>(
+    allowed: bool,
+    fetch: impl std::future::Future<Output = Result<T, ServerFnError>>,
+) -> Result<T, ServerFnError> {
+    if allowed { fetch.await } else { Ok(T::default()) }
+}
+
 fn format_epoch(_secs: u64) -> String {
     #[cfg(not(feature = "ssr"))]
     {
@@ -1996,14 +3876,20 @@ pub mod ssr_imports {
     }
 
     lazy_static::lazy_static! {
-        pub static ref CHAT_CHANNEL: tokio::sync::broadcast::Sender<i32> = {
-            let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        pub static ref CHAT_CHANNEL: tokio::sync::broadcast::Sender<super::ChatEvent> = {
+            // Events carry the messages themselves; a stream that still
+            // falls behind catches up from the database.
+            let (tx, _rx) = tokio::sync::broadcast::channel(256);
             tx
         };
         pub static ref USERS_CHANNEL: tokio::sync::broadcast::Sender<i32> = {
             let (tx, _rx) = tokio::sync::broadcast::channel(16);
             tx
         };
+        pub static ref FILES_CHANNEL: tokio::sync::broadcast::Sender<super::FileEvent> = {
+            let (tx, _rx) = tokio::sync::broadcast::channel(64);
+            tx
+        };
         pub static ref STATS: Arc<RwLock<Stats>> = {
             let stats = load_stats().unwrap_or_else(|| {
                 let now = std::time::SystemTime::now()
@@ -2019,17 +3905,26 @@ pub mod ssr_imports {
         };
     }
 
+    const STATS_FILE: &str = "stats.json";
+
     fn load_stats() -> Option<Stats> {
-        let data = std::fs::read_to_string("stats.json").ok()?;
+        let data = std::fs::read_to_string(crate::settings::data_path(STATS_FILE)).ok()?;
         serde_json::from_str(&data).ok()
     }
 
+    /// Tell every open browser that a share directory changed.
+    pub fn broadcast_file_event(event: super::FileEvent) {
+        crate::chat_render::forget_rendered();
+        _ = FILES_CHANNEL.send(event);
+    }
+
     pub fn save_stats() {
-        if let Ok(stats) = STATS.read() {
-            if let Ok(data) = serde_json::to_string_pretty(&*stats) {
-                let _ = std::fs::write("stats.json.tmp", &data)
-                    .and_then(|_| std::fs::rename("stats.json.tmp", "stats.json"));
-            }
+        if let Ok(stats) = STATS.read()
+            && let Ok(data) = serde_json::to_string_pretty(&*stats)
+        {
+            let path = crate::settings::data_path(STATS_FILE);
+            let tmp = path.with_extension("json.tmp");
+            let _ = std::fs::write(&tmp, &data).and_then(|_| std::fs::rename(&tmp, &path));
         }
     }
 
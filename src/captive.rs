@@ -0,0 +1,227 @@
+//! Answers to the captive-portal probes phones and laptops send when they
+//! join a Wi-Fi network.
+//!
+//! dnsmasq points every name at the box, so the probes land here whatever
+//! host they were meant for. Each OS decides from the answer whether the
+//! network is "online" or needs a sign-in page:
+//!
+//! - Android/ChromeOS: `/generate_204` (or `/gen_204`) expects a 204.
+//! - Apple: `/hotspot-detect.html` expects a tiny "Success" page.
+//! - Windows: `/connecttest.txt` and the older `/ncsi.txt` expect a fixed
+//!   line of text.
+//! - Firefox: `/canonical.html` and `/success.txt`.
+//!
+//! In portal mode every probe gets a redirect to the front page, so the
+//! OS keeps the sign-in sheet open and shows the share in it. Answering
+//! "online" even once makes Android and iOS close the sheet, which is why
+//! the probes never fall through to the normal pages.
+//!
+//! In dismissable mode the redirect goes to a short landing page instead.
+//! Once a client clicks through, its probes get the "online" answers for
+//! a while, so the OS closes the sheet and stops nagging. Only the plain
+//! HTTP probes can be answered; HTTPS ones simply fail, which every OS
+//! treats as "no internet" rather than as a portal.
+
+use crate::config::{CaptiveConfig, CaptiveMode};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const LANDING_PATH: &str = "/captive";
+pub const CONTINUE_PATH: &str = "/captive/continue";
+
+lazy_static! {
+    /// Clients that clicked through the landing page, and when.
+    static ref DISMISSED: Mutex<HashMap<IpAddr, Instant>> = Mutex::new(HashMap::new());
+}
+
+/// How to answer a probe.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Answer {
+    /// Send the client here; every OS reads a redirect as "portal".
+    Redirect(String),
+    /// The response the OS expects from a network with internet access.
+    Online {
+        status: u16,
+        content_type: &'static str,
+        body: &'static str,
+    },
+}
+
+/// The "online" answer for `path` if it is a known probe URL.
+fn online_answer(path: &str) -> Option<Answer> {
+    let (status, content_type, body) = match path {
+        "/generate_204" | "/gen_204" => (204, "text/plain", ""),
+        "/hotspot-detect.html" | "/library/test/success.html" => (
+            200,
+            "text/html",
+            "<HTML><HEAD><TITLE>Success</TITLE></HEAD><BODY>Success</BODY></HTML>",
+        ),
+        "/connecttest.txt" => (200, "text/plain", "Microsoft Connect Test"),
+        "/ncsi.txt" => (200, "text/plain", "Microsoft NCSI"),
+        "/canonical.html" => (
+            200,
+            "text/html",
+            "<meta http-equiv=\"refresh\" content=\"0;url=https://support.mozilla.org/kb/captive-portal\"/>",
+        ),
+        "/success.txt" => (200, "text/plain", "success\n"),
+        _ => return None,
+    };
+    Some(Answer::Online {
+        status,
+        content_type,
+        body,
+    })
+}
+
+/// The answer for a request to `path`, or `None` if it isn't a probe or
+/// probe handling is off. `canonical_host` is where the portal lives.
+pub fn answer(
+    path: &str,
+    client: Option<IpAddr>,
+    config: &CaptiveConfig,
+    canonical_host: &str,
+) -> Option<Answer> {
+    let online = online_answer(path)?;
+    match config.mode {
+        CaptiveMode::Off => None,
+        CaptiveMode::Portal => Some(Answer::Redirect(format!("http://{}/", canonical_host))),
+        CaptiveMode::Dismissable => {
+            if client.is_some_and(|ip| is_dismissed(ip, config.dismiss_hours)) {
+                Some(online)
+            } else {
+                Some(Answer::Redirect(format!(
+                    "http://{}{}",
+                    canonical_host, LANDING_PATH
+                )))
+            }
+        }
+    }
+}
+
+/// Remember that `client` clicked through the landing page.
+pub fn dismiss(client: IpAddr) {
+    let Ok(mut dismissed) = DISMISSED.lock() else {
+        return;
+    };
+    // Entries older than any sensible setting are dead weight.
+    let now = Instant::now();
+    dismissed.retain(|_, at| now.duration_since(*at) < Duration::from_secs(7 * 24 * 60 * 60));
+    dismissed.insert(client, now);
+}
+
+fn is_dismissed(client: IpAddr, hours: u32) -> bool {
+    let window = Duration::from_secs(u64::from(hours) * 60 * 60);
+    DISMISSED
+        .lock()
+        .ok()
+        .and_then(|d| d.get(&client).copied())
+        .is_some_and(|at| at.elapsed() < window)
+}
+
+/// The page shown in the sign-in sheet in dismissable mode. Plain HTML, as
+/// the sheet may not run the full app well.
+pub fn landing_page() -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>ShareBoxx</title>
+<style>
+body {{ font-family: sans-serif; max-width: 28em; margin: 3em auto; padding: 0 1em; line-height: 1.5; }}
+button {{ font-size: 1.1em; padding: 0.5em 1.5em; }}
+</style>
+</head>
+<body>
+<h1>Welcome to ShareBoxx</h1>
+<p>This Wi-Fi network has no internet access. It is a local place to share files and chat with the people around you.</p>
+<form method="post" action="{}">
+<button type="submit">Continue</button>
+</form>
+</body>
+</html>
+"#,
+        CONTINUE_PATH
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROBES: &[&str] = &[
+        "/generate_204",
+        "/gen_204",
+        "/hotspot-detect.html",
+        "/library/test/success.html",
+        "/connecttest.txt",
+        "/ncsi.txt",
+        "/canonical.html",
+        "/success.txt",
+    ];
+
+    fn config(mode: CaptiveMode, dismiss_hours: u32) -> CaptiveConfig {
+        CaptiveConfig { mode, dismiss_hours }
+    }
+
+    fn ip(last: u8) -> Option<IpAddr> {
+        Some(IpAddr::from([192, 168, 4, last]))
+    }
+
+    #[test]
+    fn portal_redirects_every_probe_and_off_answers_none() {
+        let portal = config(CaptiveMode::Portal, 24);
+        let off = config(CaptiveMode::Off, 24);
+        for path in PROBES {
+            assert_eq!(
+                answer(path, ip(10), &portal, "shareboxx.lan"),
+                Some(Answer::Redirect("http://shareboxx.lan/".to_string())),
+                "{}",
+                path
+            );
+            assert_eq!(answer(path, ip(10), &off, "shareboxx.lan"), None, "{}", path);
+        }
+        assert_eq!(answer("/files/generate_204", ip(10), &portal, "shareboxx.lan"), None);
+        assert_eq!(answer("/", ip(10), &portal, "shareboxx.lan"), None);
+    }
+
+    #[test]
+    fn dismissable_goes_online_after_dismiss_until_it_expires() {
+        let cfg = config(CaptiveMode::Dismissable, 2);
+        let landing = Some(Answer::Redirect(format!("http://shareboxx.lan:8080{}", LANDING_PATH)));
+        for path in PROBES {
+            assert_eq!(answer(path, ip(20), &cfg, "shareboxx.lan:8080"), landing, "{}", path);
+        }
+        assert_eq!(answer("/generate_204", None, &cfg, "shareboxx.lan:8080"), landing);
+
+        dismiss(ip(20).unwrap());
+        for path in PROBES {
+            assert_eq!(answer(path, ip(20), &cfg, "shareboxx.lan:8080"), online_answer(path), "{}", path);
+        }
+        assert_eq!(
+            answer("/generate_204", ip(20), &cfg, "shareboxx.lan:8080"),
+            Some(Answer::Online { status: 204, content_type: "text/plain", body: "" })
+        );
+        match answer("/hotspot-detect.html", ip(20), &cfg, "shareboxx.lan:8080") {
+            Some(Answer::Online { status: 200, body, .. }) => assert!(body.contains("Success")),
+            other => panic!("{:?}", other),
+        }
+        // Someone else on the hotspot still gets the landing page.
+        assert_eq!(answer("/generate_204", ip(21), &cfg, "shareboxx.lan:8080"), landing);
+
+        let hour = Duration::from_secs(60 * 60);
+        let backdate = |ago| {
+            if let Some(at) = Instant::now().checked_sub(ago) {
+                DISMISSED.lock().unwrap().insert(ip(20).unwrap(), at);
+            }
+        };
+        backdate(hour);
+        assert!(matches!(answer("/ncsi.txt", ip(20), &cfg, "shareboxx.lan:8080"), Some(Answer::Online { .. })));
+        backdate(2 * hour + Duration::from_secs(1));
+        assert_eq!(answer("/ncsi.txt", ip(20), &cfg, "shareboxx.lan:8080"), landing);
+    }
+}
@@ -0,0 +1,79 @@
+//! The admin's word filter for chat messages and nicknames.
+
+use crate::app::{ChatFilter, FilterMode};
+
+/// Apply the filter to `text`. In mask mode matching words come back as
+/// asterisks; in reject mode any match refuses the text and gives `None`.
+pub fn apply(filter: &ChatFilter, text: &str) -> Option<String> {
+    let words: Vec<String> = filter
+        .words
+        .iter()
+        .map(|w| w.trim().to_lowercase())
+        .filter(|w| !w.is_empty())
+        .collect();
+    if words.is_empty() {
+        return Some(text.to_string());
+    }
+
+    // Whole words only, so filtering "ass" leaves "class" alone.
+    let mut out = String::with_capacity(text.len());
+    let mut word = String::new();
+    let flush = |word: &mut String, out: &mut String| -> Option<()> {
+        if word.is_empty() {
+            return Some(());
+        }
+        if words.contains(&word.to_lowercase()) {
+            if filter.mode == FilterMode::Reject {
+                return None;
+            }
+            out.extend(std::iter::repeat_n('*', word.chars().count()));
+        } else {
+            out.push_str(word);
+        }
+        word.clear();
+        Some(())
+    };
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut out)?;
+            out.push(c);
+        }
+    }
+    flush(&mut word, &mut out)?;
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(mode: FilterMode, words: &[&str]) -> ChatFilter {
+        ChatFilter {
+            words: words.iter().map(|w| w.to_string()).collect(),
+            mode,
+        }
+    }
+
+    #[test]
+    fn mask_replaces_whole_words_only() {
+        let f = filter(FilterMode::Mask, &["darn", " Heck "]);
+        assert_eq!(apply(&f, "Darn it, heck!").as_deref(), Some("**** it, ****!"));
+        assert_eq!(apply(&f, "darned hecking class").as_deref(), Some("darned hecking class"));
+        assert_eq!(apply(&f, "ünïcödé darn").as_deref(), Some("ünïcödé ****"));
+    }
+
+    #[test]
+    fn reject_refuses_any_match() {
+        let f = filter(FilterMode::Reject, &["darn"]);
+        assert_eq!(apply(&f, "oh DARN"), None);
+        assert_eq!(apply(&f, "darnit").as_deref(), Some("darnit"));
+    }
+
+    #[test]
+    fn empty_filter_passes_everything() {
+        let f = filter(FilterMode::Reject, &["", "  "]);
+        assert_eq!(apply(&f, "anything").as_deref(), Some("anything"));
+    }
+}
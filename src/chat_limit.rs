@@ -0,0 +1,134 @@
+//! Per-client flood protection for the chat.
+//!
+//! Each client address gets a token bucket: `burst` messages may be sent
+//! back to back, after which tokens refill at `per_minute`. A client also
+//! can't post the same text twice within the duplicate window. State lives
+//! in memory only; a restart simply gives everyone a fresh bucket.
+
+use crate::app::ChatLimits;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Buckets untouched for this long are full again and can be forgotten.
+const IDLE_FORGET: Duration = Duration::from_secs(60 * 60);
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    last_text: String,
+    last_sent_at: Instant,
+}
+
+lazy_static! {
+    static ref BUCKETS: Mutex<HashMap<IpAddr, Bucket>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rejected {
+    /// Out of tokens; the next one is due in this many seconds.
+    TooFast(u64),
+    /// Same text as the client's previous message.
+    Duplicate,
+}
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejected::TooFast(secs) => write!(
+                f,
+                "You're sending messages too fast. Try again in {} second{}.",
+                secs,
+                if *secs == 1 { "" } else { "s" }
+            ),
+            Rejected::Duplicate => write!(f, "You just sent that message."),
+        }
+    }
+}
+
+/// Take a token for `client` if it has one and `text` isn't a repeat.
+/// Rejected messages don't cost a token.
+pub fn check(client: IpAddr, text: &str, limits: &ChatLimits) -> Result<(), Rejected> {
+    let now = Instant::now();
+    let burst = limits.burst.max(1) as f64;
+    let per_sec = limits.per_minute.max(1) as f64 / 60.0;
+
+    let Ok(mut buckets) = BUCKETS.lock() else {
+        return Ok(());
+    };
+    if buckets.len() > 1024 {
+        buckets.retain(|_, b| now.duration_since(b.refilled_at) < IDLE_FORGET);
+    }
+    let bucket = buckets.entry(client).or_insert_with(|| Bucket {
+        tokens: burst,
+        refilled_at: now,
+        last_text: String::new(),
+        last_sent_at: now,
+    });
+
+    let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * per_sec).min(burst);
+    bucket.refilled_at = now;
+
+    let window = Duration::from_secs(limits.duplicate_window_secs);
+    if !bucket.last_text.is_empty()
+        && bucket.last_text == text
+        && now.duration_since(bucket.last_sent_at) < window
+    {
+        return Err(Rejected::Duplicate);
+    }
+    if bucket.tokens < 1.0 {
+        let wait = ((1.0 - bucket.tokens) / per_sec).ceil() as u64;
+        return Err(Rejected::TooFast(wait.max(1)));
+    }
+
+    bucket.tokens -= 1.0;
+    bucket.last_text = text.to_string();
+    bucket.last_sent_at = now;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> ChatLimits {
+        ChatLimits {
+            burst: 3,
+            per_minute: 1,
+            max_length: 100,
+            duplicate_window_secs: 60,
+        }
+    }
+
+    #[test]
+    fn burst_then_too_fast() {
+        let client: IpAddr = "192.0.2.10".parse().unwrap();
+        for i in 0..3 {
+            assert_eq!(check(client, &format!("m{}", i), &limits()), Ok(()));
+        }
+        match check(client, "m3", &limits()) {
+            Err(Rejected::TooFast(secs)) => assert!((1..=60).contains(&secs), "{}", secs),
+            other => panic!("{:?}", other),
+        }
+        // Each client has its own bucket.
+        assert_eq!(check("192.0.2.11".parse().unwrap(), "m3", &limits()), Ok(()));
+    }
+
+    #[test]
+    fn repeats_are_refused_without_costing_a_token() {
+        let client: IpAddr = "192.0.2.12".parse().unwrap();
+        assert_eq!(check(client, "hello", &limits()), Ok(()));
+        assert_eq!(check(client, "hello", &limits()), Err(Rejected::Duplicate));
+        assert_eq!(check(client, "hello", &limits()), Err(Rejected::Duplicate));
+        assert_eq!(check(client, "other", &limits()), Ok(()));
+        assert_eq!(check(client, "hello", &limits()), Ok(()));
+
+        let no_window = ChatLimits { duplicate_window_secs: 0, ..limits() };
+        let client: IpAddr = "192.0.2.13".parse().unwrap();
+        assert_eq!(check(client, "hello", &no_window), Ok(()));
+        assert_eq!(check(client, "hello", &no_window), Ok(()));
+    }
+}
@@ -0,0 +1,395 @@
+//! Server-side rendering of chat messages to HTML.
+//!
+//! Messages are stored as typed; the HTML is produced whenever they are
+//! sent to a browser, so changing the rules here re-renders old history
+//! too. Whatever is built is passed through ammonia before it leaves the
+//! server, and the client only ever inserts that sanitised output.
+//!
+//! The supported formatting is a small Markdown subset: `**bold**`,
+//! `*italics*` or `_italics_`, `` `code` `` and line breaks. Links to
+//! `http(s)` URLs on the local network are made clickable; anything
+//! pointing at the internet stays text, there is no route to it anyway.
+//!
+//! References to shared files become links with a size badge: either
+//! written as `[[Movies/foo bar.mkv]]` (spaces allowed), or as a bare path
+//! like `Movies/foo.mkv` when it names an existing file.
+//!
+//! Looking up file references costs a `stat` per candidate word, so the
+//! rendered history is cached per message and dropped whenever a file
+//! event says the share changed.
+
+use crate::app::{format_bytes, resolve_safe_path, share_url, ChatMessage};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Longest path looked up for a `[[...]]` reference.
+const MAX_REF_LEN: usize = 256;
+/// Messages kept rendered; the cache starts over beyond this.
+const MAX_CACHED: usize = 2000;
+
+lazy_static! {
+    /// Message id to the text it was rendered from and the HTML. The text
+    /// is kept so a reused id (after the history was cleared) is noticed.
+    static ref RENDERED: Mutex<HashMap<i64, (String, String)>> = Mutex::new(HashMap::new());
+}
+
+/// Fill in `html` for messages about to be sent to a browser.
+pub fn fill(messages: &mut [ChatMessage]) {
+    for m in messages {
+        let cached = RENDERED.lock().ok().and_then(|rendered| {
+            rendered
+                .get(&m.id)
+                .filter(|(text, _)| *text == m.text)
+                .map(|(_, html)| html.clone())
+        });
+        m.html = match cached {
+            Some(html) => html,
+            None => {
+                let html = render(&m.text);
+                if let Ok(mut rendered) = RENDERED.lock() {
+                    if rendered.len() >= MAX_CACHED {
+                        rendered.clear();
+                    }
+                    rendered.insert(m.id, (m.text.clone(), html.clone()));
+                }
+                html
+            }
+        };
+    }
+}
+
+/// Drop the rendered history: file links in it may have come or gone.
+pub fn forget_rendered() {
+    if let Ok(mut rendered) = RENDERED.lock() {
+        rendered.clear();
+    }
+}
+
+pub fn render(text: &str) -> String {
+    let base = crate::settings::share_root();
+    let mut html = String::with_capacity(text.len() * 2);
+    for (i, line) in text.lines().enumerate() {
+        if i > 0 {
+            html.push_str("<br>");
+        }
+        render_code_spans(base, line, &mut html);
+    }
+    sanitizer().clean(&html).to_string()
+}
+
+/// `` `code` `` is taken literally; everything between code spans is
+/// formatted.
+fn render_code_spans(base: &Path, line: &str, out: &mut String) {
+    let mut rest = line;
+    while let Some(start) = rest.find('`') {
+        let Some(len) = rest[start + 1..].find('`').filter(|&len| len > 0) else {
+            break;
+        };
+        render_refs(base, &rest[..start], out);
+        out.push_str("<code>");
+        escape_into(&rest[start + 1..start + 1 + len], out);
+        out.push_str("</code>");
+        rest = &rest[start + 1 + len + 1..];
+    }
+    render_refs(base, rest, out);
+}
+
+/// `[[path]]` file references, which may contain spaces and characters
+/// that would otherwise read as formatting.
+fn render_refs(base: &Path, text: &str, out: &mut String) {
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let inner = &rest[start + 2..];
+        let link = inner
+            .find("]]")
+            .filter(|&end| end <= MAX_REF_LEN)
+            .and_then(|end| file_link(base, &inner[..end]).map(|link| (end, link)));
+        match link {
+            Some((end, link)) => {
+                render_emphasis(base, &rest[..start], out);
+                out.push_str(&link);
+                rest = &inner[end + 2..];
+            }
+            None => {
+                render_emphasis(base, &rest[..start + 2], out);
+                rest = inner;
+            }
+        }
+    }
+    render_emphasis(base, rest, out);
+}
+
+/// `**bold**`, `*italics*` and `_italics_`. A delimiter only opens when
+/// followed by a non-space and only closes after one; underscores must
+/// also sit at word boundaries, so `holiday_2023_final.jpg` is left alone.
+fn render_emphasis(base: &Path, text: &str, out: &mut String) {
+    let mut plain_start = 0;
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let delim = ["**", "*", "_"].into_iter().find(|d| rest.starts_with(d));
+        let Some(delim) = delim.filter(|d| opens(text, i, d)) else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+            continue;
+        };
+        let inner_start = i + delim.len();
+        let Some(inner_len) = find_closer(text, inner_start, delim) else {
+            i = inner_start;
+            continue;
+        };
+        render_words(base, &text[plain_start..i], out);
+        let tag = if delim == "**" { "strong" } else { "em" };
+        out.push_str(&format!("<{}>", tag));
+        render_emphasis(base, &text[inner_start..inner_start + inner_len], out);
+        out.push_str(&format!("</{}>", tag));
+        i = inner_start + inner_len + delim.len();
+        plain_start = i;
+    }
+    render_words(base, &text[plain_start..], out);
+}
+
+fn opens(text: &str, at: usize, delim: &str) -> bool {
+    let next = text[at + delim.len()..].chars().next();
+    if next.is_none_or(char::is_whitespace) {
+        return false;
+    }
+    delim != "_" || !text[..at].chars().next_back().is_some_and(char::is_alphanumeric)
+}
+
+/// Length of the emphasised text starting at `from`, if `delim` is closed
+/// later on.
+fn find_closer(text: &str, from: usize, delim: &str) -> Option<usize> {
+    let mut search = from;
+    while let Some(pos) = text[search..].find(delim) {
+        let at = search + pos;
+        let before = text[..at].chars().next_back();
+        let after = text[at + delim.len()..].chars().next();
+        let closes = at > from
+            && before.is_some_and(|c| !c.is_whitespace())
+            && (delim != "_" || !after.is_some_and(char::is_alphanumeric))
+            // Neither half of a "**" closes a single "*".
+            && !(delim == "*" && (after == Some('*') || before == Some('*')));
+        if closes {
+            return Some(at - from);
+        }
+        search = at + delim.len();
+    }
+    None
+}
+
+/// Plain text, with any word that names a shared file or a LAN address
+/// turned into a link.
+fn render_words(base: &Path, text: &str, out: &mut String) {
+    let mut word_start = None;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        if !c.is_whitespace() {
+            word_start.get_or_insert(i);
+            continue;
+        }
+        if let Some(start) = word_start.take() {
+            render_word(base, &text[start..i], out);
+        }
+        if i < text.len() {
+            escape_into(c.encode_utf8(&mut [0; 4]), out);
+        }
+    }
+}
+
+fn render_word(base: &Path, word: &str, out: &mut String) {
+    // Punctuation around a path ("see Movies/foo.mkv!") isn't part of it.
+    let core = word.trim_start_matches(['(', '"', '\'']);
+    let core = core.trim_end_matches(['.', ',', '!', '?', ';', ':', ')', '"', '\'']);
+    let link = if core.starts_with("http://") || core.starts_with("https://") {
+        lan_link(core)
+    } else if core.contains('/') || core.contains('.') {
+        file_link(base, core)
+    } else {
+        None
+    };
+    match link {
+        Some(link) => {
+            let lead = word.find(core).unwrap_or(0);
+            escape_into(&word[..lead], out);
+            out.push_str(&link);
+            escape_into(&word[lead + core.len()..], out);
+        }
+        None => escape_into(word, out),
+    }
+}
+
+/// Link and size badge for `rel` if it is a file on the share.
+fn file_link(base: &Path, rel: &str) -> Option<String> {
+    let rel = rel.trim().trim_start_matches('/');
+    let rel = rel.strip_prefix("files/").unwrap_or(rel);
+    // Dot-entries are server-internal and not served anyway.
+    if rel.is_empty() || rel.split('/').any(|seg| seg.is_empty() || seg.starts_with('.')) {
+        return None;
+    }
+    let target = resolve_safe_path(base, rel).ok()?;
+    let meta = std::fs::metadata(&target).ok()?;
+    if !meta.is_file() {
+        return None;
+    }
+    let (dir, name) = rel.rsplit_once('/').unwrap_or(("", rel));
+    Some(format!(
+        "<a class=\"chat-file\" href=\"{}\">{}</a> <span class=\"chat-file-size\">{}</span>",
+        share_url("/files/", dir, name),
+        escape(rel),
+        format_bytes(meta.len()),
+    ))
+}
+
+/// Link for an http(s) URL whose host is on the local network.
+fn lan_link(url: &str) -> Option<String> {
+    let after_scheme = url.split_once("://")?.1;
+    let authority = after_scheme.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    let host = if let Some(v6) = host_port.strip_prefix('[') {
+        v6.split(']').next()?
+    } else {
+        host_port.split(':').next()?
+    };
+    if !is_lan_host(host) {
+        return None;
+    }
+    let url = escape(url);
+    Some(format!("<a class=\"chat-link\" href=\"{}\">{}</a>", url, url))
+}
+
+fn is_lan_host(host: &str) -> bool {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return match ip {
+            IpAddr::V4(v4) => v4.is_private() || v4.is_link_local() || v4.is_loopback(),
+            // fc00::/7 unique local and fe80::/10 link-local.
+            IpAddr::V6(v6) => {
+                let first = v6.segments()[0];
+                (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80 || v6.is_loopback()
+            }
+        };
+    }
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let valid = !host.is_empty()
+        && host.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+    valid
+        && (!host.contains('.')
+            || [".lan", ".local", ".home.arpa", ".internal"]
+                .iter()
+                .any(|suffix| host.ends_with(suffix)))
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    escape_into(text, &mut out);
+    out
+}
+
+fn escape_into(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// Only the markup this module produces survives.
+fn sanitizer() -> ammonia::Builder<'static> {
+    let mut b = ammonia::Builder::empty();
+    b.add_tags(["a", "span", "strong", "em", "code", "br"])
+        .add_tag_attributes("a", ["href"])
+        .add_allowed_classes("a", ["chat-file", "chat-link"])
+        .add_allowed_classes("span", ["chat-file-size"])
+        .add_url_schemes(["http", "https"])
+        .url_relative(ammonia::UrlRelative::PassThrough)
+        .link_rel(Some("external noopener noreferrer"));
+    b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markup_and_script_links_are_neutralised() {
+        let html = render("<script>alert(1)</script> <b>hi</b>");
+        assert!(!html.contains("<script") && !html.contains("<b>"), "{}", html);
+        assert!(html.contains("&lt;script&gt;"), "{}", html);
+
+        for text in [
+            "javascript:alert(1)",
+            "javascript://192.168.1.1/%0aalert(1)",
+            "[x](javascript:alert(1))",
+            "<a href=\"javascript:alert(1)\">x</a>",
+        ] {
+            let html = render(text);
+            assert!(!html.contains("<a"), "{} -> {}", text, html);
+        }
+
+        let html = render("http://192.168.4.1/\"onmouseover=\"alert(1)");
+        // The quote stays inside the attribute value.
+        assert!(html.contains("href=\"http://192.168.4.1/&quot;onmouseover=&quot;alert(1\""), "{}", html);
+    }
+
+    #[test]
+    fn emphasis_needs_word_boundaries_for_underscores() {
+        assert_eq!(render("holiday_2023_final.jpg"), "holiday_2023_final.jpg");
+        assert_eq!(render("snake_case_name"), "snake_case_name");
+        assert_eq!(render("an _important_ point"), "an <em>important</em> point");
+        assert_eq!(render("**bold** and *it*"), "<strong>bold</strong> and <em>it</em>");
+        assert_eq!(render("2 * 3 * 4"), "2 * 3 * 4");
+    }
+
+    #[test]
+    fn only_lan_urls_become_links() {
+        assert!(render("http://shareboxx.lan/files/").contains("<a class=\"chat-link\""));
+        assert!(render("https://192.168.4.1:8080/x").contains("<a class=\"chat-link\""));
+        assert!(!render("https://example.com/").contains("<a"));
+    }
+
+    #[test]
+    fn file_references_link_only_inside_the_share() {
+        let _lock = crate::settings::lock_for_tests();
+        let settings = crate::settings::init_for_tests();
+        let dir = settings.share_root.join("chat-refs");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("foo bar.mkv"), vec![0u8; 2048]).unwrap();
+        std::fs::write(dir.join("clip.mp4"), b"clip").unwrap();
+        let outside = settings.data_dir.join("outside.txt");
+        std::fs::write(&outside, b"not shared").unwrap();
+        std::os::unix::fs::symlink(&outside, dir.join("escape.txt")).unwrap();
+
+        assert_eq!(
+            render("look: [[chat-refs/foo bar.mkv]]"),
+            "look: <a class=\"chat-file\" href=\"/files/chat-refs/foo%20bar.mkv\" rel=\"external noopener noreferrer\">\
+             chat-refs/foo bar.mkv</a> <span class=\"chat-file-size\">2.0 KB</span>"
+        );
+        let html = render("see /files/chat-refs/clip.mp4!");
+        assert!(html.contains("href=\"/files/chat-refs/clip.mp4\""), "{}", html);
+        assert!(html.contains("<span class=\"chat-file-size\">4 B</span>!"), "{}", html);
+
+        let share = settings.share_root.to_string_lossy().to_string();
+        for text in [
+            "../outside.txt".to_string(),
+            "chat-refs/../../outside.txt".to_string(),
+            "[[../outside.txt]]".to_string(),
+            "chat-refs/escape.txt".to_string(),
+            outside.to_string_lossy().to_string(),
+            format!("{}/chat-refs/clip.mp4", share),
+            "/etc/passwd".to_string(),
+            "chat-refs/missing.mkv".to_string(),
+        ] {
+            let html = render(&text);
+            assert!(!html.contains("<a"), "{} -> {}", text, html);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_file(&outside).unwrap();
+    }
+}
@@ -1,9 +1,12 @@
+use crate::app::{ChatFilter, ChatLimits};
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
 
 const CONFIG_FILE: &str = "config.json";
 pub const DEFAULT_EXPIRATION_DAYS: u32 = 30;
+pub const DEFAULT_MAX_UPLOAD_SIZE: u64 = 10 * 1024 * 1024 * 1024;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
@@ -17,12 +20,218 @@ pub struct Config {
     pub admin_salt: String,
     #[serde(default = "default_chat_enabled")]
     pub chat_enabled: bool,
+    /// Remove EXIF/GPS and similar metadata from uploaded images before
+    /// they appear on the share.
+    #[serde(default)]
+    pub strip_metadata: bool,
+    /// Largest single upload accepted, in bytes.
+    #[serde(default = "default_max_upload_size")]
+    pub max_upload_size: u64,
+    /// Malware scanner run on every upload before it is published.
+    #[serde(default)]
+    pub scanner: ScannerConfig,
+    #[serde(default)]
+    pub chat_limits: ChatLimits,
+    #[serde(default)]
+    pub chat_filter: ChatFilter,
+    /// Which host names the box answers to, and where everything else is
+    /// sent.
+    #[serde(default)]
+    pub hostname: HostnameConfig,
+    /// How captive-portal probes from joining devices are answered.
+    #[serde(default)]
+    pub captive: CaptiveConfig,
+    /// Multicast DNS advertisement for machines outside the hotspot.
+    #[serde(default)]
+    pub mdns: MdnsConfig,
+}
+
+/// Requests for any host other than the canonical one or an alias are
+/// redirected, which is what makes phones on the hotspot land on the box.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostnameConfig {
+    /// Name in redirect targets; include the port if it isn't 80.
+    pub canonical_host: String,
+    /// Other names (or IP addresses) served without a redirect.
+    pub aliases: Vec<String>,
+    pub redirect: RedirectMode,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectMode {
+    /// Keep the path and query if they name something this server serves,
+    /// otherwise go to the front page.
+    #[default]
+    KeepPath,
+    /// Always redirect to the front page.
+    Root,
+    /// Serve every host name as is.
+    Off,
+}
+
+impl Default for HostnameConfig {
+    fn default() -> Self {
+        Self {
+            canonical_host: "shareboxx.lan".to_string(),
+            aliases: Vec::new(),
+            redirect: RedirectMode::KeepPath,
+        }
+    }
+}
+
+/// Paths whose meaning doesn't depend on the host that was asked for.
+const APP_PATH_PREFIXES: &[&str] = &[
+    "/files/", "/zip/", "/thumb/", "/stats", "/admin", "/api/", "/pkg/",
+];
+
+impl HostnameConfig {
+    /// Whether a request for `host` (as sent, possibly with a port) is
+    /// served directly. Loopback always is, for local maintenance.
+    pub fn accepts(&self, host: &str) -> bool {
+        let name = strip_port(host);
+        let matches = |candidate: &str| strip_port(candidate).eq_ignore_ascii_case(name);
+        self.redirect == RedirectMode::Off
+            || name == "localhost"
+            || name.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+            || matches(&self.canonical_host)
+            || self.aliases.iter().any(|a| matches(a))
+    }
+
+    /// Where to send a request for a host that isn't accepted.
+    pub fn redirect_target(&self, path_and_query: &str) -> String {
+        let path = path_and_query.split('?').next().unwrap_or("");
+        let keep = self.redirect == RedirectMode::KeepPath
+            && APP_PATH_PREFIXES.iter().any(|p| path.starts_with(p));
+        format!(
+            "http://{}{}",
+            self.canonical_host,
+            if keep { path_and_query } else { "/" }
+        )
+    }
+}
+
+/// "host:port" -> "host", leaving bracketed IPv6 addresses intact.
+fn strip_port(host: &str) -> &str {
+    if let Some(end) = host.find(']') {
+        return host[..=end].trim_start_matches('[').trim_end_matches(']');
+    }
+    match host.rsplit_once(':') {
+        Some((name, port)) if !name.contains(':') && port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptiveConfig {
+    pub mode: CaptiveMode,
+    /// How long a client that clicked through the landing page is told it
+    /// is online, in dismissable mode.
+    pub dismiss_hours: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptiveMode {
+    /// Keep the sign-in sheet open on the share.
+    #[default]
+    Portal,
+    /// Show a landing page; after "Continue" the device is told it's online.
+    Dismissable,
+    /// Don't answer probes specially; they get the normal host redirect.
+    Off,
+}
+
+impl Default for CaptiveConfig {
+    fn default() -> Self {
+        Self {
+            mode: CaptiveMode::Portal,
+            dismiss_hours: 24,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MdnsConfig {
+    pub enabled: bool,
+    /// Name shown by service browsers.
+    pub instance_name: String,
+    /// Advertised as `<host_name>.local`.
+    pub host_name: String,
+    /// Interface names to advertise on; empty means all of them.
+    pub interfaces: Vec<String>,
+    /// Also advertise on loopback, for testing on a single machine.
+    pub loopback: bool,
+}
+
+impl Default for MdnsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            instance_name: "ShareBoxx".to_string(),
+            host_name: "shareboxx".to_string(),
+            interfaces: Vec::new(),
+            loopback: false,
+        }
+    }
+}
+
+impl MdnsConfig {
+    /// The advertised host name, e.g. "shareboxx.local".
+    pub fn local_host(&self) -> String {
+        format!("{}.local", self.host_name.trim_end_matches(".local"))
+    }
+
+    /// Whether `host` (possibly with a port) is the advertised name, which
+    /// has to be served since mDNS clients can't follow a redirect to a
+    /// name only dnsmasq knows.
+    pub fn answers_for(&self, host: &str) -> bool {
+        self.enabled
+            && strip_port(host)
+                .trim_end_matches('.')
+                .eq_ignore_ascii_case(&self.local_host())
+    }
+}
+
+/// How uploads are scanned while they sit in quarantine.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScannerConfig {
+    /// No scanning; uploads are published immediately.
+    #[default]
+    None,
+    /// A clamd daemon, reached over a unix socket path or "host:port",
+    /// using the INSTREAM protocol.
+    Clamd { address: String },
+    /// An arbitrary program run with the file path as its last argument.
+    /// Exit code 0 means clean, 1 means infected, anything else is an error.
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// Built-in scanner that only detects the EICAR test signature, for
+    /// exercising the quarantine flow without a real engine.
+    Fake,
+}
+
+impl ScannerConfig {
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self, ScannerConfig::None)
+    }
 }
 
 fn default_expiration_days() -> u32 {
     DEFAULT_EXPIRATION_DAYS
 }
 
+fn default_max_upload_size() -> u64 {
+    DEFAULT_MAX_UPLOAD_SIZE
+}
+
 fn default_chat_enabled() -> bool {
     true
 }
@@ -35,15 +244,24 @@ impl Default for Config {
             admin_password_hash: String::new(),
             admin_salt: String::new(),
             chat_enabled: true,
+            strip_metadata: false,
+            max_upload_size: DEFAULT_MAX_UPLOAD_SIZE,
+            scanner: ScannerConfig::None,
+            chat_limits: ChatLimits::default(),
+            chat_filter: ChatFilter::default(),
+            hostname: HostnameConfig::default(),
+            captive: CaptiveConfig::default(),
+            mdns: MdnsConfig::default(),
         }
     }
 }
 
 fn config_path() -> PathBuf {
-    PathBuf::from(CONFIG_FILE)
+    crate::settings::data_path(CONFIG_FILE)
 }
 
-/// Read config.json from CWD; return defaults if missing or unparseable.
+/// Read config.json from the data directory; return defaults if missing or
+/// unparseable.
 pub fn load() -> Config {
     match std::fs::read_to_string(config_path()) {
         Ok(s) => serde_json::from_str(&s).unwrap_or_default(),
@@ -51,44 +269,110 @@ pub fn load() -> Config {
     }
 }
 
-/// Persist `cfg` to CWD/config.json (atomic via tmp+rename).
+lazy_static! {
+    static ref CACHED: RwLock<Option<Arc<Config>>> = RwLock::new(None);
+}
+
+/// The config as last loaded or saved, for code that runs on every
+/// request. Unlike `load` it doesn't see edits made to config.json by hand
+/// until the server restarts or the config is saved again.
+pub fn cached() -> Arc<Config> {
+    if let Ok(cached) = CACHED.read()
+        && let Some(cfg) = &*cached
+    {
+        return cfg.clone();
+    }
+    let cfg = Arc::new(load());
+    if let Ok(mut cached) = CACHED.write() {
+        *cached = Some(cfg.clone());
+    }
+    cfg
+}
+
+/// Persist `cfg` to config.json in the data directory (atomic via
+/// tmp+rename).
 pub fn save(cfg: &Config) -> std::io::Result<()> {
     let data = serde_json::to_string_pretty(cfg)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        .map_err(std::io::Error::other)?;
     let tmp = config_path().with_extension("json.tmp");
     std::fs::write(&tmp, &data)?;
     std::fs::rename(&tmp, config_path())?;
+    if let Ok(mut cached) = CACHED.write() {
+        *cached = Some(Arc::new(cfg.clone()));
+    }
     Ok(())
 }
 
 impl Config {
+    /// Feature flags the UI needs before anything else: whether chat is
+    /// enabled and whether an admin password is set.
+    pub fn runtime_settings(&self) -> (bool, bool) {
+        (self.chat_enabled, self.is_admin_configured())
+    }
+
     pub fn is_admin_configured(&self) -> bool {
-        !self.admin_password_hash.is_empty() && !self.admin_salt.is_empty()
+        !self.admin_password_hash.is_empty()
+            && (!crate::password::is_legacy(&self.admin_password_hash) || !self.admin_salt.is_empty())
     }
 
     pub fn verify_password(&self, password: &str) -> bool {
-        if !self.is_admin_configured() {
-            return false;
+        self.is_admin_configured()
+            && crate::password::verify(&self.admin_password_hash, &self.admin_salt, password)
+    }
+
+    /// Whether the stored hash is outdated and should be replaced with
+    /// `set_password` the next time the password is known.
+    pub fn password_needs_upgrade(&self) -> bool {
+        crate::password::needs_upgrade(&self.admin_password_hash)
+    }
+
+    /// Store a fresh hash of `password`. The salt is part of the hash, so
+    /// the separate `admin_salt` field is cleared.
+    pub fn set_password(&mut self, password: &str) -> Result<(), String> {
+        self.admin_password_hash = crate::password::hash(password)?;
+        self.admin_salt.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hostname(redirect: RedirectMode) -> HostnameConfig {
+        HostnameConfig {
+            canonical_host: "shareboxx.lan:8080".to_string(),
+            aliases: vec!["box.local".to_string(), "10.0.0.1".to_string()],
+            redirect,
+        }
+    }
+
+    #[test]
+    fn accepts_canonical_aliases_and_loopback() {
+        let h = hostname(RedirectMode::KeepPath);
+        for host in ["shareboxx.lan", "ShareBoxx.LAN:8080", "shareboxx.lan:80", "box.local", "box.local:3000", "10.0.0.1:8080"] {
+            assert!(h.accepts(host), "{}", host);
+        }
+        // Loopback is exempt for maintenance; other addresses are not.
+        for host in ["localhost:3000", "127.0.0.1", "127.0.0.1:3000", "[::1]:8080"] {
+            assert!(h.accepts(host), "{}", host);
+        }
+        for host in ["192.168.4.1", "10.0.0.2:8080", "[fe80::1]", "captive.apple.com", "shareboxx.lan.evil", "box"] {
+            assert!(!h.accepts(host), "{}", host);
         }
-        let Ok(salt_bytes) = hex::decode(&self.admin_salt) else {
-            return false;
-        };
-        let mut hasher = Sha256::new();
-        hasher.update(&salt_bytes);
-        hasher.update(password.as_bytes());
-        let computed = hex::encode(hasher.finalize());
-        // Constant-time-ish comparison; the inputs are fixed length hex so
-        // a normal eq is acceptable here, but compare bytes to avoid
-        // short-circuiting on the first mismatched character.
-        let a = computed.as_bytes();
-        let b = self.admin_password_hash.as_bytes();
-        if a.len() != b.len() {
-            return false;
+        assert!(hostname(RedirectMode::Off).accepts("anything.example"));
+    }
+
+    #[test]
+    fn redirect_keeps_only_app_paths() {
+        let h = hostname(RedirectMode::KeepPath);
+        for path in ["/files/a%20b.txt?download=1", "/zip/Movies", "/thumb/x.jpg", "/stats", "/admin", "/api/upload?id=1", "/pkg/app.wasm"] {
+            assert_eq!(h.redirect_target(path), format!("http://shareboxx.lan:8080{}", path));
         }
-        let mut diff: u8 = 0;
-        for i in 0..a.len() {
-            diff |= a[i] ^ b[i];
+        for path in ["/", "/hotspot-detect.html", "/index.php?page=2", "/filesystem", "/favicon.ico"] {
+            assert_eq!(h.redirect_target(path), "http://shareboxx.lan:8080/", "{}", path);
         }
-        diff == 0
+        let root = hostname(RedirectMode::Root);
+        assert_eq!(root.redirect_target("/files/a.txt?x=1"), "http://shareboxx.lan:8080/");
     }
 }
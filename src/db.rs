@@ -1,9 +1,55 @@
+use crate::app::{ChatMessage, DEFAULT_CHANNEL};
 use rusqlite::{params, Connection};
 
 const DB_FILE: &str = "uploads.db";
 
+/// Open the database. The schema is set up once at startup by `migrate`.
 pub fn open() -> rusqlite::Result<Connection> {
-    let conn = Connection::open(DB_FILE)?;
+    Connection::open(crate::settings::data_path(DB_FILE))
+}
+
+/// Schema steps, applied in order. `PRAGMA user_version` holds how many
+/// have run. Databases from before the steps existed already carry parts
+/// of the schema, so the first ones check before they add anything.
+const MIGRATIONS: &[fn(&Connection) -> rusqlite::Result<()>] = &[
+    schema_uploads,
+    schema_partial_uploads,
+    schema_scans,
+    schema_chat,
+    schema_admin,
+    schema_file_index,
+    schema_meta,
+];
+
+/// Bring the schema up to date. Run once at startup, before anything else
+/// opens the database.
+pub fn migrate() -> rusqlite::Result<()> {
+    migrate_conn(&mut open()?)
+}
+
+fn migrate_conn(conn: &mut Connection) -> rusqlite::Result<()> {
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    let user_version: i64 = tx.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+    // Until the `meta` table existed, `user_version` only ever meant "the
+    // watcher has reconciled this database", not a schema version.
+    let legacy = !table_exists(&tx, "meta")?;
+    let done = if legacy { 0 } else { user_version.max(0) as usize };
+    for step in MIGRATIONS.iter().skip(done) {
+        step(&tx)?;
+    }
+    if legacy && user_version >= 1 {
+        set_meta(&tx, META_RECONCILED, "1")?;
+    }
+    tx.execute_batch(&format!("PRAGMA user_version = {}", MIGRATIONS.len()))?;
+    tx.commit()
+}
+
+fn table_exists(conn: &Connection, name: &str) -> rusqlite::Result<bool> {
+    conn.prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")?
+        .exists(params![name])
+}
+
+fn schema_uploads(conn: &Connection) -> rusqlite::Result<()> {
     conn.execute(
         "CREATE TABLE IF NOT EXISTS uploads (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -12,20 +58,236 @@ pub fn open() -> rusqlite::Result<Connection> {
         )",
         [],
     )?;
-    Ok(conn)
+    // Content hash column, added after the fact; older databases get it
+    // bolted on here.
+    let has_hash: bool = conn
+        .prepare("SELECT 1 FROM pragma_table_info('uploads') WHERE name = 'sha256'")?
+        .exists([])?;
+    if !has_hash {
+        conn.execute("ALTER TABLE uploads ADD COLUMN sha256 TEXT", [])?;
+    }
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS uploads_sha256 ON uploads (sha256)",
+        [],
+    )?;
+    // Approved files used to be dropped from the table; they are now kept
+    // and pinned so the watcher doesn't mistake them for new arrivals.
+    let has_pinned: bool = conn
+        .prepare("SELECT 1 FROM pragma_table_info('uploads') WHERE name = 'pinned'")?
+        .exists([])?;
+    if !has_pinned {
+        conn.execute("ALTER TABLE uploads ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0", [])?;
+    }
+    Ok(())
+}
+
+fn schema_partial_uploads(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS partial_uploads (
+            id TEXT PRIMARY KEY,
+            upload_path TEXT NOT NULL,
+            file_name TEXT NOT NULL,
+            total_size INTEGER NOT NULL,
+            received INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    let has_result: bool = conn
+        .prepare("SELECT 1 FROM pragma_table_info('partial_uploads') WHERE name = 'result'")?
+        .exists([])?;
+    if !has_result {
+        conn.execute("ALTER TABLE partial_uploads ADD COLUMN result TEXT", [])?;
+    }
+    Ok(())
+}
+
+fn schema_scans(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS scans (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            quarantine_name TEXT NOT NULL UNIQUE,
+            upload_path TEXT NOT NULL,
+            file_name TEXT NOT NULL,
+            sha256 TEXT,
+            status TEXT NOT NULL,
+            detail TEXT NOT NULL DEFAULT '',
+            created_at INTEGER NOT NULL,
+            scanned_at INTEGER
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn schema_chat(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS messages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            body TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    // Sender address, kept so the admin can mute a client rather than a
+    // nickname. Never sent to other chat users.
+    let has_client: bool = conn
+        .prepare("SELECT 1 FROM pragma_table_info('messages') WHERE name = 'client'")?
+        .exists([])?;
+    if !has_client {
+        conn.execute("ALTER TABLE messages ADD COLUMN client TEXT NOT NULL DEFAULT ''", [])?;
+    }
+    // Chat channels. Messages from before channels existed land in the
+    // default one.
+    let has_channel: bool = conn
+        .prepare("SELECT 1 FROM pragma_table_info('messages') WHERE name = 'channel'")?
+        .exists([])?;
+    if !has_channel {
+        conn.execute(
+            &format!(
+                "ALTER TABLE messages ADD COLUMN channel TEXT NOT NULL DEFAULT '{}'",
+                DEFAULT_CHANNEL
+            ),
+            [],
+        )?;
+    }
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS messages_channel ON messages (channel, id)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS channels (
+            name TEXT PRIMARY KEY,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO channels (name, created_at) VALUES (?1, 0)",
+        params![DEFAULT_CHANNEL],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chat_mutes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            kind TEXT NOT NULL,
+            value TEXT NOT NULL,
+            until INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn schema_admin(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS login_lockouts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            at INTEGER NOT NULL,
+            client TEXT NOT NULL,
+            failures INTEGER NOT NULL,
+            until INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS admin_sessions (
+            token_hash TEXT PRIMARY KEY,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    // Sessions of named accounts; NULL is the config.json admin.
+    let has_account: bool = conn
+        .prepare("SELECT 1 FROM pragma_table_info('admin_sessions') WHERE name = 'account_id'")?
+        .exists([])?;
+    if !has_account {
+        conn.execute("ALTER TABLE admin_sessions ADD COLUMN account_id INTEGER", [])?;
+    }
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS accounts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            role TEXT NOT NULL,
+            password_hash TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn schema_file_index(conn: &Connection) -> rusqlite::Result<()> {
+    // Filename search. Path separators, dots and underscores all split
+    // tokens, so "holiday" finds "Photos/holiday_2023.jpg".
+    conn.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS file_index USING fts5(
+            rel_path,
+            size UNINDEXED,
+            tokenize = 'unicode61 remove_diacritics 2'
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Small flags that belong to the database rather than the config.
+fn schema_meta(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+const META_RECONCILED: &str = "reconciled";
+
+fn set_meta(conn: &Connection, key: &str, value: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO meta (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![key, value],
+    )?;
+    Ok(())
 }
 
-pub fn record_upload(conn: &Connection, rel_path: &str, ts: u64) -> rusqlite::Result<()> {
+pub fn record_upload(
+    conn: &Connection,
+    rel_path: &str,
+    ts: u64,
+    sha256: Option<&str>,
+) -> rusqlite::Result<()> {
     conn.execute(
-        "INSERT INTO uploads (rel_path, uploaded_at) VALUES (?1, ?2)
-         ON CONFLICT(rel_path) DO UPDATE SET uploaded_at = excluded.uploaded_at",
-        params![rel_path, ts as i64],
+        "INSERT INTO uploads (rel_path, uploaded_at, sha256) VALUES (?1, ?2, ?3)
+         ON CONFLICT(rel_path) DO UPDATE SET uploaded_at = excluded.uploaded_at, sha256 = excluded.sha256",
+        params![rel_path, ts as i64, sha256],
     )?;
     Ok(())
 }
 
+/// Every tracked path whose content hashes to `sha256`, oldest first.
+pub fn find_by_hash(conn: &Connection, sha256: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT rel_path FROM uploads WHERE sha256 = ?1 ORDER BY uploaded_at ASC",
+    )?;
+    let rows = stmt.query_map(params![sha256], |r| r.get::<_, String>(0))?;
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+/// Uploads subject to expiration (i.e. not approved), oldest first.
 pub fn list_tracked(conn: &Connection) -> rusqlite::Result<Vec<(i64, String, u64)>> {
-    let mut stmt = conn.prepare("SELECT id, rel_path, uploaded_at FROM uploads ORDER BY uploaded_at ASC")?;
+    let mut stmt = conn.prepare(
+        "SELECT id, rel_path, uploaded_at FROM uploads WHERE pinned = 0 ORDER BY uploaded_at ASC",
+    )?;
     let rows = stmt.query_map([], |r| {
         Ok((
             r.get::<_, i64>(0)?,
@@ -45,6 +307,57 @@ pub fn delete_by_id(conn: &Connection, id: i64) -> rusqlite::Result<()> {
     Ok(())
 }
 
+/// Exempt an upload from expiration.
+pub fn pin_upload(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute("UPDATE uploads SET pinned = 1 WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+/// Start tracking a file found on disk, unless it already has a row.
+pub fn track_existing(conn: &Connection, rel_path: &str, ts: u64, pinned: bool) -> rusqlite::Result<bool> {
+    let n = conn.execute(
+        "INSERT OR IGNORE INTO uploads (rel_path, uploaded_at, pinned) VALUES (?1, ?2, ?3)",
+        params![rel_path, ts as i64, pinned],
+    )?;
+    Ok(n > 0)
+}
+
+/// Every path with a row, pinned or not.
+pub fn tracked_paths(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT rel_path FROM uploads")?;
+    let rows = stmt.query_map([], |r| r.get::<_, String>(0))?;
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+/// Forget everything below directory `dir_rel` (uploads rows and search
+/// index entries), after the directory itself disappeared.
+pub fn forget_under(conn: &Connection, dir_rel: &str) -> rusqlite::Result<()> {
+    let prefix = format!("{}/", dir_rel.trim_end_matches('/'));
+    conn.execute(
+        "DELETE FROM uploads WHERE substr(rel_path, 1, length(?1)) = ?1",
+        params![prefix],
+    )?;
+    conn.execute(
+        "DELETE FROM file_index WHERE substr(rel_path, 1, length(?1)) = ?1",
+        params![prefix],
+    )?;
+    Ok(())
+}
+
+/// Whether the share has been reconciled against this database before.
+pub fn reconciled_before(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.prepare("SELECT 1 FROM meta WHERE key = ?1")?
+        .exists(params![META_RECONCILED])
+}
+
+pub fn mark_reconciled(conn: &Connection) -> rusqlite::Result<()> {
+    set_meta(conn, META_RECONCILED, "1")
+}
+
 pub fn delete_by_path(conn: &Connection, rel_path: &str) -> rusqlite::Result<()> {
     conn.execute("DELETE FROM uploads WHERE rel_path = ?1", params![rel_path])?;
     Ok(())
@@ -57,3 +370,733 @@ pub fn update_path(conn: &Connection, old: &str, new: &str) -> rusqlite::Result<
     )?;
     Ok(())
 }
+
+/// Replace the whole filename index with `files` (rel_path, size).
+pub fn rebuild_file_index(conn: &mut Connection, files: &[(String, u64)]) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM file_index", [])?;
+    {
+        let mut stmt = tx.prepare("INSERT INTO file_index (rel_path, size) VALUES (?1, ?2)")?;
+        for (rel_path, size) in files {
+            stmt.execute(params![rel_path, *size as i64])?;
+        }
+    }
+    tx.commit()
+}
+
+pub fn index_file(conn: &Connection, rel_path: &str, size: u64) -> rusqlite::Result<()> {
+    unindex_file(conn, rel_path)?;
+    conn.execute(
+        "INSERT INTO file_index (rel_path, size) VALUES (?1, ?2)",
+        params![rel_path, size as i64],
+    )?;
+    Ok(())
+}
+
+pub fn unindex_file(conn: &Connection, rel_path: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM file_index WHERE rel_path = ?1", params![rel_path])?;
+    Ok(())
+}
+
+/// Turn free-form user input into an FTS5 query: every word must match as
+/// a prefix. Quoting each word keeps FTS5 operators in the input inert.
+fn fts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = input
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| format!("\"{}\"*", w))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Indexed files matching `query`, best matches first, as (rel_path, size).
+pub fn search_file_index(
+    conn: &Connection,
+    query: &str,
+    limit: u32,
+) -> rusqlite::Result<Vec<(String, u64)>> {
+    let Some(q) = fts_query(query) else {
+        return Ok(Vec::new());
+    };
+    let mut stmt = conn.prepare(
+        "SELECT rel_path, size FROM file_index WHERE file_index MATCH ?1 ORDER BY rank LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![q, limit], |r| {
+        Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)? as u64))
+    })?;
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+/// An in-progress resumable upload. `received` is the last acknowledged byte
+/// offset; the partial file on disk is authoritative if the two disagree.
+#[derive(Clone, Debug)]
+pub struct PartialUpload {
+    pub id: String,
+    pub upload_path: String,
+    pub file_name: String,
+    pub total_size: u64,
+    pub received: u64,
+    pub updated_at: u64,
+    /// Set once the upload completed: the JSON answer to the final chunk,
+    /// kept for a while in case that answer got lost.
+    pub result: Option<String>,
+}
+
+pub fn create_partial(
+    conn: &Connection,
+    id: &str,
+    upload_path: &str,
+    file_name: &str,
+    total_size: u64,
+    ts: u64,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO partial_uploads (id, upload_path, file_name, total_size, received, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, 0, ?5, ?5)",
+        params![id, upload_path, file_name, total_size as i64, ts as i64],
+    )?;
+    Ok(())
+}
+
+pub fn get_partial(conn: &Connection, id: &str) -> rusqlite::Result<Option<PartialUpload>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, upload_path, file_name, total_size, received, updated_at, result
+         FROM partial_uploads WHERE id = ?1",
+    )?;
+    let mut rows = stmt.query_map(params![id], partial_from_row)?;
+    rows.next().transpose()
+}
+
+pub fn update_partial_received(
+    conn: &Connection,
+    id: &str,
+    received: u64,
+    ts: u64,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE partial_uploads SET received = ?1, updated_at = ?2 WHERE id = ?3",
+        params![received as i64, ts as i64, id],
+    )?;
+    Ok(())
+}
+
+/// Mark a partial upload as completed with `result`.
+pub fn complete_partial(conn: &Connection, id: &str, result: &str, ts: u64) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE partial_uploads SET received = total_size, result = ?1, updated_at = ?2 WHERE id = ?3",
+        params![result, ts as i64, id],
+    )?;
+    Ok(())
+}
+
+pub fn delete_partial(conn: &Connection, id: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM partial_uploads WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+/// Partial uploads that have not received a byte since `cutoff`, and
+/// completed ones that finished before `completed_cutoff`.
+pub fn list_stale_partials(
+    conn: &Connection,
+    cutoff: u64,
+    completed_cutoff: u64,
+) -> rusqlite::Result<Vec<PartialUpload>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, upload_path, file_name, total_size, received, updated_at, result
+         FROM partial_uploads
+         WHERE (result IS NULL AND updated_at < ?1) OR (result IS NOT NULL AND updated_at < ?2)",
+    )?;
+    let rows = stmt.query_map(params![cutoff as i64, completed_cutoff as i64], partial_from_row)?;
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+/// Bytes the partial uploads have announced but not sent yet: disk space
+/// they will still take.
+pub fn partial_bytes_outstanding(conn: &Connection) -> rusqlite::Result<u64> {
+    conn.query_row(
+        "SELECT COALESCE(SUM(MAX(total_size - received, 0)), 0) FROM partial_uploads",
+        [],
+        |r| r.get::<_, i64>(0),
+    )
+    .map(|n| n as u64)
+}
+
+fn partial_from_row(r: &rusqlite::Row<'_>) -> rusqlite::Result<PartialUpload> {
+    Ok(PartialUpload {
+        id: r.get(0)?,
+        upload_path: r.get(1)?,
+        file_name: r.get(2)?,
+        total_size: r.get::<_, i64>(3)? as u64,
+        received: r.get::<_, i64>(4)? as u64,
+        updated_at: r.get::<_, i64>(5)? as u64,
+        result: r.get(6)?,
+    })
+}
+
+pub const SCAN_PENDING: &str = "pending";
+pub const SCAN_CLEAN: &str = "clean";
+pub const SCAN_INFECTED: &str = "infected";
+pub const SCAN_ERROR: &str = "error";
+
+/// An upload held in quarantine. Rows stay after a clean verdict so the
+/// scan history can be audited; `status` tells them apart.
+#[derive(Clone, Debug)]
+pub struct ScanRecord {
+    pub id: i64,
+    pub quarantine_name: String,
+    pub upload_path: String,
+    pub file_name: String,
+    pub sha256: Option<String>,
+    pub status: String,
+    pub detail: String,
+    pub created_at: u64,
+}
+
+pub fn create_scan(
+    conn: &Connection,
+    quarantine_name: &str,
+    upload_path: &str,
+    file_name: &str,
+    sha256: Option<&str>,
+    ts: u64,
+) -> rusqlite::Result<i64> {
+    conn.execute(
+        "INSERT INTO scans (quarantine_name, upload_path, file_name, sha256, status, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![quarantine_name, upload_path, file_name, sha256, SCAN_PENDING, ts as i64],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn get_scan(conn: &Connection, id: i64) -> rusqlite::Result<Option<ScanRecord>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, quarantine_name, upload_path, file_name, sha256, status, detail, created_at
+         FROM scans WHERE id = ?1",
+    )?;
+    let mut rows = stmt.query_map(params![id], scan_from_row)?;
+    rows.next().transpose()
+}
+
+pub fn set_scan_status(
+    conn: &Connection,
+    id: i64,
+    status: &str,
+    detail: &str,
+    ts: u64,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE scans SET status = ?1, detail = ?2, scanned_at = ?3 WHERE id = ?4",
+        params![status, detail, ts as i64, id],
+    )?;
+    Ok(())
+}
+
+/// Scans with the given status, oldest first.
+pub fn list_scans(conn: &Connection, status: &str) -> rusqlite::Result<Vec<ScanRecord>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, quarantine_name, upload_path, file_name, sha256, status, detail, created_at
+         FROM scans WHERE status = ?1 ORDER BY created_at ASC",
+    )?;
+    let rows = stmt.query_map(params![status], scan_from_row)?;
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+pub fn delete_scan(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM scans WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+fn scan_from_row(r: &rusqlite::Row<'_>) -> rusqlite::Result<ScanRecord> {
+    Ok(ScanRecord {
+        id: r.get(0)?,
+        quarantine_name: r.get(1)?,
+        upload_path: r.get(2)?,
+        file_name: r.get(3)?,
+        sha256: r.get(4)?,
+        status: r.get(5)?,
+        detail: r.get(6)?,
+        created_at: r.get::<_, i64>(7)? as u64,
+    })
+}
+
+const LEGACY_CHAT_FILE: &str = "chat.json";
+
+pub fn insert_message(
+    conn: &Connection,
+    channel: &str,
+    name: &str,
+    body: &str,
+    client: &str,
+    ts: u64,
+) -> rusqlite::Result<i64> {
+    conn.execute(
+        "INSERT INTO messages (channel, name, body, client, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![channel, name, body, client, ts as i64],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Up to `limit` messages in `channel` older than `before_id` (or the
+/// newest ones when `None`), returned oldest first.
+pub fn messages_before(
+    conn: &Connection,
+    channel: &str,
+    before_id: Option<i64>,
+    limit: u32,
+) -> rusqlite::Result<Vec<ChatMessage>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, body, created_at, channel FROM messages
+         WHERE channel = ?1 AND (?2 IS NULL OR id < ?2) ORDER BY id DESC LIMIT ?3",
+    )?;
+    let rows = stmt.query_map(params![channel, before_id, limit], message_from_row)?;
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    out.reverse();
+    Ok(out)
+}
+
+/// The newest `limit` messages in any channel newer than `after_id`,
+/// returned oldest first. Used to catch up a reconnecting SSE client.
+pub fn messages_after(conn: &Connection, after_id: i64, limit: u32) -> rusqlite::Result<Vec<ChatMessage>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, body, created_at, channel FROM messages WHERE id > ?1 ORDER BY id DESC LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![after_id, limit], message_from_row)?;
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    out.reverse();
+    Ok(out)
+}
+
+/// Id of the newest message in any channel; 0 when there are none.
+pub fn last_message_id(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.query_row("SELECT COALESCE(MAX(id), 0) FROM messages", [], |r| r.get(0))
+}
+
+fn message_from_row(r: &rusqlite::Row<'_>) -> rusqlite::Result<ChatMessage> {
+    Ok(ChatMessage {
+        id: r.get(0)?,
+        name: r.get(1)?,
+        text: r.get(2)?,
+        timestamp: r.get::<_, i64>(3)? as u64,
+        channel: r.get(4)?,
+        html: String::new(),
+    })
+}
+
+/// The newest `limit` messages with their sender address, newest first,
+/// for the admin's moderation list.
+pub fn recent_messages_with_client(
+    conn: &Connection,
+    limit: u32,
+) -> rusqlite::Result<Vec<(ChatMessage, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, body, created_at, channel, client FROM messages ORDER BY id DESC LIMIT ?1",
+    )?;
+    let rows = stmt.query_map(params![limit], |r| Ok((message_from_row(r)?, r.get::<_, String>(5)?)))?;
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+/// Returns whether a message with that id existed.
+pub fn delete_message(conn: &Connection, id: i64) -> rusqlite::Result<bool> {
+    Ok(conn.execute("DELETE FROM messages WHERE id = ?1", params![id])? > 0)
+}
+
+/// Channel names, the default one first and the rest alphabetically.
+pub fn list_channels(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM channels ORDER BY name = ?1 DESC, name ASC",
+    )?;
+    let rows = stmt.query_map(params![DEFAULT_CHANNEL], |r| r.get::<_, String>(0))?;
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+pub fn channel_exists(conn: &Connection, name: &str) -> rusqlite::Result<bool> {
+    conn.prepare("SELECT 1 FROM channels WHERE name = ?1")?
+        .exists(params![name])
+}
+
+/// Returns false if a channel with that name already exists.
+pub fn create_channel(conn: &Connection, name: &str, ts: u64) -> rusqlite::Result<bool> {
+    Ok(conn.execute(
+        "INSERT OR IGNORE INTO channels (name, created_at) VALUES (?1, ?2)",
+        params![name, ts as i64],
+    )? > 0)
+}
+
+/// Remove a channel together with its history.
+pub fn delete_channel(conn: &mut Connection, name: &str) -> rusqlite::Result<bool> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM messages WHERE channel = ?1", params![name])?;
+    let removed = tx.execute("DELETE FROM channels WHERE name = ?1", params![name])? > 0;
+    tx.commit()?;
+    Ok(removed)
+}
+
+pub const MUTE_NAME: &str = "name";
+pub const MUTE_CLIENT: &str = "client";
+
+/// Silence a nickname (compared case-insensitively) or a client address
+/// until `until`.
+pub fn add_mute(conn: &Connection, kind: &str, value: &str, until: u64) -> rusqlite::Result<i64> {
+    let value = if kind == MUTE_NAME { value.to_lowercase() } else { value.to_string() };
+    conn.execute(
+        "INSERT INTO chat_mutes (kind, value, until) VALUES (?1, ?2, ?3)",
+        params![kind, value, until as i64],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn remove_mute(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM chat_mutes WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+/// Mutes still in force: (id, kind, value, until_secs). Expired ones are
+/// dropped on the way.
+pub fn active_mutes(conn: &Connection, now: u64) -> rusqlite::Result<Vec<(i64, String, String, u64)>> {
+    conn.execute("DELETE FROM chat_mutes WHERE until <= ?1", params![now as i64])?;
+    let mut stmt = conn.prepare("SELECT id, kind, value, until FROM chat_mutes ORDER BY until ASC")?;
+    let rows = stmt.query_map([], |r| {
+        Ok((
+            r.get::<_, i64>(0)?,
+            r.get::<_, String>(1)?,
+            r.get::<_, String>(2)?,
+            r.get::<_, i64>(3)? as u64,
+        ))
+    })?;
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+/// When the latest mute covering this nickname or client ends, if any.
+pub fn muted_until(conn: &Connection, name: &str, client: &str, now: u64) -> rusqlite::Result<Option<u64>> {
+    conn.query_row(
+        "SELECT MAX(until) FROM chat_mutes
+         WHERE until > ?1 AND ((kind = ?2 AND value = ?3) OR (kind = ?4 AND value = ?5))",
+        params![now as i64, MUTE_NAME, name.to_lowercase(), MUTE_CLIENT, client],
+        |r| r.get::<_, Option<i64>>(0),
+    )
+    .map(|until| until.map(|u| u as u64))
+}
+
+/// Lockouts kept in the log; older ones are dropped as new ones arrive.
+const LOCKOUT_LOG_LEN: i64 = 200;
+
+pub fn log_lockout(conn: &Connection, at: u64, client: &str, failures: u32, until: u64) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO login_lockouts (at, client, failures, until) VALUES (?1, ?2, ?3, ?4)",
+        params![at as i64, client, failures, until as i64],
+    )?;
+    conn.execute(
+        "DELETE FROM login_lockouts WHERE id <= (SELECT MAX(id) FROM login_lockouts) - ?1",
+        params![LOCKOUT_LOG_LEN],
+    )?;
+    Ok(())
+}
+
+/// Logged lockouts, newest first: (at_secs, client, failures, until_secs).
+pub fn recent_lockouts(conn: &Connection) -> rusqlite::Result<Vec<(u64, String, u32, u64)>> {
+    let mut stmt = conn.prepare("SELECT at, client, failures, until FROM login_lockouts ORDER BY id DESC")?;
+    let rows = stmt.query_map([], |r| {
+        Ok((
+            r.get::<_, i64>(0)? as u64,
+            r.get::<_, String>(1)?,
+            r.get::<_, u32>(2)?,
+            r.get::<_, i64>(3)? as u64,
+        ))
+    })?;
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+/// A live admin session.
+#[derive(Clone, Debug)]
+pub struct AdminSession {
+    /// The account signed in, or `None` for the config.json admin.
+    pub account_id: Option<i64>,
+}
+
+pub fn create_session(
+    conn: &Connection,
+    token_hash: &str,
+    account_id: Option<i64>,
+    now: u64,
+    expires_at: u64,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO admin_sessions (token_hash, account_id, created_at, expires_at) VALUES (?1, ?2, ?3, ?4)",
+        params![token_hash, account_id, now as i64, expires_at as i64],
+    )?;
+    Ok(())
+}
+
+/// Extend a live session to `expires_at` and return it; `None` if there is
+/// no such session or it has already expired.
+pub fn touch_session(
+    conn: &Connection,
+    token_hash: &str,
+    now: u64,
+    expires_at: u64,
+) -> rusqlite::Result<Option<AdminSession>> {
+    let mut stmt = conn.prepare(
+        "UPDATE admin_sessions SET expires_at = ?3 WHERE token_hash = ?1 AND expires_at > ?2
+         RETURNING account_id",
+    )?;
+    let mut rows = stmt.query_map(params![token_hash, now as i64, expires_at as i64], |r| {
+        Ok(AdminSession { account_id: r.get(0)? })
+    })?;
+    rows.next().transpose()
+}
+
+pub fn delete_session(conn: &Connection, token_hash: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM admin_sessions WHERE token_hash = ?1", params![token_hash])?;
+    Ok(())
+}
+
+pub fn delete_all_sessions(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.execute("DELETE FROM admin_sessions", [])
+}
+
+pub fn delete_expired_sessions(conn: &Connection, now: u64) -> rusqlite::Result<usize> {
+    conn.execute("DELETE FROM admin_sessions WHERE expires_at <= ?1", params![now as i64])
+}
+
+pub fn count_sessions(conn: &Connection, now: u64) -> rusqlite::Result<u64> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM admin_sessions WHERE expires_at > ?1",
+        params![now as i64],
+        |r| r.get::<_, i64>(0),
+    )
+    .map(|n| n as u64)
+}
+
+/// (id, name, role, password_hash, created_at) of an admin account.
+pub type AccountRow = (i64, String, String, String, u64);
+
+fn account_row(r: &rusqlite::Row) -> rusqlite::Result<AccountRow> {
+    Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get::<_, i64>(4)? as u64))
+}
+
+/// Add an account. False if the name (compared case-insensitively) is
+/// taken.
+pub fn create_account(conn: &Connection, name: &str, role: &str, password_hash: &str, now: u64) -> rusqlite::Result<bool> {
+    let n = conn.execute(
+        "INSERT OR IGNORE INTO accounts (name, role, password_hash, created_at) VALUES (?1, ?2, ?3, ?4)",
+        params![name, role, password_hash, now as i64],
+    )?;
+    Ok(n > 0)
+}
+
+pub fn find_account(conn: &Connection, name: &str) -> rusqlite::Result<Option<AccountRow>> {
+    let mut stmt = conn.prepare("SELECT id, name, role, password_hash, created_at FROM accounts WHERE name = ?1")?;
+    let mut rows = stmt.query_map(params![name], account_row)?;
+    rows.next().transpose()
+}
+
+pub fn get_account(conn: &Connection, id: i64) -> rusqlite::Result<Option<AccountRow>> {
+    let mut stmt = conn.prepare("SELECT id, name, role, password_hash, created_at FROM accounts WHERE id = ?1")?;
+    let mut rows = stmt.query_map(params![id], account_row)?;
+    rows.next().transpose()
+}
+
+pub fn list_accounts(conn: &Connection) -> rusqlite::Result<Vec<AccountRow>> {
+    let mut stmt = conn.prepare("SELECT id, name, role, password_hash, created_at FROM accounts ORDER BY name")?;
+    let rows = stmt.query_map([], account_row)?;
+    let mut out = Vec::new();
+    for row in rows {
+        out.push(row?);
+    }
+    Ok(out)
+}
+
+pub fn has_accounts(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.prepare("SELECT 1 FROM accounts LIMIT 1")?.exists([])
+}
+
+/// Remove an account and sign it out everywhere.
+pub fn delete_account(conn: &mut Connection, id: i64) -> rusqlite::Result<bool> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM admin_sessions WHERE account_id = ?1", params![id])?;
+    let n = tx.execute("DELETE FROM accounts WHERE id = ?1", params![id])?;
+    tx.commit()?;
+    Ok(n > 0)
+}
+
+/// Give an account a new password and sign it out everywhere.
+pub fn reset_account_password(conn: &mut Connection, id: i64, password_hash: &str) -> rusqlite::Result<bool> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM admin_sessions WHERE account_id = ?1", params![id])?;
+    let n = tx.execute(
+        "UPDATE accounts SET password_hash = ?2 WHERE id = ?1",
+        params![id, password_hash],
+    )?;
+    tx.commit()?;
+    Ok(n > 0)
+}
+
+pub fn clear_messages(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM messages", [])?;
+    Ok(())
+}
+
+/// One-time move of the old `chat.json` history into the messages table.
+/// The file is renamed to `chat.json.imported` inside the transaction, so
+/// the import can neither run twice nor lose the history. A file that
+/// doesn't parse is an error and is left in place.
+pub fn import_legacy_chat(conn: &mut Connection) -> rusqlite::Result<usize> {
+    let path = crate::settings::data_path(LEGACY_CHAT_FILE);
+    let Ok(data) = std::fs::read_to_string(&path) else {
+        return Ok(0);
+    };
+    let legacy: Vec<(String, String, u64)> = serde_json::from_str(&data)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    let tx = conn.transaction()?;
+    for (name, body, ts) in &legacy {
+        insert_message(&tx, DEFAULT_CHANNEL, name, body, "", *ts)?;
+    }
+    std::fs::rename(&path, path.with_extension("json.imported"))
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    tx.commit()?;
+    Ok(legacy.len())
+}
+
+/// Migrate the test process's scratch database once and open it.
+#[cfg(test)]
+pub fn open_for_tests() -> Connection {
+    static MIGRATED: std::sync::Once = std::sync::Once::new();
+    crate::settings::init_for_tests();
+    MIGRATED.call_once(|| migrate().unwrap());
+    open().unwrap()
+}
+
+/// Every path in the filename index, sorted.
+#[cfg(test)]
+pub fn indexed_paths(conn: &Connection) -> Vec<String> {
+    let mut stmt = conn.prepare("SELECT rel_path FROM file_index ORDER BY rel_path").unwrap();
+    let rows = stmt.query_map([], |r| r.get::<_, String>(0)).unwrap();
+    rows.map(Result::unwrap).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_version(conn: &Connection) -> i64 {
+        conn.query_row("PRAGMA user_version", [], |r| r.get(0)).unwrap()
+    }
+
+    /// Run `migrate` against a database of its own, set up by `prepare`.
+    fn migrated(name: &str, prepare: &str) -> Connection {
+        let dir = crate::settings::init_for_tests().data_dir.join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(DB_FILE);
+        Connection::open(&path).unwrap().execute_batch(prepare).unwrap();
+        let mut conn = Connection::open(&path).unwrap();
+        // A second run finds nothing left to do.
+        migrate_conn(&mut conn).unwrap();
+        migrate_conn(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn fresh_database_gets_the_whole_schema() {
+        let conn = migrated("db-fresh", "");
+        assert_eq!(user_version(&conn), MIGRATIONS.len() as i64);
+        assert!(!reconciled_before(&conn).unwrap());
+        record_upload(&conn, "a.txt", 1, Some("abc")).unwrap();
+        assert_eq!(find_by_hash(&conn, "abc").unwrap(), vec!["a.txt"]);
+        mark_reconciled(&conn).unwrap();
+        assert!(reconciled_before(&conn).unwrap());
+        assert_eq!(user_version(&conn), MIGRATIONS.len() as i64);
+    }
+
+    #[test]
+    fn old_databases_keep_their_rows_and_reconciled_marker() {
+        let conn = migrated(
+            "db-legacy",
+            "CREATE TABLE uploads (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                rel_path TEXT NOT NULL UNIQUE,
+                uploaded_at INTEGER NOT NULL
+            );
+            INSERT INTO uploads (rel_path, uploaded_at) VALUES ('old.txt', 5);
+            PRAGMA user_version = 1;",
+        );
+        assert_eq!(user_version(&conn), MIGRATIONS.len() as i64);
+        assert!(reconciled_before(&conn).unwrap());
+        assert_eq!(list_tracked(&conn).unwrap(), vec![(1, "old.txt".to_string(), 5)]);
+    }
+
+    #[test]
+    fn file_index_search_and_forget() {
+        let conn = migrated("db-index", "");
+        index_file(&conn, "Photos/holiday_2023.jpg", 10).unwrap();
+        index_file(&conn, "Photos/Trip/beach.png", 20).unwrap();
+        index_file(&conn, "Photography.txt", 30).unwrap();
+        index_file(&conn, "Photos/holiday_2023.jpg", 11).unwrap();
+        assert_eq!(
+            search_file_index(&conn, "holiday", 10).unwrap(),
+            vec![("Photos/holiday_2023.jpg".to_string(), 11)]
+        );
+        // FTS5 syntax in the input is taken as plain words.
+        assert_eq!(search_file_index(&conn, "\"beach*", 10).unwrap().len(), 1);
+        assert!(search_file_index(&conn, "photo NOT trip", 10).unwrap().is_empty());
+
+        forget_under(&conn, "Photos").unwrap();
+        assert_eq!(indexed_paths(&conn), vec!["Photography.txt"]);
+    }
+
+    #[test]
+    fn messages_after_returns_the_newest_page_in_every_channel() {
+        let conn = migrated("db-chat", "");
+        let mut ids = Vec::new();
+        for (i, channel) in ["general", "random", "general", "random", "general"].iter().enumerate() {
+            ids.push(insert_message(&conn, channel, "n", &format!("m{}", i), "", i as u64).unwrap());
+        }
+        let after = |id, limit| -> Vec<(i64, String)> {
+            messages_after(&conn, id, limit).unwrap().into_iter().map(|m| (m.id, m.channel)).collect()
+        };
+        assert_eq!(
+            after(ids[1], 10),
+            vec![(ids[2], "general".to_string()), (ids[3], "random".to_string()), (ids[4], "general".to_string())]
+        );
+        // Capped to the newest ones, still oldest first.
+        assert_eq!(
+            after(0, 2),
+            vec![(ids[3], "random".to_string()), (ids[4], "general".to_string())]
+        );
+        assert!(after(ids[4], 10).is_empty());
+    }
+}
@@ -2,12 +2,42 @@
 
 pub mod app;
 
+#[cfg(feature = "ssr")]
+pub mod captive;
+#[cfg(feature = "ssr")]
+pub mod chat_filter;
+#[cfg(feature = "ssr")]
+pub mod chat_limit;
+#[cfg(feature = "ssr")]
+pub mod chat_render;
 #[cfg(feature = "ssr")]
 pub mod config;
 #[cfg(feature = "ssr")]
 pub mod db;
 #[cfg(feature = "ssr")]
 pub mod admin_session;
+#[cfg(feature = "ssr")]
+pub mod login_guard;
+#[cfg(feature = "ssr")]
+pub mod mdns;
+#[cfg(feature = "ssr")]
+pub mod password;
+#[cfg(feature = "ssr")]
+pub mod quarantine;
+#[cfg(feature = "ssr")]
+pub mod sanitize;
+#[cfg(feature = "ssr")]
+pub mod scanner;
+#[cfg(feature = "ssr")]
+pub mod settings;
+#[cfg(feature = "ssr")]
+pub mod thumbnails;
+#[cfg(feature = "ssr")]
+pub mod uploads;
+#[cfg(feature = "ssr")]
+pub mod watcher;
+#[cfg(feature = "ssr")]
+pub mod zip_stream;
 
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
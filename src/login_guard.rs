@@ -0,0 +1,184 @@
+//! Brute-force protection for the admin login.
+//!
+//! Failed attempts are counted per client address. The first few are
+//! free; after that each failure makes the client wait twice as long as
+//! the last before another password is even looked at, and from
+//! `LOCKOUT_AFTER` failures on every further failure locks it out for
+//! `LOCKOUT`. A successful login clears the count. State lives in memory;
+//! lockouts are also written to the database for the admin to review.
+//!
+//! `check` reserves the attempt under the same lock it checks with, and
+//! `failed`, `succeeded` or `abandoned` settle it. An attempt still being
+//! verified counts as a failure in waiting, so a burst of parallel
+//! requests gets no more guesses than the same requests one by one.
+
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Failures allowed before any waiting is imposed.
+const FREE_ATTEMPTS: u32 = 3;
+/// Failures after which the client is locked out.
+pub const LOCKOUT_AFTER: u32 = 10;
+const LOCKOUT: Duration = Duration::from_secs(15 * 60);
+/// Entries untouched for this long are forgotten.
+const IDLE_FORGET: Duration = Duration::from_secs(24 * 60 * 60);
+
+struct Attempts {
+    failures: u32,
+    /// Attempts let through by `check` and not settled yet.
+    in_flight: u32,
+    blocked_until: Instant,
+    last_failure: Instant,
+}
+
+lazy_static! {
+    static ref ATTEMPTS: Mutex<HashMap<IpAddr, Attempts>> = Mutex::new(HashMap::new());
+}
+
+/// Refusal to check a password because the client has to wait.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Blocked(pub u64);
+
+impl std::fmt::Display for Blocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Too many attempts, try again in {} s.", self.0)
+    }
+}
+
+/// What a failed attempt led to.
+pub struct Failure {
+    pub failures: u32,
+    /// Seconds until the next attempt is accepted; 0 for none.
+    pub wait_secs: u64,
+    /// This failure started a lockout.
+    pub locked_out: bool,
+}
+
+/// The entry for `client`, created if needed and with its failures
+/// forgotten if they are old. Prunes idle entries when the map grows.
+fn entry(attempts: &mut HashMap<IpAddr, Attempts>, client: IpAddr, now: Instant) -> &mut Attempts {
+    if attempts.len() > 1024 {
+        attempts.retain(|_, a| a.in_flight > 0 || now.duration_since(a.last_failure) < IDLE_FORGET);
+    }
+    let a = attempts.entry(client).or_insert(Attempts {
+        failures: 0,
+        in_flight: 0,
+        blocked_until: now,
+        last_failure: now,
+    });
+    if now.duration_since(a.last_failure) >= IDLE_FORGET {
+        a.failures = 0;
+    }
+    a
+}
+
+/// Whether `client` may try a password now. If so the attempt is
+/// reserved and has to be settled with `failed`, `succeeded` or
+/// `abandoned`.
+pub fn check(client: IpAddr) -> Result<(), Blocked> {
+    let now = Instant::now();
+    let Ok(mut attempts) = ATTEMPTS.lock() else {
+        return Ok(());
+    };
+    let a = entry(&mut attempts, client, now);
+    if a.blocked_until > now {
+        return Err(Blocked(a.blocked_until.duration_since(now).as_secs().max(1)));
+    }
+    // Past the free attempts a failure imposes a wait, so only one
+    // attempt at a time gets to find out.
+    if a.in_flight > 0 && a.failures + a.in_flight >= FREE_ATTEMPTS {
+        return Err(Blocked(1));
+    }
+    a.in_flight += 1;
+    Ok(())
+}
+
+/// Record a wrong password from `client`.
+pub fn failed(client: IpAddr) -> Failure {
+    let now = Instant::now();
+    let Ok(mut attempts) = ATTEMPTS.lock() else {
+        return Failure { failures: 0, wait_secs: 0, locked_out: false };
+    };
+    let a = entry(&mut attempts, client, now);
+    a.in_flight = a.in_flight.saturating_sub(1);
+    a.failures += 1;
+    a.last_failure = now;
+
+    let wait = if a.failures >= LOCKOUT_AFTER {
+        LOCKOUT
+    } else if a.failures > FREE_ATTEMPTS {
+        Duration::from_secs(1 << (a.failures - FREE_ATTEMPTS))
+    } else {
+        Duration::ZERO
+    };
+    a.blocked_until = now + wait;
+    Failure {
+        failures: a.failures,
+        wait_secs: wait.as_secs(),
+        locked_out: a.failures >= LOCKOUT_AFTER,
+    }
+}
+
+/// Forget the failures of a client that just logged in.
+pub fn succeeded(client: IpAddr) {
+    if let Ok(mut attempts) = ATTEMPTS.lock() {
+        attempts.remove(&client);
+    }
+}
+
+/// Release an attempt that ended before the password was checked.
+pub fn abandoned(client: IpAddr) {
+    if let Ok(mut attempts) = ATTEMPTS.lock()
+        && let Some(a) = attempts.get_mut(&client)
+    {
+        a.in_flight = a.in_flight.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_attempts_are_reserved() {
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        for _ in 0..FREE_ATTEMPTS {
+            assert_eq!(check(client), Ok(()));
+        }
+        // Every free attempt is in flight; the next has to wait for them.
+        assert_eq!(check(client), Err(Blocked(1)));
+        abandoned(client);
+        assert_eq!(check(client), Ok(()));
+        for _ in 0..FREE_ATTEMPTS {
+            assert_eq!(failed(client).wait_secs, 0);
+        }
+        assert_eq!(check(client), Ok(()));
+        assert_eq!(check(client), Err(Blocked(1)));
+        assert!(failed(client).wait_secs > 0);
+        assert!(check(client).is_err());
+    }
+
+    #[test]
+    fn failures_double_the_wait_then_lock_out() {
+        let client: IpAddr = "192.0.2.2".parse().unwrap();
+        let mut waits = Vec::new();
+        for _ in 0..LOCKOUT_AFTER {
+            let failure = failed(client);
+            waits.push(failure.wait_secs);
+            assert_eq!(failure.locked_out, failure.failures >= LOCKOUT_AFTER);
+        }
+        assert_eq!(&waits[..5], &[0, 0, 0, 2, 4]);
+        assert_eq!(waits[LOCKOUT_AFTER as usize - 1], LOCKOUT.as_secs());
+        assert!(matches!(check(client), Err(Blocked(secs)) if secs > LOCKOUT.as_secs() - 5));
+        assert!(failed(client).locked_out);
+
+        // Other clients are unaffected, and logging in starts over.
+        assert_eq!(check("192.0.2.3".parse().unwrap()), Ok(()));
+        succeeded(client);
+        assert_eq!(check(client), Ok(()));
+        assert_eq!(failed(client).wait_secs, 0);
+    }
+}
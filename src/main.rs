@@ -3,7 +3,7 @@ use actix_files::Files;
 #[cfg(feature = "ssr")]
 use actix_multipart::form::{tempfile::TempFileConfig, MultipartFormConfig};
 #[cfg(feature = "ssr")]
-use actix_web::{web, get, App, HttpResponse, HttpServer};
+use actix_web::{web, get, post, App, HttpResponse, HttpServer};
 #[cfg(feature = "ssr")]
 use actix_web::middleware::{Next, from_fn};
 #[cfg(feature = "ssr")]
@@ -20,6 +20,27 @@ use shareboxx::app::App;
 #[cfg(feature = "ssr")]
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    if std::env::args().nth(1).as_deref() == Some("hash-password") {
+        hash_password();
+    }
+    if std::env::args().skip(1).any(|a| a == "-h" || a == "--help") {
+        println!("{}", shareboxx::settings::USAGE);
+        return Ok(());
+    }
+    let settings = match shareboxx::settings::Settings::from_args(std::env::args().skip(1), |k| std::env::var(k).ok()) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, shareboxx::settings::USAGE);
+            std::process::exit(2);
+        }
+    };
+    settings.ensure_dirs()?;
+    println!(
+        "data directory {}, sharing {}",
+        settings.data_dir.display(),
+        settings.share_root.display()
+    );
+    shareboxx::settings::init(settings);
 
     // Load Leptos options from Cargo.toml
     let conf = get_configuration(None).unwrap();
@@ -27,12 +48,18 @@ async fn main() -> std::io::Result<()> {
     let leptos_options = conf.leptos_options;
 
     let routes = generate_route_list(App);
-    println!("listening on http://{}", &addr);
-
-    // Initialise the uploads tracking DB so a missing file doesn't surprise
-    // the first upload or expiration sweep.
-    if let Err(e) = shareboxx::db::open() {
-        eprintln!("warning: failed to open uploads.db: {}", e);
+    println!("listening on http://{}", addr);
+
+    // Set up the database schema before anything opens it.
+    shareboxx::db::migrate()
+        .map_err(|e| std::io::Error::other(format!("cannot set up uploads.db: {}", e)))?;
+    match shareboxx::db::open() {
+        Ok(mut conn) => match shareboxx::db::import_legacy_chat(&mut conn) {
+            Ok(0) => {}
+            Ok(n) => println!("imported {} chat message(s) from chat.json", n),
+            Err(e) => eprintln!("warning: failed to import chat.json: {}", e),
+        },
+        Err(e) => eprintln!("warning: failed to open uploads.db: {}", e),
     }
 
     tokio::spawn(async {
@@ -51,6 +78,28 @@ async fn main() -> std::io::Result<()> {
         }
     });
 
+    // Hourly sweep of resumable uploads that were abandoned mid-way, and of
+    // expired admin sessions.
+    tokio::spawn(async {
+        loop {
+            shareboxx::uploads::expire_stale();
+            shareboxx::admin_session::expire_stale();
+            tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+        }
+    });
+
+    // Pick up quarantined uploads whose scan was interrupted by a restart.
+    shareboxx::quarantine::resume_pending();
+
+    // Reconcile the share with the database (search index, expiration
+    // tracking) and keep it reconciled as files change out-of-band.
+    shareboxx::watcher::spawn();
+
+    // Advertise over mDNS for machines that don't use our DNS.
+    shareboxx::mdns::spawn(addr.port());
+
+    let max_upload_size = shareboxx::config::load().max_upload_size;
+
     HttpServer::new(move || {
         let site_root = &leptos_options.site_root;
 
@@ -58,7 +107,7 @@ async fn main() -> std::io::Result<()> {
             .wrap(from_fn(domain_redirect))
             .app_data(web::Data::new(
                 MultipartFormConfig::default()
-                    .total_limit(10 * 1024 * 1024 * 1024)
+                    .total_limit(usize::try_from(max_upload_size).unwrap_or(usize::MAX))
                     .memory_limit(10 * 1024 * 1024)
                     .error_handler(handle_multipart_error),
             ))
@@ -71,10 +120,22 @@ async fn main() -> std::io::Result<()> {
             // serve other assets from the `assets` directory
             .service(Files::new("/assets", site_root.as_ref()))
             .service(serve_file)
+            .service(serve_zip)
+            .service(serve_thumbnail)
             // serve the favicon from /favicon.ico
             .service(favicon)
+            // landing page for the dismissable captive-portal mode
+            .service(captive_landing)
+            .service(captive_continue)
             // uploader
             .service(web::resource("/upload").route(web::post().to(save_files)))
+            .service(web::resource("/upload/resumable").route(web::post().to(create_upload)))
+            .service(
+                web::resource("/upload/resumable/{id}")
+                    .route(web::get().to(upload_status))
+                    .route(web::patch().to(upload_chunk))
+                    .route(web::delete().to(abort_upload)),
+            )
 
             .leptos_routes(
                 routes.to_owned(),
@@ -86,7 +147,7 @@ async fn main() -> std::io::Result<()> {
             .app_data(web::Data::new(leptos_options.to_owned()))
             // Store temp files on same drive, otherwise .persist() will fail due to cross-device link error
             .app_data(web::Data::new(
-                TempFileConfig::default().directory("files"),
+                TempFileConfig::default().directory(shareboxx::settings::share_root()),
             ))
     })
     .bind(&addr)?
@@ -94,6 +155,35 @@ async fn main() -> std::io::Result<()> {
     .await
 }
 
+/// `shareboxx hash-password`: read a password from stdin and print its
+/// hash for `admin_password_hash`, then exit. Reading stdin keeps the
+/// password off the command line.
+#[cfg(feature = "ssr")]
+fn hash_password() -> ! {
+    use std::io::Read;
+    let mut input = String::new();
+    if let Err(e) = std::io::stdin().read_to_string(&mut input) {
+        eprintln!("failed to read password: {}", e);
+        std::process::exit(1);
+    }
+    let password = input.strip_suffix('\n').unwrap_or(&input);
+    let password = password.strip_suffix('\r').unwrap_or(password);
+    if password.is_empty() {
+        eprintln!("password is empty");
+        std::process::exit(1);
+    }
+    match shareboxx::password::hash(password) {
+        Ok(hash) => {
+            println!("{}", hash);
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("failed to hash password: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
 #[cfg(feature = "ssr")]
 fn handle_multipart_error(err: actix_multipart::MultipartError, _req: &actix_web::HttpRequest) -> actix_web::Error {
     println!("Multipart error: {}", err);
@@ -119,7 +209,12 @@ async fn serve_file(
 ) -> actix_web::Result<actix_files::NamedFile> {
     use shareboxx::app::ssr_imports::*;
 
-    let base = std::path::PathBuf::from("./files");
+    // Dot-entries are server-internal (partial uploads, temp files).
+    if path.split('/').any(|seg| seg.starts_with('.')) {
+        return Err(actix_web::error::ErrorNotFound("File not found"));
+    }
+
+    let base = shareboxx::settings::share_root().to_path_buf();
     let file_path = base.join(path.as_ref());
 
     // Path traversal protection via canonicalize
@@ -144,6 +239,151 @@ async fn serve_file(
     Ok(actix_files::NamedFile::open(canonical)?)
 }
 
+/// Serve a cached, downscaled JPEG of an image in the share.
+#[cfg(feature = "ssr")]
+#[get("/thumb/{path:.*}")]
+async fn serve_thumbnail(
+    path: web::Path<String>,
+) -> actix_web::Result<actix_files::NamedFile> {
+    if path.split('/').any(|seg| seg.starts_with('.'))
+        || !shareboxx::thumbnails::is_thumbnailable(&path)
+    {
+        return Err(actix_web::error::ErrorNotFound("File not found"));
+    }
+
+    let base = shareboxx::settings::share_root().to_path_buf();
+    let canonical = base.join(path.as_ref()).canonicalize()
+        .map_err(|_| actix_web::error::ErrorNotFound("File not found"))?;
+    let canonical_base = base.canonicalize()
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Server error"))?;
+    if !canonical.starts_with(&canonical_base) || canonical.is_dir() {
+        return Err(actix_web::error::ErrorNotFound("File not found"));
+    }
+
+    // Decoding and resizing is CPU-bound; keep it off the async workers.
+    let rel = path.into_inner();
+    let thumb = web::block(move || shareboxx::thumbnails::thumbnail_for(&canonical, &rel))
+        .await?
+        .map_err(|_| actix_web::error::ErrorNotFound("No preview available"))?;
+
+    Ok(actix_files::NamedFile::open(thumb)?
+        .set_content_type("image/jpeg".parse().unwrap()))
+}
+
+/// `io::Write` adapter that forwards buffered bytes to a streaming response
+/// body. Writes fail with `BrokenPipe` once the client has gone away.
+#[cfg(feature = "ssr")]
+struct ChannelWriter {
+    tx: tokio::sync::mpsc::Sender<actix_web::web::Bytes>,
+    buf: Vec<u8>,
+}
+
+#[cfg(feature = "ssr")]
+impl ChannelWriter {
+    const FLUSH_AT: usize = 64 * 1024;
+
+    fn new(tx: tokio::sync::mpsc::Sender<actix_web::web::Bytes>) -> Self {
+        Self { tx, buf: Vec::with_capacity(Self::FLUSH_AT) }
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl std::io::Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= Self::FLUSH_AT {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(Self::FLUSH_AT));
+        self.tx
+            .blocking_send(actix_web::web::Bytes::from(chunk))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "client went away"))
+    }
+}
+
+/// Stream a directory as an uncompressed ZIP, built on the fly.
+#[cfg(feature = "ssr")]
+#[get("/zip/{path:.*}")]
+async fn serve_zip(
+    path: web::Path<String>,
+) -> actix_web::Result<HttpResponse> {
+    use futures::StreamExt;
+    use shareboxx::app::ssr_imports::*;
+    use shareboxx::zip_stream::{collect_entries, ZipStream};
+    use tokio_stream::wrappers::ReceiverStream;
+
+    if path.split('/').any(|seg| seg.starts_with('.')) {
+        return Err(actix_web::error::ErrorNotFound("Directory not found"));
+    }
+    let base = shareboxx::settings::share_root().to_path_buf();
+    let dir = shareboxx::app::resolve_safe_path(&base, path.trim_end_matches('/'))
+        .map_err(|_| actix_web::error::ErrorNotFound("Directory not found"))?;
+    if !dir.is_dir() {
+        return Err(actix_web::error::ErrorNotFound("Directory not found"));
+    }
+
+    let archive_name = if path.trim_matches('/').is_empty() {
+        "shareboxx".to_string()
+    } else {
+        dir.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "shareboxx".to_string())
+    };
+    let entries = collect_entries(&dir)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Server error"))?;
+
+    if let Ok(mut stats) = STATS.write() {
+        stats.total_downloads += 1;
+        stats.total_download_bytes += entries.iter().map(|e| e.size).sum::<u64>();
+    }
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<actix_web::web::Bytes>(8);
+    // Reading files and pushing into the channel both block; run it on the
+    // blocking pool. Nobody waits for it: the response ends when it drops
+    // the sender.
+    actix_web::rt::task::spawn_blocking(move || {
+        let mut zip = ZipStream::new(ChannelWriter::new(tx));
+        for entry in &entries {
+            match zip.add(entry) {
+                Ok(()) => {}
+                // Vanished or unreadable since listing: leave it out.
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::PermissionDenied) => {}
+                Err(e) => {
+                    if e.kind() != std::io::ErrorKind::BrokenPipe {
+                        eprintln!("zip download: {}: {}", entry.name, e);
+                    }
+                    return;
+                }
+            }
+        }
+        let _ = zip.finish();
+    });
+
+    let ascii_name: String = archive_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || " -_.".contains(c) { c } else { '_' })
+        .collect();
+    let encoded: String = archive_name
+        .bytes()
+        .map(|b| if b.is_ascii_alphanumeric() || b"-_.".contains(&b) { (b as char).to_string() } else { format!("%{:02X}", b) })
+        .collect();
+    let stream = ReceiverStream::new(rx).map(Ok::<_, actix_web::Error>);
+    Ok(HttpResponse::Ok()
+        .insert_header(("Content-Type", "application/zip"))
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.zip\"; filename*=UTF-8''{}.zip", ascii_name, encoded),
+        ))
+        .streaming(stream))
+}
+
 #[cfg(feature = "ssr")]
 #[derive(Debug, actix_multipart::form::MultipartForm)]
 struct UploadForm {
@@ -158,68 +398,230 @@ async fn save_files(
 ) -> Result<impl actix_web::Responder, actix_web::Error> {
     use shareboxx::app::ssr_imports::*;
 
-    let mut total_size: u64 = 0;
-    // Reuse a single connection for the whole batch.
-    let db_conn = shareboxx::db::open().ok();
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
+    // Same checks as a resumable upload gets in `uploads::begin`.
+    let base = shareboxx::settings::share_root().to_path_buf();
+    let dir = shareboxx::app::resolve_safe_path(&base, &form.upload_path)
+        .map_err(|_| actix_web::error::ErrorBadRequest("invalid upload path"))?;
+    if !dir.is_dir() {
+        return Err(actix_web::error::ErrorBadRequest("upload path is not a directory"));
+    }
+    let names: Vec<String> = form.files.iter().map(|f| f.file_name.clone().unwrap_or_default()).collect();
+    if names.iter().any(|n| !n.is_empty() && !shareboxx::uploads::is_valid_file_name(n)) {
+        return Err(actix_web::error::ErrorBadRequest("invalid file name"));
+    }
 
-    for f in form.files {
-        let file_name = f.file_name.unwrap_or_default();
-        if file_name.is_empty() {
-            continue;
-        }
-        let path = format!("./files/{}{}", form.upload_path.clone(), file_name);
-
-        // This logic can be simplified/made more robust, but keeping it for now
-        let mut new_path = path.clone();
-        if let (Some(parent), Some(stem), Some(ext)) = (
-            std::path::Path::new(&path).parent(),
-            std::path::Path::new(&path).file_stem().and_then(|s| s.to_str()),
-            std::path::Path::new(&path).extension().and_then(|s| s.to_str()),
-        ) {
-            let mut i = 1;
-            while std::path::Path::new(&new_path).exists() {
-                new_path = parent
-                    .join(format!("{}-{}.{}", stem, i, ext))
-                    .to_str()
-                    .ok_or_else(|| actix_web::error::ErrorInternalServerError("Invalid path"))?
-                    .to_string();
-                i += 1;
+    // Hashing and sanitising read whole files; keep them off the async
+    // workers.
+    let upload_path = form.upload_path;
+    let files = form.files;
+    let (saved, total_size) = web::block(move || -> std::io::Result<_> {
+        let mut total_size: u64 = 0;
+        let mut saved = Vec::new();
+        // Reuse a single connection for the whole batch.
+        let db_conn = shareboxx::db::open().ok();
+
+        for (f, file_name) in files.into_iter().zip(names) {
+            if file_name.is_empty() {
+                continue;
             }
+            let size = f.size as u64;
+
+            let staged = f.file.into_temp_path().keep().map_err(|e| e.error)?;
+            let done = shareboxx::uploads::finalize(db_conn.as_ref(), &staged, &upload_path, &file_name, None)?;
+
+            shareboxx::uploads::announce(&done);
+            total_size += size;
+            saved.push(done);
+        }
+        Ok((saved, total_size))
+    })
+    .await?
+    .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to persist file: {}", e)))?;
+
+    if total_size > 0
+        && let Ok(mut stats) = STATS.write()
+    {
+        stats.total_uploads += 1;
+        stats.total_upload_bytes += total_size;
+    }
+
+    Ok(actix_web::HttpResponse::Ok().json(saved))
+}
+
+#[cfg(feature = "ssr")]
+#[derive(serde::Deserialize)]
+struct CreateUpload {
+    upload_path: String,
+    file_name: String,
+    size: u64,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(serde::Serialize)]
+struct UploadStatus {
+    id: String,
+    offset: u64,
+    size: u64,
+}
+
+#[cfg(feature = "ssr")]
+impl From<shareboxx::db::PartialUpload> for UploadStatus {
+    fn from(u: shareboxx::db::PartialUpload) -> Self {
+        Self { id: u.id, offset: u.received, size: u.total_size }
+    }
+}
+
+#[cfg(feature = "ssr")]
+fn upload_error(err: shareboxx::uploads::UploadError) -> actix_web::Error {
+    use shareboxx::uploads::UploadError;
+    match err {
+        UploadError::NotFound => actix_web::error::ErrorNotFound("Upload not found"),
+        // No Upload-Offset: the client backs off and asks again.
+        UploadError::Busy => actix_web::error::ErrorConflict("Upload is busy"),
+        UploadError::Completed(..) => actix_web::error::ErrorConflict(err.to_string()),
+        UploadError::OffsetMismatch(offset) => actix_web::error::InternalError::from_response(
+            "offset mismatch",
+            HttpResponse::Conflict()
+                .insert_header(("Upload-Offset", offset.to_string()))
+                .finish(),
+        )
+        .into(),
+        UploadError::TooLarge(msg) => actix_web::error::ErrorPayloadTooLarge(msg),
+        UploadError::Invalid(msg) => actix_web::error::ErrorBadRequest(msg),
+        UploadError::Io(e) => {
+            eprintln!("resumable upload: {}", e);
+            actix_web::error::ErrorInternalServerError("Server error")
         }
+    }
+}
+
+/// Start a resumable upload. The client then PATCHes chunks to
+/// `/upload/resumable/{id}` with an `Upload-Offset` header, and can GET the
+/// same URL after a dropped connection to learn where to resume.
+#[cfg(feature = "ssr")]
+async fn create_upload(
+    body: web::Json<CreateUpload>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let conn = shareboxx::db::open()
+        .map_err(|e| upload_error(e.into()))?;
+    let upload = shareboxx::uploads::begin(&conn, &body.upload_path, &body.file_name, body.size)
+        .map_err(upload_error)?;
+    Ok(HttpResponse::Created()
+        .insert_header(("Location", format!("/upload/resumable/{}", upload.id)))
+        .json(UploadStatus::from(upload)))
+}
+
+#[cfg(feature = "ssr")]
+async fn upload_status(id: web::Path<String>) -> Result<HttpResponse, actix_web::Error> {
+    let conn = shareboxx::db::open()
+        .map_err(|e| upload_error(e.into()))?;
+    let upload = shareboxx::uploads::status(&conn, &id).map_err(upload_error)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(("Upload-Offset", upload.received.to_string()))
+        .json(UploadStatus::from(upload)))
+}
 
-        let persisted = f.file.persist(&new_path)
-            .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to persist file: {}", e)))?;
+#[cfg(feature = "ssr")]
+async fn upload_chunk(
+    req: actix_web::HttpRequest,
+    id: web::Path<String>,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, actix_web::Error> {
+    use futures::StreamExt;
+    use shareboxx::app::ssr_imports::*;
 
-        if let Ok(meta) = persisted.metadata() {
-            total_size += meta.len();
+    let offset: u64 = req
+        .headers()
+        .get("Upload-Offset")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Missing Upload-Offset"))?;
+
+    let conn = shareboxx::db::open()
+        .map_err(|e| upload_error(e.into()))?;
+    let (upload, mut writer) = match shareboxx::uploads::open_at(&conn, &id, offset) {
+        Ok(opened) => opened,
+        // The final chunk went through before but its answer was lost;
+        // answer the same again.
+        Err(shareboxx::uploads::UploadError::Completed(size, result)) => {
+            return Ok(HttpResponse::Ok()
+                .insert_header(("Upload-Offset", size.to_string()))
+                .content_type("application/json")
+                .body(result));
         }
+        Err(e) => return Err(upload_error(e)),
+    };
 
-        // Track the upload for expiration. The relative path is what the
-        // serve_file/admin layers operate on, so strip the leading "./files/".
-        if let Some(conn) = db_conn.as_ref() {
-            let rel = new_path.trim_start_matches("./files/").to_string();
-            if let Err(e) = shareboxx::db::record_upload(conn, &rel, now) {
-                eprintln!("warning: failed to record upload {}: {}", rel, e);
+    let mut failure: Option<actix_web::Error> = None;
+    while let Some(chunk) = payload.next().await {
+        let chunk = match chunk {
+            Ok(c) => c,
+            Err(e) => {
+                failure = Some(e.into());
+                break;
             }
+        };
+        if writer.received + chunk.len() as u64 > upload.total_size {
+            failure = Some(upload_error(shareboxx::uploads::UploadError::TooLarge(
+                "chunk exceeds declared upload size".to_string(),
+            )));
+            break;
+        }
+        if let Err(e) = writer.write(&chunk) {
+            failure = Some(upload_error(e.into()));
+            break;
         }
     }
 
-    if total_size > 0 {
+    // Acknowledge whatever reached the disk, even when the connection dropped
+    // halfway, so the client can resume from there.
+    let received = writer.received;
+    if let Err(e) = writer.finish(&conn) {
+        eprintln!("resumable upload: failed to record offset for {}: {}", id, e);
+    }
+    if let Some(e) = failure {
+        return Err(e);
+    }
+
+    // `writer` keeps the upload claimed until we return, so a retried
+    // final chunk can't complete it a second time.
+    if received == upload.total_size {
+        // Hashing, sanitising and moving the file take a while for big
+        // uploads; keep them off the async workers.
+        let finished = upload.clone();
+        let done = web::block(move || {
+            let conn = shareboxx::db::open()?;
+            shareboxx::uploads::complete(&conn, &finished)
+        })
+        .await?
+        .map_err(upload_error)?;
+        shareboxx::uploads::announce(&done);
         if let Ok(mut stats) = STATS.write() {
             stats.total_uploads += 1;
-            stats.total_upload_bytes += total_size;
+            stats.total_upload_bytes += upload.total_size;
         }
+        return Ok(HttpResponse::Ok()
+            .insert_header(("Upload-Offset", received.to_string()))
+            .json(done));
     }
 
-    Ok(actix_web::HttpResponse::Ok().body("ok"))
+    Ok(HttpResponse::NoContent()
+        .insert_header(("Upload-Offset", received.to_string()))
+        .finish())
+}
+
+#[cfg(feature = "ssr")]
+async fn abort_upload(id: web::Path<String>) -> Result<HttpResponse, actix_web::Error> {
+    let conn = shareboxx::db::open()
+        .map_err(|e| upload_error(e.into()))?;
+    shareboxx::uploads::abort(&conn, &id).map_err(upload_error)?;
+    Ok(HttpResponse::NoContent().finish())
 }
 
 #[cfg(feature = "ssr")]
 fn run_expiration_sweep() {
+    use shareboxx::app::{FileChange, FileEvent, ssr_imports::broadcast_file_event};
+
     let cfg = shareboxx::config::load();
     if !cfg.expiration_enabled {
         return;
@@ -251,7 +653,7 @@ fn run_expiration_sweep() {
         if uploaded_at >= cutoff {
             continue;
         }
-        let full = std::path::PathBuf::from("./files").join(&rel_path);
+        let full = shareboxx::settings::share_root().join(&rel_path);
         match std::fs::remove_file(&full) {
             Ok(_) => deleted += 1,
             Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {
@@ -266,6 +668,8 @@ fn run_expiration_sweep() {
         if let Err(e) = shareboxx::db::delete_by_id(&conn, id) {
             eprintln!("expiration sweep: failed to delete row {}: {}", id, e);
         }
+        let _ = shareboxx::db::unindex_file(&conn, &rel_path);
+        broadcast_file_event(FileEvent::for_path(&rel_path, FileChange::Deleted));
     }
 
     if deleted > 0 {
@@ -278,15 +682,25 @@ async fn domain_redirect(
     req: ServiceRequest,
     next: Next<impl MessageBody + 'static>,
 ) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
-    // Check if request hostname matches shareboxx.lan, otherwise redirect to it.
+    // Redirect requests for any other host name to the configured one.
     // ShareBoxx is HTTP-only by design — see README "Why no HTTPS?". Browsers'
     // captive-portal probes use plain HTTP and modern HTTPS-First modes carve
     // out RFC1918 / *.lan addresses, so HTTP is sustainable for this device.
-    if req.connection_info().host() != "shareboxx.lan" && !req.connection_info().host().starts_with("127.0.0.1") {
+    let config = shareboxx::config::cached();
+    let peer = req.peer_addr().map(|a| a.ip());
+    let probe = shareboxx::captive::answer(req.path(), peer, &config.captive, &config.hostname.canonical_host);
+    if let Some(answer) = probe {
+        return Ok(ServiceResponse::new(req.request().to_owned(), captive_response(answer)).map_into_boxed_body());
+    }
+
+    let hostname = &config.hostname;
+    let host = req.connection_info().host().to_string();
+    if !hostname.accepts(&host) && !config.mdns.answers_for(&host) {
+        let path_and_query = req.uri().path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
         return Ok(ServiceResponse::new(
             req.request().to_owned(),
             HttpResponse::TemporaryRedirect()
-                .append_header(("Location", "http://shareboxx.lan"))
+                .append_header(("Location", hostname.redirect_target(path_and_query)))
                 .finish(),
         )
         .map_into_boxed_body());
@@ -295,15 +709,63 @@ async fn domain_redirect(
     next.call(req).await.map(ServiceResponse::map_into_boxed_body)
 }
 
+#[cfg(feature = "ssr")]
+fn captive_response(answer: shareboxx::captive::Answer) -> HttpResponse {
+    use shareboxx::captive::Answer;
+    // Probes must never be answered from a cache, or a device would keep
+    // seeing the portal (or not) after the mode changed.
+    match answer {
+        // 302 rather than 307: it's what the probe clients are written for.
+        Answer::Redirect(location) => HttpResponse::Found()
+            .append_header(("Location", location))
+            .append_header(("Cache-Control", "no-store"))
+            .finish(),
+        Answer::Online { status, content_type, body } => {
+            let status = actix_web::http::StatusCode::from_u16(status).unwrap_or(actix_web::http::StatusCode::OK);
+            let mut response = HttpResponse::build(status);
+            response.append_header(("Cache-Control", "no-store"));
+            if body.is_empty() {
+                response.finish()
+            } else {
+                response.content_type(content_type).body(body)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[get("/captive")]
+async fn captive_landing() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .append_header(("Cache-Control", "no-store"))
+        .body(shareboxx::captive::landing_page())
+}
+
+#[cfg(feature = "ssr")]
+#[post("/captive/continue")]
+async fn captive_continue(req: actix_web::HttpRequest) -> HttpResponse {
+    if let Some(addr) = req.peer_addr() {
+        shareboxx::captive::dismiss(addr.ip());
+    }
+    HttpResponse::SeeOther().append_header(("Location", "/")).finish()
+}
+
 #[cfg(feature = "ssr")]
 #[get("/ws")]
-async fn counter_events() -> impl actix_web::Responder {
+async fn counter_events(req: actix_web::HttpRequest) -> impl actix_web::Responder {
     use actix_web::web::Bytes;
     use futures::StreamExt;
+    use shareboxx::app::{ChatEvent, FileChange, FileEvent};
     use shareboxx::app::ssr_imports::*;
     use tokio_stream::wrappers::ReceiverStream;
 
     let (tx, rx) = tokio::sync::mpsc::channel::<Bytes>(32);
+    let last_event_id: Option<i64> = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
 
     // Increment connected users and broadcast new count
     let new_count = CONNECTED_USERS.fetch_add(1, Ordering::Relaxed) + 1;
@@ -314,24 +776,62 @@ async fn counter_events() -> impl actix_web::Responder {
     }
 
     tokio::spawn(async move {
-        // Send initial user count to this client
+        let mut chat_rx = CHAT_CHANNEL.subscribe();
+        let mut users_rx = USERS_CHANNEL.subscribe();
+        let mut files_rx = FILES_CHANNEL.subscribe();
+
+        // A fresh stream starts after the newest message; the browser loads
+        // the history up to there itself.
+        let mut last_chat_id = match last_event_id {
+            Some(after) => after,
+            None => shareboxx::db::open()
+                .and_then(|conn| shareboxx::db::last_message_id(&conn))
+                .unwrap_or(0),
+        };
+
+        // Send initial user count to this client. It goes out after
+        // subscribing, so the browser can take it as the sign that loading
+        // the chat history now can't miss anything.
         let _ = tx.send(Bytes::from(format!(
             "event: users\ndata: {new_count}\n\n"
         ))).await;
 
-        let mut chat_rx = CHAT_CHANNEL.subscribe();
-        let mut users_rx = USERS_CHANNEL.subscribe();
+        // A reconnecting browser sends the id of the last chat message it
+        // saw; replay what it missed. This runs after subscribing, so
+        // nothing falls in between, and `last_chat_id` filters the overlap.
+        if last_event_id.is_some()
+            && let Some(last) = replay_chat(&tx, last_chat_id).await
+        {
+            last_chat_id = last;
+        }
 
         loop {
             tokio::select! {
                 result = chat_rx.recv() => {
                     match result {
-                        Ok(_) => {
-                            if tx.send(Bytes::from("event: chat\ndata: update\n\n")).await.is_err() {
+                        Ok(event) => {
+                            if let ChatEvent::Message(m) = &event {
+                                if m.id <= last_chat_id {
+                                    continue;
+                                }
+                                last_chat_id = m.id;
+                            }
+                            if tx.send(chat_event_bytes(&event)).await.is_err() {
+                                break;
+                            }
+                        }
+                        // Too slow to keep up: events were dropped for this
+                        // stream. Messages are in the database; channel
+                        // changes are refetched.
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
+                            match replay_chat(&tx, last_chat_id).await {
+                                Some(last) => last_chat_id = last,
+                                None => break,
+                            }
+                            if tx.send(chat_event_bytes(&ChatEvent::Channels)).await.is_err() {
                                 break;
                             }
                         }
-                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
                         Err(_) => break,
                     }
                 }
@@ -346,6 +846,26 @@ async fn counter_events() -> impl actix_web::Responder {
                         Err(_) => break,
                     }
                 }
+                result = files_rx.recv() => {
+                    match result {
+                        Ok(event) => {
+                            let data = serde_json::to_string(&event).unwrap_or_default();
+                            if tx.send(Bytes::from(format!("event: files\ndata: {data}\n\n"))).await.is_err() {
+                                break;
+                            }
+                        }
+                        // Which directories changed is lost; have the
+                        // browser reload whatever it shows.
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
+                            let event = FileEvent::in_dir("", FileChange::Rescan);
+                            let data = serde_json::to_string(&event).unwrap_or_default();
+                            if tx.send(Bytes::from(format!("event: files\ndata: {data}\n\n"))).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(_) => break,
+                    }
+                }
                 // Periodic heartbeat to detect dead connections
                 _ = tokio::time::sleep(std::time::Duration::from_secs(15)) => {
                     if tx.send(Bytes::from(": heartbeat\n\n")).await.is_err() {
@@ -367,6 +887,48 @@ async fn counter_events() -> impl actix_web::Responder {
         .streaming(stream)
 }
 
+/// Send the chat messages newer than `after` down an SSE stream, at most
+/// one history page of them: a client that was away longer has older
+/// messages to scroll back to, and `Last-Event-ID: 0` mustn't replay the
+/// whole history. Returns the id of the last message sent, or `None` if
+/// the client went away.
+#[cfg(feature = "ssr")]
+async fn replay_chat(
+    tx: &tokio::sync::mpsc::Sender<actix_web::web::Bytes>,
+    after: i64,
+) -> Option<i64> {
+    use shareboxx::app::{ChatEvent, CHAT_PAGE_SIZE};
+
+    let mut missed = match shareboxx::db::open()
+        .and_then(|conn| shareboxx::db::messages_after(&conn, after, CHAT_PAGE_SIZE))
+    {
+        Ok(missed) => missed,
+        Err(e) => {
+            eprintln!("chat replay: {}", e);
+            return Some(after);
+        }
+    };
+    shareboxx::chat_render::fill(&mut missed);
+    let mut last = after;
+    for message in missed {
+        last = message.id;
+        tx.send(chat_event_bytes(&ChatEvent::Message(message))).await.ok()?;
+    }
+    Some(last)
+}
+
+/// SSE frame for a chat event. Messages carry their id so the browser can
+/// report it back as `Last-Event-ID` after a reconnect.
+#[cfg(feature = "ssr")]
+fn chat_event_bytes(event: &shareboxx::app::ChatEvent) -> actix_web::web::Bytes {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    let frame = match event {
+        shareboxx::app::ChatEvent::Message(m) => format!("event: chat\nid: {}\ndata: {}\n\n", m.id, data),
+        _ => format!("event: chat\ndata: {}\n\n", data),
+    };
+    actix_web::web::Bytes::from(frame)
+}
+
 #[cfg(not(feature = "ssr"))]
 pub fn main() {
     // no client-side main function
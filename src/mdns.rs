@@ -0,0 +1,156 @@
+//! Multicast DNS advertisement, so machines that don't use the box's
+//! dnsmasq (a laptop on the wired or mesh side) can still find it.
+//!
+//! The server is published as `_http._tcp` for browsers and generic
+//! service browsers, and as `_shareboxx._tcp` with TXT records carrying
+//! the version and the runtime feature flags. It also answers for
+//! `<host_name>.local`. The config is re-read periodically: the records
+//! are re-announced when the flags change, and advertising is restarted
+//! (or stopped) when the `mdns` section itself changes.
+
+use crate::config::{Config, MdnsConfig};
+use mdns_sd::{IfKind, ServiceDaemon, ServiceInfo};
+use std::time::Duration;
+
+const HTTP_SERVICE: &str = "_http._tcp.local.";
+const SHAREBOXX_SERVICE: &str = "_shareboxx._tcp.local.";
+
+/// How often the config is checked for changes.
+const REFRESH: Duration = Duration::from_secs(30);
+
+/// Advertise on a background thread for as long as the config has it
+/// enabled. `port` is the port the HTTP server listens on.
+pub fn spawn(port: u16) {
+    std::thread::spawn(move || {
+        // The `mdns` section the daemon was last (re)started for, whether
+        // or not starting worked, so a failure is reported only once.
+        let mut current: Option<MdnsConfig> = None;
+        let mut daemon: Option<ServiceDaemon> = None;
+        let mut announced = None;
+        loop {
+            let cfg = crate::config::load();
+            let wanted = cfg.mdns.enabled.then_some(&cfg.mdns);
+            if current.as_ref() != wanted {
+                if let (Some(old), Some(mdns)) = (daemon.take(), current.as_ref()) {
+                    stop(&old, mdns);
+                    println!("mdns: stopped advertising \"{}\"", mdns.instance_name);
+                }
+                announced = None;
+                current = wanted.cloned();
+                if let Some(mdns) = wanted {
+                    match start(mdns) {
+                        Ok(started) => {
+                            println!(
+                                "mdns: advertising \"{}\" as {}",
+                                mdns.instance_name,
+                                mdns.local_host()
+                            );
+                            daemon = Some(started);
+                        }
+                        Err(e) => eprintln!("mdns: failed to start: {}", e),
+                    }
+                }
+            }
+            if let Some(daemon) = &daemon {
+                let flags = cfg.runtime_settings();
+                if announced != Some(flags) {
+                    match register(daemon, &cfg, port) {
+                        Ok(()) => announced = Some(flags),
+                        Err(e) => eprintln!("mdns: failed to register: {}", e),
+                    }
+                }
+            }
+            std::thread::sleep(REFRESH);
+        }
+    });
+}
+
+fn start(cfg: &MdnsConfig) -> mdns_sd::Result<ServiceDaemon> {
+    let daemon = ServiceDaemon::new()?;
+    if !cfg.interfaces.is_empty() {
+        daemon.disable_interface(IfKind::All)?;
+        for name in &cfg.interfaces {
+            daemon.enable_interface(IfKind::Name(name.clone()))?;
+        }
+    }
+    if cfg.loopback {
+        daemon.enable_interface(vec![IfKind::LoopbackV4, IfKind::LoopbackV6])?;
+    }
+    Ok(daemon)
+}
+
+/// Withdraw both services, so browsers drop them right away rather than
+/// when the records expire, and shut the daemon down.
+fn stop(daemon: &ServiceDaemon, cfg: &MdnsConfig) {
+    for service in [HTTP_SERVICE, SHAREBOXX_SERVICE] {
+        if let Ok(done) = daemon.unregister(&format!("{}.{}", cfg.instance_name, service)) {
+            let _ = done.recv_timeout(Duration::from_secs(1));
+        }
+    }
+    let _ = daemon.shutdown();
+}
+
+/// Publish (or re-publish) both services with the current flags.
+fn register(daemon: &ServiceDaemon, cfg: &Config, port: u16) -> mdns_sd::Result<()> {
+    let (chat_enabled, admin_configured) = cfg.runtime_settings();
+    let flag = |on: bool| if on { "1" } else { "0" };
+    let host = format!("{}.", cfg.mdns.local_host());
+    let name = &cfg.mdns.instance_name;
+
+    let http = ServiceInfo::new(HTTP_SERVICE, name, &host, (), port, &[("path", "/")][..])?
+        .enable_addr_auto();
+    let txt = [
+        ("version", env!("CARGO_PKG_VERSION")),
+        ("path", "/"),
+        ("chat", flag(chat_enabled)),
+        ("admin", flag(admin_configured)),
+    ];
+    let shareboxx = ServiceInfo::new(SHAREBOXX_SERVICE, name, &host, (), port, &txt[..])?
+        .enable_addr_auto();
+    daemon.register(http)?;
+    daemon.register(shareboxx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mdns_sd::ServiceEvent;
+
+    #[test]
+    fn shareboxx_service_resolves_on_loopback() {
+        let mdns = MdnsConfig {
+            instance_name: format!("ShareBoxx test {}", std::process::id()),
+            host_name: format!("shareboxx-test-{}", std::process::id()),
+            interfaces: vec!["lo".to_string()],
+            loopback: true,
+            ..Default::default()
+        };
+        let cfg = Config { mdns: mdns.clone(), ..Default::default() };
+        let advertiser = start(&mdns).unwrap();
+        register(&advertiser, &cfg, 3999).unwrap();
+
+        let browser = start(&mdns).unwrap();
+        let events = browser.browse(SHAREBOXX_SERVICE).unwrap();
+        let fullname = format!("{}.{}", mdns.instance_name, SHAREBOXX_SERVICE);
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        let info = loop {
+            let left = deadline.saturating_duration_since(std::time::Instant::now());
+            match events.recv_timeout(left) {
+                Ok(ServiceEvent::ServiceResolved(info)) if info.get_fullname() == fullname => break info,
+                Ok(_) => continue,
+                Err(e) => panic!("{} was not resolved: {}", fullname, e),
+            }
+        };
+
+        let (chat_enabled, admin_configured) = cfg.runtime_settings();
+        let flag = |on: bool| if on { "1" } else { "0" };
+        assert_eq!(info.get_port(), 3999);
+        assert_eq!(info.get_property_val_str("version"), Some(env!("CARGO_PKG_VERSION")));
+        assert_eq!(info.get_property_val_str("path"), Some("/"));
+        assert_eq!(info.get_property_val_str("chat"), Some(flag(chat_enabled)));
+        assert_eq!(info.get_property_val_str("admin"), Some(flag(admin_configured)));
+
+        let _ = browser.shutdown();
+        stop(&advertiser, &mdns);
+    }
+}
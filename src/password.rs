@@ -0,0 +1,124 @@
+//! Admin password hashing.
+//!
+//! New hashes are Argon2id in the PHC string format
+//! (`$argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>`), which carries the
+//! algorithm, its parameters and the salt, so the cost can be raised later
+//! without breaking existing config files.
+//!
+//! The original format, a hex SHA-256 of salt || password with the salt
+//! kept in `admin_salt`, still verifies so that updated boxes keep their
+//! password. `admin_login` replaces such a hash after the next successful
+//! login.
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Argon2, Params};
+use sha2::{Digest, Sha256};
+
+/// Hash `password` with the current algorithm and parameters.
+pub fn hash(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut rand::rngs::OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Check `password` against a stored hash in either format. `legacy_salt`
+/// is only used for the old SHA-256 hashes.
+pub fn verify(stored: &str, legacy_salt: &str, password: &str) -> bool {
+    if is_legacy(stored) {
+        return verify_legacy(stored, legacy_salt, password);
+    }
+    let Ok(parsed) = PasswordHash::new(stored) else {
+        return false;
+    };
+    Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_ok()
+}
+
+/// Whether a stored hash should be replaced by `hash` once the password is
+/// known: it is in the old format or uses weaker parameters than now.
+pub fn needs_upgrade(stored: &str) -> bool {
+    if is_legacy(stored) {
+        return true;
+    }
+    let Ok(parsed) = PasswordHash::new(stored) else {
+        return true;
+    };
+    parsed.algorithm != argon2::Algorithm::Argon2id.ident()
+        || Params::try_from(&parsed).map_or(true, |p| {
+            // Only the costs; a parsed hash also carries its output length,
+            // which the defaults leave unset.
+            let current = Params::default();
+            (p.m_cost(), p.t_cost(), p.p_cost()) != (current.m_cost(), current.t_cost(), current.p_cost())
+        })
+}
+
+/// Old hashes are bare hex; PHC strings start with '$'.
+pub fn is_legacy(stored: &str) -> bool {
+    !stored.starts_with('$')
+}
+
+fn verify_legacy(stored: &str, salt: &str, password: &str) -> bool {
+    let Ok(salt_bytes) = hex::decode(salt) else {
+        return false;
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(&salt_bytes);
+    hasher.update(password.as_bytes());
+    let computed = hex::encode(hasher.finalize());
+    // Compare every byte rather than stopping at the first mismatch.
+    let a = computed.as_bytes();
+    let b = stored.as_bytes();
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A hash in the original format: hex SHA-256 of salt || password.
+    fn legacy(salt: &str, password: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(hex::decode(salt).unwrap());
+        hasher.update(password.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    #[test]
+    fn legacy_hashes_verify_and_need_upgrade() {
+        let salt = "00112233445566778899aabbccddeeff";
+        let stored = legacy(salt, "hunter2");
+        assert!(is_legacy(&stored));
+        assert!(verify(&stored, salt, "hunter2"));
+        assert!(!verify(&stored, salt, "hunter3"));
+        assert!(!verify(&stored, "ffee", "hunter2"));
+        assert!(!verify(&stored, "not hex", "hunter2"));
+        assert!(needs_upgrade(&stored));
+    }
+
+    #[test]
+    fn new_hashes_verify_and_are_current() {
+        let stored = hash("hunter2").unwrap();
+        assert!(!is_legacy(&stored));
+        assert!(verify(&stored, "", "hunter2"));
+        assert!(!verify(&stored, "", "hunter3"));
+        assert!(!needs_upgrade(&stored));
+
+        let weaker = Argon2::new(
+            argon2::Algorithm::Argon2id,
+            argon2::Version::V0x13,
+            Params::new(8 * 1024, 1, 1, None).unwrap(),
+        )
+        .hash_password(b"hunter2", &SaltString::generate(&mut rand::rngs::OsRng))
+        .unwrap()
+        .to_string();
+        assert!(verify(&weaker, "", "hunter2"));
+        assert!(needs_upgrade(&weaker));
+        assert!(needs_upgrade("$garbage"));
+    }
+}
@@ -0,0 +1,226 @@
+//! Holding area for uploads awaiting a malware scan.
+//!
+//! When a scanner is configured, completed uploads are moved into a
+//! quarantine directory in the data directory instead of the share, out of
+//! reach of clients, and queued for a single
+//! background worker. Clean files are then published as usual; infected
+//! ones are moved aside for the admin to review, and scan errors leave the
+//! file where it is so it can be retried.
+
+use crate::db::{self, ScanRecord};
+use crate::scanner::{self, Verdict};
+use crate::uploads::{self, Finalized};
+use once_cell::sync::OnceCell;
+use rand::RngCore;
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+
+fn quarantine_dir() -> PathBuf {
+    crate::settings::data_path("quarantine")
+}
+
+fn infected_dir() -> PathBuf {
+    quarantine_dir().join("infected")
+}
+
+static QUEUE: OnceCell<Sender<i64>> = OnceCell::new();
+
+fn enqueue(id: i64) {
+    let tx = QUEUE.get_or_init(|| {
+        let (tx, rx) = std::sync::mpsc::channel::<i64>();
+        // One worker: scanning is I/O- and CPU-heavy and a Pi has little of
+        // either to spare.
+        std::thread::spawn(move || {
+            for id in rx {
+                process(id);
+            }
+        });
+        tx
+    });
+    let _ = tx.send(id);
+}
+
+/// Where the bytes of a quarantined upload currently live.
+pub fn held_path(rec: &ScanRecord) -> PathBuf {
+    let dir = if rec.status == db::SCAN_INFECTED {
+        infected_dir()
+    } else {
+        quarantine_dir()
+    };
+    dir.join(&rec.quarantine_name)
+}
+
+/// Move a completed upload into quarantine and queue it for scanning.
+pub fn submit(
+    conn: &Connection,
+    staged: &Path,
+    upload_path: &str,
+    file_name: &str,
+    sha256: Option<&str>,
+) -> std::io::Result<i64> {
+    std::fs::create_dir_all(quarantine_dir())?;
+    let mut buf = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut buf);
+    let name = hex::encode(buf);
+    uploads::move_file(staged, &quarantine_dir().join(&name))?;
+
+    let id = db::create_scan(conn, &name, upload_path, file_name, sha256, uploads::now_secs())
+        .map_err(std::io::Error::other)?;
+    enqueue(id);
+    Ok(id)
+}
+
+fn process(id: i64) {
+    let conn = match db::open() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("scan {}: cannot open db: {}", id, e);
+            return;
+        }
+    };
+    let rec = match db::get_scan(&conn, id) {
+        Ok(Some(rec)) if rec.status == db::SCAN_PENDING => rec,
+        _ => return,
+    };
+    let path = held_path(&rec);
+    let cfg = crate::config::load();
+    let now = uploads::now_secs();
+
+    let outcome = match scanner::scan(&cfg.scanner, &path) {
+        Verdict::Clean => release(&conn, &rec).map(|_| ()),
+        Verdict::Infected(signature) => {
+            eprintln!(
+                "scan: {}{} is infected ({}); kept in quarantine",
+                rec.upload_path, rec.file_name, signature
+            );
+            std::fs::create_dir_all(infected_dir())
+                .and_then(|_| std::fs::rename(&path, infected_dir().join(&rec.quarantine_name)))
+                .and_then(|_| {
+                    db::set_scan_status(&conn, id, db::SCAN_INFECTED, &signature, now)
+                        .map_err(std::io::Error::other)
+                })
+        }
+        Verdict::Error(msg) => {
+            eprintln!("scan: {}{} could not be scanned: {}", rec.upload_path, rec.file_name, msg);
+            db::set_scan_status(&conn, id, db::SCAN_ERROR, &msg, now)
+                .map_err(std::io::Error::other)
+        }
+    };
+    if let Err(e) = outcome {
+        let _ = db::set_scan_status(&conn, id, db::SCAN_ERROR, &e.to_string(), now);
+    }
+}
+
+/// Publish a quarantined upload to the share, whatever its verdict. Used
+/// for clean scans and by the admin to override a false positive.
+pub fn release(conn: &Connection, rec: &ScanRecord) -> std::io::Result<Finalized> {
+    let done = uploads::publish(
+        Some(conn),
+        &held_path(rec),
+        &rec.upload_path,
+        &rec.file_name,
+        rec.sha256.clone(),
+    )?;
+    db::set_scan_status(conn, rec.id, db::SCAN_CLEAN, &done.rel_path, uploads::now_secs())
+        .map_err(std::io::Error::other)?;
+    uploads::announce(&done);
+    Ok(done)
+}
+
+/// Delete a quarantined upload and its scan record.
+pub fn discard(conn: &Connection, rec: &ScanRecord) -> std::io::Result<()> {
+    match std::fs::remove_file(held_path(rec)) {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    db::delete_scan(conn, rec.id).map_err(std::io::Error::other)
+}
+
+/// Queue a previously failed scan again.
+pub fn rescan(conn: &Connection, rec: &ScanRecord) -> std::io::Result<()> {
+    db::set_scan_status(conn, rec.id, db::SCAN_PENDING, "", uploads::now_secs())
+        .map_err(std::io::Error::other)?;
+    enqueue(rec.id);
+    Ok(())
+}
+
+/// Re-queue scans interrupted by a restart, and retry ones that failed
+/// (the scanner may simply not have been up yet).
+pub fn resume_pending() {
+    let conn = match db::open() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("quarantine: cannot open db: {}", e);
+            return;
+        }
+    };
+    for status in [db::SCAN_PENDING, db::SCAN_ERROR] {
+        for rec in db::list_scans(&conn, status).unwrap_or_default() {
+            if status == db::SCAN_ERROR {
+                let _ = rescan(&conn, &rec);
+            } else {
+                enqueue(rec.id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ScannerConfig;
+
+    /// Wait for the scan worker to settle `id`.
+    fn settled(conn: &Connection, id: i64) -> ScanRecord {
+        for _ in 0..100 {
+            let rec = db::get_scan(conn, id).unwrap().unwrap();
+            if rec.status != db::SCAN_PENDING {
+                return rec;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        panic!("scan {} never finished", id);
+    }
+
+    #[test]
+    fn stub_scanner_verdicts_publish_or_hold() {
+        let _lock = crate::settings::lock_for_tests();
+        let conn = db::open_for_tests();
+        let settings = crate::settings::get();
+        let staged = |name: &str| {
+            let path = settings.data_dir.join(name);
+            std::fs::write(&path, name.as_bytes()).unwrap();
+            path
+        };
+        let use_scanner = |script: &str| {
+            let cfg = crate::config::Config {
+                scanner: ScannerConfig::Command {
+                    program: "sh".to_string(),
+                    args: vec!["-c".to_string(), script.to_string()],
+                },
+                ..Default::default()
+            };
+            crate::config::save(&cfg).unwrap();
+        };
+
+        use_scanner("exit 0");
+        let id = submit(&conn, &staged("clean.txt"), "", "clean.txt", None).unwrap();
+        let rec = settled(&conn, id);
+        assert_eq!(rec.status, db::SCAN_CLEAN);
+        assert!(!quarantine_dir().join(&rec.quarantine_name).exists());
+        assert_eq!(std::fs::read(settings.share_root.join(&rec.detail)).unwrap(), b"clean.txt");
+
+        use_scanner("echo Stub.Signature; exit 1");
+        let id = submit(&conn, &staged("bad.txt"), "", "bad.txt", None).unwrap();
+        let rec = settled(&conn, id);
+        assert_eq!(rec.status, db::SCAN_INFECTED);
+        assert_eq!(rec.detail, "Stub.Signature");
+        assert!(held_path(&rec).starts_with(infected_dir()));
+        assert_eq!(std::fs::read(held_path(&rec)).unwrap(), b"bad.txt");
+        assert!(!settings.share_root.join("bad.txt").exists());
+
+        let _ = std::fs::remove_file(crate::settings::data_path("config.json"));
+    }
+}
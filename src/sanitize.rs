@@ -0,0 +1,508 @@
+//! Lossless removal of identifying metadata (GPS position, camera serial
+//! numbers, timestamps, editing history) from uploaded files before they
+//! become visible on the share.
+//!
+//! Each supported format is a `MetadataStripper`; add a new one to
+//! `STRIPPERS` to cover another format. Strippers only drop metadata
+//! containers and never touch the encoded image data.
+
+use std::path::Path;
+
+/// Files larger than this are left alone rather than read into memory.
+const MAX_SANITIZE_BYTES: u64 = 128 * 1024 * 1024;
+
+pub trait MetadataStripper: Sync {
+    /// Lower-case file extensions (without the dot) this stripper handles.
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// Return the cleaned file contents, or `None` if there was nothing to
+    /// remove. An error means the file didn't parse and is left as-is.
+    fn strip(&self, data: &[u8]) -> Result<Option<Vec<u8>>, String>;
+}
+
+static STRIPPERS: &[&dyn MetadataStripper] = &[&Jpeg, &Png, &WebP];
+
+fn stripper_for(file_name: &str) -> Option<&'static dyn MetadataStripper> {
+    let ext = Path::new(file_name)
+        .extension()?
+        .to_str()?
+        .to_lowercase();
+    STRIPPERS
+        .iter()
+        .copied()
+        .find(|s| s.extensions().contains(&ext.as_str()))
+}
+
+/// Strip metadata from `path` in place, picking the format from
+/// `file_name`. Returns whether the file was rewritten.
+pub fn sanitize_file(path: &Path, file_name: &str) -> std::io::Result<bool> {
+    let Some(stripper) = stripper_for(file_name) else {
+        return Ok(false);
+    };
+    if std::fs::metadata(path)?.len() > MAX_SANITIZE_BYTES {
+        return Ok(false);
+    }
+    let data = std::fs::read(path)?;
+    let cleaned = match stripper.strip(&data) {
+        Ok(Some(c)) => c,
+        Ok(None) => return Ok(false),
+        Err(e) => {
+            eprintln!("sanitize: leaving {} untouched: {}", file_name, e);
+            return Ok(false);
+        }
+    };
+    let tmp = path.with_extension("sanitize.tmp");
+    std::fs::write(&tmp, &cleaned)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(true)
+}
+
+fn be16(d: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(d.get(at..at + 2)?.try_into().ok()?))
+}
+
+// ── JPEG ────────────────────────────────────────────────────────────────────
+
+/// Drops EXIF/XMP (APP1), IPTC (APP13), comments and vendor APPn segments.
+/// JFIF (APP0), ICC profiles (APP2) and Adobe colour info (APP14) are kept
+/// since they affect how the image renders, and the EXIF orientation is
+/// carried over in a minimal EXIF block so portrait photos stay upright.
+struct Jpeg;
+
+impl Jpeg {
+    const SOI: u8 = 0xD8;
+    const EOI: u8 = 0xD9;
+    const SOS: u8 = 0xDA;
+    const APP0: u8 = 0xE0;
+    const APP1: u8 = 0xE1;
+    const APP2: u8 = 0xE2;
+    const APP14: u8 = 0xEE;
+    const COM: u8 = 0xFE;
+
+    fn keep_segment(marker: u8, payload: &[u8]) -> bool {
+        match marker {
+            Self::APP0 | Self::APP14 => true,
+            Self::APP2 => payload.starts_with(b"ICC_PROFILE\0"),
+            Self::APP1..=0xEF | Self::COM => false,
+            _ => true,
+        }
+    }
+
+    /// EXIF orientation (1-8) from an APP1 payload, if present.
+    fn orientation(payload: &[u8]) -> Option<u16> {
+        let tiff = payload.strip_prefix(b"Exif\0\0")?;
+        let big_endian = match tiff.get(0..2)? {
+            b"MM" => true,
+            b"II" => false,
+            _ => return None,
+        };
+        let rd16 = |at: usize| -> Option<u16> {
+            let b: [u8; 2] = tiff.get(at..at + 2)?.try_into().ok()?;
+            Some(if big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) })
+        };
+        let rd32 = |at: usize| -> Option<u32> {
+            let b: [u8; 4] = tiff.get(at..at + 4)?.try_into().ok()?;
+            Some(if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+        };
+        let ifd = rd32(4)? as usize;
+        let count = rd16(ifd)? as usize;
+        (0..count).find_map(|i| {
+            let entry = ifd + 2 + i * 12;
+            (rd16(entry)? == 0x0112).then(|| rd16(entry + 8))?
+        })
+    }
+
+    /// An APP1 segment holding nothing but an orientation tag.
+    fn orientation_segment(orientation: u16) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(32);
+        payload.extend_from_slice(b"Exif\0\0");
+        payload.extend_from_slice(b"MM\0\x2a");
+        payload.extend_from_slice(&8u32.to_be_bytes()); // IFD0 offset
+        payload.extend_from_slice(&1u16.to_be_bytes()); // one entry
+        payload.extend_from_slice(&0x0112u16.to_be_bytes());
+        payload.extend_from_slice(&3u16.to_be_bytes()); // SHORT
+        payload.extend_from_slice(&1u32.to_be_bytes());
+        payload.extend_from_slice(&orientation.to_be_bytes());
+        payload.extend_from_slice(&[0, 0]);
+        payload.extend_from_slice(&0u32.to_be_bytes()); // no next IFD
+
+        let mut seg = vec![0xFF, Self::APP1];
+        seg.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+        seg.extend_from_slice(&payload);
+        seg
+    }
+}
+
+impl MetadataStripper for Jpeg {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["jpg", "jpeg", "jpe", "jfif"]
+    }
+
+    fn strip(&self, data: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        if data.get(0..2) != Some(&[0xFF, Self::SOI]) {
+            return Err("not a JPEG".to_string());
+        }
+        let mut kept: Vec<&[u8]> = Vec::new();
+        let mut removed = false;
+        let mut orientation = None;
+        let mut pos = 2;
+        let tail = loop {
+            if data.get(pos) != Some(&0xFF) {
+                return Err(format!("expected marker at offset {}", pos));
+            }
+            // Markers may be preceded by any number of 0xFF fill bytes.
+            while data.get(pos + 1) == Some(&0xFF) {
+                pos += 1;
+            }
+            let marker = *data.get(pos + 1).ok_or("truncated JPEG")?;
+            if marker == Self::SOS || marker == Self::EOI {
+                // Entropy-coded data follows; copy the rest verbatim.
+                break &data[pos..];
+            }
+            if (0xD0..=0xD7).contains(&marker) || marker == 0x01 {
+                kept.push(&data[pos..pos + 2]);
+                pos += 2;
+                continue;
+            }
+            let len = be16(data, pos + 2).ok_or("truncated JPEG")? as usize;
+            let end = pos + 2 + len;
+            if len < 2 || end > data.len() {
+                return Err("bad segment length".to_string());
+            }
+            let payload = &data[pos + 4..end];
+            if Self::keep_segment(marker, payload) {
+                kept.push(&data[pos..end]);
+            } else {
+                if marker == Self::APP1 && orientation.is_none() {
+                    orientation = Self::orientation(payload).filter(|o| (2..=8).contains(o));
+                }
+                removed = true;
+            }
+            pos = end;
+        };
+        if !removed {
+            return Ok(None);
+        }
+
+        let mut out = Vec::with_capacity(data.len());
+        out.extend_from_slice(&[0xFF, Self::SOI]);
+        // APP0 must stay first when present, so the EXIF stub goes after it.
+        let mut segments = kept.into_iter().peekable();
+        if let Some(first) = segments.peek()
+            && first.get(1) == Some(&Self::APP0)
+        {
+            out.extend_from_slice(first);
+            segments.next();
+        }
+        if let Some(o) = orientation {
+            out.extend_from_slice(&Self::orientation_segment(o));
+        }
+        for seg in segments {
+            out.extend_from_slice(seg);
+        }
+        out.extend_from_slice(tail);
+        Ok(Some(out))
+    }
+}
+
+// ── PNG ─────────────────────────────────────────────────────────────────────
+
+/// Drops EXIF (eXIf), text (tEXt, zTXt, iTXt) and timestamp (tIME) chunks.
+struct Png;
+
+impl Png {
+    const SIGNATURE: &'static [u8] = b"\x89PNG\r\n\x1a\n";
+    const DROPPED: &'static [&'static [u8; 4]] = &[b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+}
+
+impl MetadataStripper for Png {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["png"]
+    }
+
+    fn strip(&self, data: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        if !data.starts_with(Self::SIGNATURE) {
+            return Err("not a PNG".to_string());
+        }
+        let mut out = Vec::with_capacity(data.len());
+        out.extend_from_slice(Self::SIGNATURE);
+        let mut removed = false;
+        let mut pos = Self::SIGNATURE.len();
+        while pos < data.len() {
+            let len = data
+                .get(pos..pos + 4)
+                .map(|b| u32::from_be_bytes(b.try_into().unwrap()) as usize)
+                .ok_or("truncated PNG")?;
+            let kind = data.get(pos + 4..pos + 8).ok_or("truncated PNG")?;
+            // length + type + data + crc; the length is untrusted, so it
+            // mustn't be able to wrap around.
+            let end = pos
+                .checked_add(12)
+                .and_then(|p| p.checked_add(len))
+                .filter(|&end| end <= data.len())
+                .ok_or("bad chunk length")?;
+            if Self::DROPPED.iter().any(|d| &d[..] == kind) {
+                removed = true;
+            } else {
+                out.extend_from_slice(&data[pos..end]);
+            }
+            pos = end;
+            if kind == b"IEND" {
+                break;
+            }
+        }
+        Ok(removed.then_some(out))
+    }
+}
+
+// ── WebP ────────────────────────────────────────────────────────────────────
+
+/// Drops EXIF and XMP chunks and clears their flags in the VP8X header.
+struct WebP;
+
+impl WebP {
+    const VP8X_XMP: u8 = 0x04;
+    const VP8X_EXIF: u8 = 0x08;
+}
+
+impl MetadataStripper for WebP {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["webp"]
+    }
+
+    fn strip(&self, data: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        if data.get(0..4) != Some(b"RIFF") || data.get(8..12) != Some(b"WEBP") {
+            return Err("not a WebP".to_string());
+        }
+        let mut body = Vec::with_capacity(data.len());
+        let mut removed = false;
+        let mut pos = 12;
+        while pos + 8 <= data.len() {
+            let kind = &data[pos..pos + 4];
+            let len = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            // Chunks are padded to even; the length is untrusted, so it
+            // mustn't be able to wrap around.
+            let end = pos
+                .checked_add(8)
+                .and_then(|p| p.checked_add(len))
+                .and_then(|p| p.checked_add(len & 1))
+                .filter(|&end| end <= data.len())
+                .ok_or("bad chunk length")?;
+            match kind {
+                b"EXIF" | b"XMP " => removed = true,
+                b"VP8X" if len >= 1 => {
+                    let start = body.len();
+                    body.extend_from_slice(&data[pos..end]);
+                    body[start + 8] &= !(Self::VP8X_EXIF | Self::VP8X_XMP);
+                }
+                _ => body.extend_from_slice(&data[pos..end]),
+            }
+            pos = end;
+        }
+        if !removed {
+            return Ok(None);
+        }
+        let mut out = Vec::with_capacity(body.len() + 12);
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&((body.len() + 4) as u32).to_le_bytes());
+        out.extend_from_slice(b"WEBP");
+        out.extend_from_slice(&body);
+        Ok(Some(out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_chunk_lengths_are_refused() {
+        let mut png = Png::SIGNATURE.to_vec();
+        png.extend_from_slice(&u32::MAX.to_be_bytes());
+        png.extend_from_slice(b"tEXt");
+        assert_eq!(Png.strip(&png), Err("bad chunk length".to_string()));
+
+        let mut webp = b"RIFF\0\0\0\0WEBP".to_vec();
+        webp.extend_from_slice(b"EXIF");
+        webp.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(WebP.strip(&webp), Err("bad chunk length".to_string()));
+    }
+
+    fn encoded(format: image::ImageFormat) -> Vec<u8> {
+        let img = image::RgbImage::from_fn(8, 6, |x, y| image::Rgb([x as u8 * 30, y as u8 * 40, 90]));
+        let mut out = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut out, format).unwrap();
+        out.into_inner()
+    }
+
+    fn jpeg_segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut seg = vec![0xFF, marker];
+        seg.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+        seg.extend_from_slice(payload);
+        seg
+    }
+
+    /// Big-endian EXIF with IFD0 entries `(tag, short value)` and a GPS
+    /// sub-IFD holding a latitude reference.
+    fn exif_payload(tags: &[(u16, u16)]) -> Vec<u8> {
+        let mut entries = tags.to_vec();
+        entries.push((0x8825, 0)); // GPS IFD pointer, patched below
+        let gps_ifd = 8 + 2 + entries.len() * 12 + 4;
+        let mut tiff = b"MM\0\x2a".to_vec();
+        tiff.extend_from_slice(&8u32.to_be_bytes());
+        tiff.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+        for (tag, value) in entries {
+            tiff.extend_from_slice(&tag.to_be_bytes());
+            if tag == 0x8825 {
+                tiff.extend_from_slice(&4u16.to_be_bytes()); // LONG
+                tiff.extend_from_slice(&1u32.to_be_bytes());
+                tiff.extend_from_slice(&(gps_ifd as u32).to_be_bytes());
+            } else {
+                tiff.extend_from_slice(&3u16.to_be_bytes()); // SHORT
+                tiff.extend_from_slice(&1u32.to_be_bytes());
+                tiff.extend_from_slice(&value.to_be_bytes());
+                tiff.extend_from_slice(&[0, 0]);
+            }
+        }
+        tiff.extend_from_slice(&0u32.to_be_bytes());
+        tiff.extend_from_slice(&1u16.to_be_bytes());
+        tiff.extend_from_slice(&1u16.to_be_bytes()); // GPSLatitudeRef
+        tiff.extend_from_slice(&2u16.to_be_bytes()); // ASCII
+        tiff.extend_from_slice(&2u32.to_be_bytes());
+        tiff.extend_from_slice(b"N\0\0\0");
+        tiff.extend_from_slice(&0u32.to_be_bytes());
+        [b"Exif\0\0".as_slice(), &tiff].concat()
+    }
+
+    /// Offset of the first segment after SOI and APP0.
+    fn after_app0(jpeg: &[u8]) -> usize {
+        assert_eq!(&jpeg[2..4], &[0xFF, Jpeg::APP0]);
+        4 + be16(jpeg, 4).unwrap() as usize
+    }
+
+    #[test]
+    fn jpeg_loses_exif_and_keeps_image_data_and_icc() {
+        let plain = encoded(image::ImageFormat::Jpeg);
+        let split = after_app0(&plain);
+        let icc = jpeg_segment(Jpeg::APP2, b"ICC_PROFILE\0\x01\x01not a real profile");
+        let gps = jpeg_segment(Jpeg::APP1, &exif_payload(&[]));
+        let tagged = [&plain[..split], &gps, &icc, &plain[split..]].concat();
+
+        let cleaned = Jpeg.strip(&tagged).unwrap().unwrap();
+        // Only the EXIF segment went; APP0, ICC, tables, SOF, SOS and the
+        // entropy-coded data are the same bytes as before.
+        assert_eq!(cleaned, [&plain[..split], &icc, &plain[split..]].concat());
+        assert!(!cleaned.windows(4).any(|w| w == b"Exif"));
+        assert_eq!(image::load_from_memory(&cleaned).unwrap().width(), 8);
+        assert_eq!(Jpeg.strip(&cleaned), Ok(None));
+
+        // An orientation survives on its own; the GPS position does not.
+        let rotated = [&plain[..split], &jpeg_segment(Jpeg::APP1, &exif_payload(&[(0x0112, 6)])), &plain[split..]].concat();
+        let cleaned = Jpeg.strip(&rotated).unwrap().unwrap();
+        let stub = Jpeg::orientation_segment(6);
+        assert_eq!(cleaned, [&plain[..split], &stub, &plain[split..]].concat());
+        assert!(!cleaned.windows(2).any(|w| w == 0x8825u16.to_be_bytes()));
+    }
+
+    fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(&crc32fast::hash(&chunk[4..]).to_be_bytes());
+        chunk
+    }
+
+    fn png_chunks(png: &[u8]) -> Vec<(String, &[u8])> {
+        let mut chunks = Vec::new();
+        let mut pos = Png::SIGNATURE.len();
+        while pos < png.len() {
+            let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+            let kind = String::from_utf8_lossy(&png[pos + 4..pos + 8]).to_string();
+            chunks.push((kind, &png[pos..pos + 12 + len]));
+            pos += 12 + len;
+        }
+        chunks
+    }
+
+    #[test]
+    fn png_loses_text_and_exif_chunks_and_keeps_idat() {
+        let plain = encoded(image::ImageFormat::Png);
+        let ihdr_end = Png::SIGNATURE.len() + 12 + 13;
+        let metadata = [
+            png_chunk(b"eXIf", &exif_payload(&[])[6..]),
+            png_chunk(b"tEXt", b"Author\0Someone"),
+            png_chunk(b"zTXt", b"Comment\0\0x\x9c\x03\0\0\0\0\x01"),
+            png_chunk(b"iTXt", b"Location\0\0\0\0\0Somewhere"),
+        ]
+        .concat();
+        let tagged = [&plain[..ihdr_end], &metadata, &plain[ihdr_end..]].concat();
+
+        let cleaned = Png.strip(&tagged).unwrap().unwrap();
+        assert_eq!(cleaned, plain);
+        let kinds: Vec<String> = png_chunks(&cleaned).into_iter().map(|(k, _)| k).collect();
+        assert!(kinds.iter().all(|k| !["eXIf", "tEXt", "zTXt", "iTXt"].contains(&k.as_str())));
+        let idat = |png| -> Vec<Vec<u8>> {
+            png_chunks(png).into_iter().filter(|(k, _)| k == "IDAT").map(|(_, c)| c.to_vec()).collect()
+        };
+        assert!(!idat(&cleaned).is_empty());
+        assert_eq!(idat(&cleaned), idat(&plain));
+        assert_eq!(image::load_from_memory(&cleaned).unwrap().height(), 6);
+    }
+
+    fn riff_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    #[test]
+    fn webp_loses_exif_and_xmp_and_clears_their_flags() {
+        let plain = encoded(image::ImageFormat::WebP);
+        assert_eq!(&plain[12..16], b"VP8L");
+        let bitstream = &plain[12..];
+        // Extended layout: VP8X header, the image, then the metadata.
+        let mut vp8x = vec![WebP::VP8X_EXIF | WebP::VP8X_XMP, 0, 0, 0];
+        vp8x.extend_from_slice(&7u32.to_le_bytes()[..3]);
+        vp8x.extend_from_slice(&5u32.to_le_bytes()[..3]);
+        let body = [
+            riff_chunk(b"VP8X", &vp8x),
+            bitstream.to_vec(),
+            riff_chunk(b"EXIF", &exif_payload(&[])[6..]),
+            riff_chunk(b"XMP ", b"<x:xmpmeta>odd</x:xmpmeta>x"),
+        ]
+        .concat();
+        let tagged = [b"RIFF".as_slice(), &((body.len() + 4) as u32).to_le_bytes(), b"WEBP", &body].concat();
+
+        let cleaned = WebP.strip(&tagged).unwrap().unwrap();
+        assert_eq!(u32::from_le_bytes(cleaned[4..8].try_into().unwrap()) as usize, cleaned.len() - 8);
+        assert_eq!(&cleaned[12..16], b"VP8X");
+        assert_eq!(cleaned[20] & (WebP::VP8X_EXIF | WebP::VP8X_XMP), 0);
+        assert_eq!(&cleaned[30..], bitstream);
+        assert!(!cleaned.windows(4).any(|w| w == b"EXIF" || w == b"XMP "));
+        assert_eq!(image::load_from_memory(&cleaned).unwrap().width(), 8);
+    }
+
+    #[test]
+    fn mismatched_extension_is_left_alone() {
+        let dir = std::env::temp_dir().join(format!("shareboxx-sanitize-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let plain = encoded(image::ImageFormat::Png);
+        let tagged = [&plain[..33], &png_chunk(b"tEXt", b"Author\0Someone"), &plain[33..]].concat();
+
+        for name in ["photo.jpg", "photo.webp", "photo.png.txt", "photo"] {
+            let path = dir.join(name);
+            std::fs::write(&path, &tagged).unwrap();
+            assert!(!sanitize_file(&path, name).unwrap(), "{}", name);
+            assert_eq!(std::fs::read(&path).unwrap(), tagged, "{}", name);
+        }
+        let path = dir.join("photo.png");
+        std::fs::write(&path, &tagged).unwrap();
+        assert!(sanitize_file(&path, "photo.png").unwrap());
+        assert_eq!(std::fs::read(&path).unwrap(), plain);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
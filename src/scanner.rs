@@ -0,0 +1,190 @@
+//! Malware scanning of quarantined uploads via clamd, an external
+//! command, or a built-in fake that only detects the EICAR test file.
+
+use crate::config::ScannerConfig;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// The EICAR anti-virus test string, recognised by the fake scanner.
+const EICAR: &[u8] = br"X5O!P%@AP[4\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*";
+const CLAMD_CHUNK: usize = 64 * 1024;
+const CLAMD_TIMEOUT: Duration = Duration::from_secs(300);
+/// How long a scan command may run before it is killed.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict {
+    Clean,
+    /// Carries the signature name reported by the scanner.
+    Infected(String),
+    /// The scan could not be completed; the file stays in quarantine.
+    Error(String),
+}
+
+/// Scan `path` with the configured scanner. `ScannerConfig::None` always
+/// reports clean.
+pub fn scan(cfg: &ScannerConfig, path: &Path) -> Verdict {
+    match cfg {
+        ScannerConfig::None => Verdict::Clean,
+        ScannerConfig::Clamd { address } => scan_clamd(address, path),
+        ScannerConfig::Command { program, args } => scan_command(program, args, path, COMMAND_TIMEOUT),
+        ScannerConfig::Fake => scan_fake(path),
+    }
+}
+
+fn scan_fake(path: &Path) -> Verdict {
+    match std::fs::read(path) {
+        Ok(data) if data.windows(EICAR.len()).any(|w| w == EICAR) => {
+            Verdict::Infected("Eicar-Test-Signature".to_string())
+        }
+        Ok(_) => Verdict::Clean,
+        Err(e) => Verdict::Error(format!("read failed: {}", e)),
+    }
+}
+
+fn scan_command(program: &str, args: &[String], path: &Path, timeout: Duration) -> Verdict {
+    let mut child = match std::process::Command::new(program)
+        .args(args)
+        .arg(path)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+    {
+        Ok(c) => c,
+        Err(e) => return Verdict::Error(format!("failed to run {}: {}", program, e)),
+    };
+
+    // Drain stdout on the side so a chatty scanner can't block on a full
+    // pipe while we wait for it.
+    let (out_tx, out_rx) = std::sync::mpsc::channel();
+    if let Some(mut stdout) = child.stdout.take() {
+        std::thread::spawn(move || {
+            let mut out = Vec::new();
+            let _ = stdout.read_to_end(&mut out);
+            let _ = out_tx.send(out);
+        });
+    }
+
+    let deadline = std::time::Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if std::time::Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Verdict::Error(format!("{} timed out after {}s", program, timeout.as_secs()));
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(100)),
+            Err(e) => return Verdict::Error(format!("failed to wait for {}: {}", program, e)),
+        }
+    };
+
+    // Anything the scanner left running in the background may still hold
+    // the pipe open; don't wait on it for long.
+    let stdout = out_rx.recv_timeout(Duration::from_secs(1)).unwrap_or_default();
+    let report = String::from_utf8_lossy(&stdout)
+        .lines()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or("")
+        .trim()
+        .to_string();
+    match status.code() {
+        Some(0) => Verdict::Clean,
+        Some(1) => Verdict::Infected(if report.is_empty() { "infected".to_string() } else { report }),
+        Some(code) => Verdict::Error(format!("{} exited with {}", program, code)),
+        None => Verdict::Error(format!("{} was killed by a signal", program)),
+    }
+}
+
+fn scan_clamd(address: &str, path: &Path) -> Verdict {
+    let result = if address.starts_with('/') {
+        #[cfg(unix)]
+        {
+            std::os::unix::net::UnixStream::connect(address).and_then(|s| {
+                s.set_read_timeout(Some(CLAMD_TIMEOUT))?;
+                s.set_write_timeout(Some(CLAMD_TIMEOUT))?;
+                clamd_instream(s, path)
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            Err(std::io::Error::other("unix sockets are not supported here"))
+        }
+    } else {
+        std::net::TcpStream::connect(address).and_then(|s| {
+            s.set_read_timeout(Some(CLAMD_TIMEOUT))?;
+            s.set_write_timeout(Some(CLAMD_TIMEOUT))?;
+            clamd_instream(s, path)
+        })
+    };
+    match result {
+        Ok(reply) => parse_clamd_reply(&reply),
+        Err(e) => Verdict::Error(format!("clamd: {}", e)),
+    }
+}
+
+/// Stream the file to clamd as length-prefixed chunks and return its reply.
+fn clamd_instream<S: Read + Write>(mut sock: S, path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    sock.write_all(b"zINSTREAM\0")?;
+    let mut buf = vec![0u8; CLAMD_CHUNK];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        sock.write_all(&(n as u32).to_be_bytes())?;
+        sock.write_all(&buf[..n])?;
+    }
+    sock.write_all(&0u32.to_be_bytes())?;
+    sock.flush()?;
+
+    let mut reply = Vec::new();
+    sock.read_to_end(&mut reply)?;
+    let reply = String::from_utf8_lossy(&reply);
+    Ok(reply.trim_end_matches(['\0', '\n']).to_string())
+}
+
+/// clamd answers "stream: OK", "stream: <name> FOUND" or "... ERROR".
+fn parse_clamd_reply(reply: &str) -> Verdict {
+    let body = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+    if body == "OK" {
+        Verdict::Clean
+    } else if let Some(name) = body.strip_suffix("FOUND") {
+        Verdict::Infected(name.trim().to_string())
+    } else {
+        Verdict::Error(format!("clamd: {}", body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["-c".to_string(), script.to_string(), "scan".to_string()]
+    }
+
+    #[test]
+    fn command_verdict_follows_exit_code() {
+        let path = Path::new("/dev/null");
+        let timeout = Duration::from_secs(10);
+        assert_eq!(scan_command("sh", &sh("exit 0"), path, timeout), Verdict::Clean);
+        assert_eq!(
+            scan_command("sh", &sh("echo; echo \"$1: Bad.Thing FOUND\"; exit 1"), path, timeout),
+            Verdict::Infected("/dev/null: Bad.Thing FOUND".to_string())
+        );
+        assert!(matches!(scan_command("sh", &sh("exit 2"), path, timeout), Verdict::Error(_)));
+    }
+
+    #[test]
+    fn command_is_killed_at_the_deadline() {
+        let started = std::time::Instant::now();
+        let verdict = scan_command("sh", &sh("sleep 30"), Path::new("/dev/null"), Duration::from_millis(300));
+        assert!(matches!(verdict, Verdict::Error(ref e) if e.contains("timed out")), "{:?}", verdict);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
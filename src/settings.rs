@@ -0,0 +1,181 @@
+//! Where the server keeps its state and which directory it shares,
+//! decided once at startup from command-line flags and environment
+//! variables.
+//!
+//! The data directory holds `config.json`, `uploads.db`, `stats.json`, the
+//! thumbnail cache, in-progress and quarantined uploads and the legacy
+//! `chat.json`. The share root is the directory served under `/files`.
+//! Both default to the working directory layout the server has always
+//! used: `.` and `./files`.
+
+use once_cell::sync::OnceCell;
+use std::path::{Path, PathBuf};
+
+const DATA_DIR_ENV: &str = "SHAREBOXX_DATA_DIR";
+const SHARE_ROOT_ENV: &str = "SHAREBOXX_SHARE_ROOT";
+
+pub const USAGE: &str = "\
+Usage: shareboxx [--data-dir DIR] [--share-root DIR]
+       shareboxx hash-password < password-file
+
+  --data-dir DIR    state: config.json, uploads.db, stats.json, thumbnails,
+                    uploads in progress and in quarantine
+                    (env SHAREBOXX_DATA_DIR, default: working directory)
+  --share-root DIR  directory shared under /files
+                    (env SHAREBOXX_SHARE_ROOT, default: DATA_DIR/files)
+
+  hash-password     read a password on stdin and print the hash to put in
+                    config.json as admin_password_hash";
+
+static SETTINGS: OnceCell<Settings> = OnceCell::new();
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub data_dir: PathBuf,
+    pub share_root: PathBuf,
+}
+
+impl Settings {
+    /// Flags win over environment variables. Paths are made absolute so
+    /// that stripping the share root off a path always works.
+    pub fn from_args<I: IntoIterator<Item = String>>(
+        args: I,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let mut data_dir = env(DATA_DIR_ENV).map(PathBuf::from);
+        let mut share_root = env(SHARE_ROOT_ENV).map(PathBuf::from);
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((f, v)) => (f.to_string(), Some(v.to_string())),
+                None => (arg.clone(), None),
+            };
+            let slot = match flag.as_str() {
+                "--data-dir" => &mut data_dir,
+                "--share-root" => &mut share_root,
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            };
+            let value = inline
+                .or_else(|| args.next())
+                .filter(|v| !v.is_empty())
+                .ok_or_else(|| format!("{} needs a directory", flag))?;
+            *slot = Some(PathBuf::from(value));
+        }
+
+        let data_dir = data_dir.unwrap_or_else(|| PathBuf::from("."));
+        let share_root = share_root.unwrap_or_else(|| data_dir.join("files"));
+        let absolute = |p: &Path| std::path::absolute(p).map_err(|e| format!("{}: {}", p.display(), e));
+        Ok(Settings {
+            data_dir: absolute(&data_dir)?,
+            share_root: absolute(&share_root)?,
+        })
+    }
+
+    /// Create both directories if they don't exist yet.
+    pub fn ensure_dirs(&self) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.data_dir)?;
+        std::fs::create_dir_all(&self.share_root)
+    }
+}
+
+/// Install the settings for this process. Only the first call has an
+/// effect.
+pub fn init(settings: Settings) {
+    let _ = SETTINGS.set(settings);
+}
+
+/// The process settings; the defaults if `init` was never called.
+pub fn get() -> &'static Settings {
+    SETTINGS.get_or_init(default_settings)
+}
+
+#[cfg(not(test))]
+fn default_settings() -> Settings {
+    Settings::from_args(std::iter::empty(), |_| None).unwrap_or(Settings {
+        data_dir: PathBuf::from("."),
+        share_root: PathBuf::from("./files"),
+    })
+}
+
+/// Tests never fall back to the working directory: they all share one
+/// scratch directory for this test process.
+#[cfg(test)]
+fn default_settings() -> Settings {
+    let data_dir = std::env::temp_dir().join(format!("shareboxx-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+    Settings {
+        share_root: data_dir.join("files"),
+        data_dir,
+    }
+}
+
+/// The directory served under `/files`.
+pub fn share_root() -> &'static Path {
+    &get().share_root
+}
+
+/// A file or directory in the data directory.
+pub fn data_path(name: &str) -> PathBuf {
+    get().data_dir.join(name)
+}
+
+/// The scratch settings every test runs with, with both directories
+/// created.
+#[cfg(test)]
+pub fn init_for_tests() -> &'static Settings {
+    let settings = get();
+    settings.ensure_dirs().unwrap();
+    settings
+}
+
+/// Held by tests that change what is in the scratch directory (config.json,
+/// the share, the database), so they don't trip over each other.
+#[cfg(test)]
+pub fn lock_for_tests() -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str], env: &[(&str, &str)]) -> Result<Settings, String> {
+        let env: Vec<(String, String)> = env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Settings::from_args(args.iter().map(|a| a.to_string()), |key| {
+            env.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone())
+        })
+    }
+
+    #[test]
+    fn flags_override_env_in_either_form() {
+        let env = [(DATA_DIR_ENV, "/env/data"), (SHARE_ROOT_ENV, "/env/share")];
+        let s = parse(&[], &env).unwrap();
+        assert_eq!((s.data_dir, s.share_root), (PathBuf::from("/env/data"), PathBuf::from("/env/share")));
+
+        let s = parse(&["--data-dir=/flag/data", "--share-root", "/flag/share"], &env).unwrap();
+        assert_eq!((s.data_dir, s.share_root), (PathBuf::from("/flag/data"), PathBuf::from("/flag/share")));
+
+        let s = parse(&["--share-root=/flag/share"], &env).unwrap();
+        assert_eq!((s.data_dir, s.share_root), (PathBuf::from("/env/data"), PathBuf::from("/flag/share")));
+    }
+
+    #[test]
+    fn share_root_defaults_to_files_in_the_data_dir() {
+        let s = parse(&["--data-dir", "/srv/box"], &[]).unwrap();
+        assert_eq!(s.share_root, PathBuf::from("/srv/box/files"));
+
+        let s = parse(&[], &[]).unwrap();
+        assert_eq!(s.data_dir, std::path::absolute(".").unwrap());
+        assert_eq!(s.share_root, s.data_dir.join("files"));
+    }
+
+    #[test]
+    fn missing_or_empty_values_are_errors() {
+        assert_eq!(parse(&["--data-dir"], &[]).unwrap_err(), "--data-dir needs a directory");
+        assert_eq!(parse(&["--data-dir="], &[]).unwrap_err(), "--data-dir needs a directory");
+        assert_eq!(parse(&["--share-root", ""], &[]).unwrap_err(), "--share-root needs a directory");
+        assert_eq!(parse(&["--verbose"], &[]).unwrap_err(), "unknown argument '--verbose'");
+    }
+}
@@ -0,0 +1,158 @@
+//! Downscaled JPEG previews for the file listing, so phones don't have to
+//! fetch every full-size photo just to browse a directory.
+//!
+//! Thumbnails are cached in the data directory, one file per source path. The
+//! cached file's mtime is set to the source's mtime, so a changed source is
+//! detected with a single `stat` and the entry is regenerated in place.
+//!
+//! Decoding is the expensive part: it is capped in memory per image and
+//! only `MAX_JOBS` images are decoded at a time, so a directory full of
+//! large photos can't exhaust a small board.
+
+use image::codecs::jpeg::JpegEncoder;
+use lazy_static::lazy_static;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+
+const CACHE_DIR: &str = "thumbs";
+/// Longest edge of a generated thumbnail, in pixels.
+const MAX_EDGE: u32 = 320;
+const JPEG_QUALITY: u8 = 80;
+/// Most memory a decoder may allocate for one image. Enough for a 24
+/// megapixel photo; bigger images simply get no preview.
+const MAX_DECODE_ALLOC: u64 = 128 * 1024 * 1024;
+/// Images decoded at the same time.
+const MAX_JOBS: usize = 2;
+
+lazy_static! {
+    static ref JOBS: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+}
+
+/// One of the `MAX_JOBS` decoding slots, held until dropped.
+struct JobSlot;
+
+impl JobSlot {
+    /// Wait for a free slot.
+    fn take() -> JobSlot {
+        let (running, freed) = &*JOBS;
+        let mut running = running.lock().unwrap_or_else(|e| e.into_inner());
+        while *running >= MAX_JOBS {
+            running = freed.wait(running).unwrap_or_else(|e| e.into_inner());
+        }
+        *running += 1;
+        JobSlot
+    }
+}
+
+impl Drop for JobSlot {
+    fn drop(&mut self) {
+        let (running, freed) = &*JOBS;
+        *running.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        freed.notify_one();
+    }
+}
+
+fn cache_path(rel_path: &str) -> PathBuf {
+    let digest = Sha256::digest(rel_path.as_bytes());
+    crate::settings::data_path(CACHE_DIR).join(format!("{}.jpg", hex::encode(digest)))
+}
+
+/// Whether `name` is a raster format we can decode and shrink.
+pub fn is_thumbnailable(name: &str) -> bool {
+    let l = name.to_lowercase();
+    [".jpg", ".jpeg", ".png", ".gif", ".webp", ".bmp"]
+        .iter()
+        .any(|ext| l.ends_with(ext))
+}
+
+/// Return the cached thumbnail for `source` (known to clients as
+/// `rel_path`), generating it first if it is missing or stale.
+pub fn thumbnail_for(source: &Path, rel_path: &str) -> std::io::Result<PathBuf> {
+    let source_mtime = std::fs::metadata(source)?.modified()?;
+    let cached = cache_path(rel_path);
+    if let Ok(meta) = std::fs::metadata(&cached)
+        && meta.modified().ok() == Some(source_mtime)
+    {
+        return Ok(cached);
+    }
+
+    let thumb = {
+        let _slot = JobSlot::take();
+        let mut reader = image::ImageReader::open(source)?.with_guessed_format()?;
+        let mut limits = image::Limits::default();
+        limits.max_alloc = Some(MAX_DECODE_ALLOC);
+        reader.limits(limits);
+        let img = reader.decode().map_err(std::io::Error::other)?;
+        img.thumbnail(MAX_EDGE, MAX_EDGE).to_rgb8()
+    };
+
+    // Write under a unique name and rename, so concurrent requests for the
+    // same image never serve a half-written file.
+    std::fs::create_dir_all(crate::settings::data_path(CACHE_DIR))?;
+    let tmp = cached.with_extension(format!("{:016x}.tmp", rand::rngs::OsRng.next_u64()));
+    let result = (|| {
+        let mut file = std::fs::File::create(&tmp)?;
+        JpegEncoder::new_with_quality(&mut file, JPEG_QUALITY)
+            .encode_image(&thumb)
+            .map_err(std::io::Error::other)?;
+        file.set_modified(source_mtime)?;
+        std::fs::rename(&tmp, &cached)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result?;
+    Ok(cached)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn thumbnail_is_cached_and_follows_the_source() {
+        let _lock = crate::settings::lock_for_tests();
+        let settings = crate::settings::init_for_tests();
+        let dir = settings.share_root.join("thumbs-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("wide.png");
+        image::RgbImage::from_pixel(640, 480, image::Rgb([200, 30, 30])).save(&source).unwrap();
+
+        let cached = thumbnail_for(&source, "thumbs-test/wide.png").unwrap();
+        assert!(cached.starts_with(crate::settings::data_path(CACHE_DIR)));
+        assert!(!cached.starts_with(&settings.share_root));
+        assert_eq!(image::image_dimensions(&cached).unwrap(), (320, 240));
+        let source_mtime = std::fs::metadata(&source).unwrap().modified().unwrap();
+        assert_eq!(std::fs::metadata(&cached).unwrap().modified().unwrap(), source_mtime);
+
+        // Unchanged source: the cached file is served as is, whatever it holds.
+        std::fs::write(&cached, b"stale").unwrap();
+        std::fs::File::options().write(true).open(&cached).unwrap().set_modified(source_mtime).unwrap();
+        assert_eq!(thumbnail_for(&source, "thumbs-test/wide.png").unwrap(), cached);
+        assert_eq!(std::fs::read(&cached).unwrap(), b"stale");
+
+        // A new mtime on the source regenerates it in place.
+        let touched = SystemTime::now() - Duration::from_secs(3600);
+        std::fs::File::options().write(true).open(&source).unwrap().set_modified(touched).unwrap();
+        assert_eq!(thumbnail_for(&source, "thumbs-test/wide.png").unwrap(), cached);
+        assert_eq!(image::image_dimensions(&cached).unwrap(), (320, 240));
+        assert_eq!(std::fs::metadata(&cached).unwrap().modified().unwrap(), touched);
+
+        std::fs::remove_file(&cached).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn undecodable_image_is_an_error() {
+        let _lock = crate::settings::lock_for_tests();
+        let settings = crate::settings::init_for_tests();
+        let source = settings.share_root.join("not-really.png");
+        std::fs::write(&source, b"\x89PNG\r\n\x1a\n but then nothing useful").unwrap();
+        assert!(thumbnail_for(&source, "not-really.png").is_err());
+        assert!(!cache_path("not-really.png").exists());
+        std::fs::remove_file(&source).unwrap();
+    }
+}
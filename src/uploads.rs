@@ -0,0 +1,770 @@
+use crate::db::{self, PartialUpload};
+use lazy_static::lazy_static;
+use rand::RngCore;
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Directory in the data directory that holds in-progress resumable
+/// uploads. Keeping it out of the share means clients can neither list nor
+/// write into other people's uploads.
+const PARTIAL_DIR: &str = "partial";
+/// A partial upload that hasn't received a byte for this long is dropped.
+const STALE_AFTER_SECS: u64 = 24 * 60 * 60;
+/// How long a completed upload still answers for its id.
+const COMPLETED_KEPT_SECS: u64 = 60 * 60;
+
+lazy_static! {
+    /// Running SHA-256 of each resumable upload, together with the offset it
+    /// covers. Lost on restart, in which case the file is hashed once more
+    /// when it completes.
+    static ref HASHERS: Mutex<HashMap<String, (u64, Sha256)>> = Mutex::new(HashMap::new());
+    /// Uploads that a request is currently writing to or finishing.
+    static ref BUSY: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    NotFound,
+    /// The client's offset doesn't match what the server has; carries the
+    /// server-side offset so the client can resume from there.
+    OffsetMismatch(u64),
+    /// Another request is writing to this upload right now.
+    Busy,
+    /// The upload already completed; carries its size and the JSON
+    /// `Finalized` it produced.
+    Completed(u64, String),
+    /// The upload doesn't fit: over the size limit, past its declared
+    /// size, or more than the disk has room for.
+    TooLarge(String),
+    Invalid(String),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadError::NotFound => write!(f, "upload not found"),
+            UploadError::OffsetMismatch(o) => write!(f, "offset mismatch, server has {}", o),
+            UploadError::Busy => write!(f, "upload is busy"),
+            UploadError::Completed(..) => write!(f, "upload already completed"),
+            UploadError::TooLarge(s) => write!(f, "{}", s),
+            UploadError::Invalid(s) => write!(f, "{}", s),
+            UploadError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for UploadError {
+    fn from(e: std::io::Error) -> Self {
+        UploadError::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for UploadError {
+    fn from(e: rusqlite::Error) -> Self {
+        UploadError::Io(std::io::Error::other(e))
+    }
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// A bare file name: no separators, no parent references and no leading dot
+/// (dot-entries are reserved for the server's own bookkeeping).
+pub fn is_valid_file_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains('/') && !name.contains('\\') && !name.starts_with('.')
+}
+
+/// Where the bytes of partial upload `id` are staged.
+pub fn partial_path(id: &str) -> PathBuf {
+    crate::settings::data_path(PARTIAL_DIR).join(id)
+}
+
+/// Move a file, copying it when `to` is on another filesystem (the data
+/// directory and the share need not share a device). The copy goes to a
+/// hidden name next to `to` first, so the file never shows up half-written.
+pub fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {}
+        other => return other,
+    }
+    let name = to.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let tmp = to.with_file_name(format!(".{}.moving", name));
+    if let Err(e) = std::fs::copy(from, &tmp).and_then(|_| std::fs::rename(&tmp, to)) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    std::fs::remove_file(from)
+}
+
+/// Pick a free destination for `file_name` inside `upload_path`, appending
+/// `-1`, `-2`, ... to the stem (or to the whole name when it has no
+/// extension) until nothing is in the way.
+pub fn unique_destination(upload_path: &str, file_name: &str) -> PathBuf {
+    let dir = crate::settings::share_root().join(upload_path.trim_start_matches('/'));
+    free_name(&dir, file_name)
+}
+
+fn free_name(dir: &Path, file_name: &str) -> PathBuf {
+    let path = dir.join(file_name);
+    let (stem, ext) = match (
+        path.file_stem().and_then(|s| s.to_str()),
+        path.extension().and_then(|s| s.to_str()),
+    ) {
+        (Some(stem), Some(ext)) => (stem, Some(ext)),
+        _ => (file_name, None),
+    };
+    let mut new_path = path.clone();
+    let mut i = 1;
+    // symlink_metadata, so a dangling symlink also counts as taken.
+    while new_path.symlink_metadata().is_ok() {
+        new_path = match ext {
+            Some(ext) => dir.join(format!("{}-{}.{}", stem, i, ext)),
+            None => dir.join(format!("{}-{}", stem, i)),
+        };
+        i += 1;
+    }
+    new_path
+}
+
+/// Where an upload ended up. `duplicate_of` is set when identical content
+/// was already on the share, so no new copy was written. `pending_scan`
+/// means the file is held in quarantine and `rel_path` is where it will
+/// appear once the malware scan passes.
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct Finalized {
+    pub rel_path: String,
+    pub duplicate_of: Option<String>,
+    pub pending_scan: bool,
+}
+
+fn rel_to_share(path: &Path) -> String {
+    path.strip_prefix(crate::settings::share_root())
+        .unwrap_or(path)
+        .to_string_lossy()
+        .trim_start_matches('/')
+        .to_string()
+}
+
+/// SHA-256 of a file on disk, hex encoded.
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// A tracked file with the same content that is still on disk, if any.
+fn existing_copy(conn: &Connection, sha256: &str, size: u64) -> Option<String> {
+    let candidates = db::find_by_hash(conn, sha256).ok()?;
+    candidates.into_iter().find(|rel| {
+        std::fs::metadata(crate::settings::share_root().join(rel))
+            .map(|m| m.is_file() && m.len() == size)
+            .unwrap_or(false)
+    })
+}
+
+/// Hand a fully received upload over for publishing. With a malware
+/// scanner configured it goes to quarantine first and is published by the
+/// scan worker; otherwise it is published right away.
+pub fn finalize(
+    conn: Option<&Connection>,
+    staged: &Path,
+    upload_path: &str,
+    file_name: &str,
+    sha256: Option<String>,
+) -> std::io::Result<Finalized> {
+    if !crate::config::load().scanner.is_enabled() {
+        return publish(conn, staged, upload_path, file_name, sha256);
+    }
+    // Never let an unscanned file through just because the db is down.
+    let conn = conn.ok_or_else(|| std::io::Error::other("database unavailable"))?;
+    crate::quarantine::submit(conn, staged, upload_path, file_name, sha256.as_deref())?;
+    let rel = format!("{}/{}", upload_path.trim_matches('/'), file_name);
+    Ok(Finalized {
+        rel_path: rel.trim_start_matches('/').to_string(),
+        pending_scan: true,
+        ..Default::default()
+    })
+}
+
+/// Move a fully received upload from `staged` into the share and track it
+/// for expiration, stripping image metadata first when the config asks for
+/// it. `sha256` is the hash computed while the bytes streamed in; it is
+/// recomputed from disk when unknown.
+///
+/// If the same content is already shared, the staged copy is dropped: the
+/// existing file is hard-linked into the target directory, or simply
+/// pointed at when it already lives there (or the filesystem can't link).
+pub fn publish(
+    conn: Option<&Connection>,
+    staged: &Path,
+    upload_path: &str,
+    file_name: &str,
+    sha256: Option<String>,
+) -> std::io::Result<Finalized> {
+    // Sanitising rewrites the bytes, which invalidates a streamed hash.
+    let sha256 = if crate::config::load().strip_metadata
+        && crate::sanitize::sanitize_file(staged, file_name)?
+    {
+        None
+    } else {
+        sha256
+    };
+    let sha256 = match sha256 {
+        Some(h) => h,
+        None => hash_file(staged)?,
+    };
+    let size = std::fs::metadata(staged)?.len();
+
+    if let Some(existing) = conn.and_then(|c| existing_copy(c, &sha256, size)) {
+        let existing_path = crate::settings::share_root().join(&existing);
+        let target_dir = crate::settings::share_root().join(upload_path.trim_start_matches('/'));
+        let same_dir = match (existing_path.parent().map(Path::canonicalize), target_dir.canonicalize()) {
+            (Some(Ok(a)), Ok(b)) => a == b,
+            _ => false,
+        };
+        if !same_dir {
+            let dest = unique_destination(upload_path, file_name);
+            if std::fs::hard_link(&existing_path, &dest).is_ok() {
+                let _ = std::fs::remove_file(staged);
+                let rel = rel_to_share(&dest);
+                if let Some(conn) = conn {
+                    track(conn, &rel, size, &sha256);
+                }
+                return Ok(Finalized { rel_path: rel, duplicate_of: Some(existing), pending_scan: false });
+            }
+        }
+        let _ = std::fs::remove_file(staged);
+        return Ok(Finalized { rel_path: existing.clone(), duplicate_of: Some(existing), pending_scan: false });
+    }
+
+    let dest = unique_destination(upload_path, file_name);
+    move_file(staged, &dest)?;
+
+    // The relative path is what the serve_file/admin layers operate on, so
+    // strip the share root.
+    let rel = rel_to_share(&dest);
+    if let Some(conn) = conn {
+        track(conn, &rel, size, &sha256);
+    }
+    Ok(Finalized { rel_path: rel, duplicate_of: None, pending_scan: false })
+}
+
+/// Let open browsers know a published upload is now in its directory.
+/// Quarantined uploads are announced once the scan releases them, and a
+/// duplicate that resolved to an existing file changed nothing.
+pub fn announce(done: &Finalized) {
+    if !done.pending_scan && done.duplicate_of.as_ref() != Some(&done.rel_path) {
+        crate::app::ssr_imports::broadcast_file_event(crate::app::FileEvent::for_path(
+            &done.rel_path,
+            crate::app::FileChange::Created,
+        ));
+    }
+}
+
+/// Record a newly published file for expiration, dedup and search.
+fn track(conn: &Connection, rel: &str, size: u64, sha256: &str) {
+    if let Err(e) = db::record_upload(conn, rel, now_secs(), Some(sha256)) {
+        eprintln!("warning: failed to record upload {}: {}", rel, e);
+    }
+    if let Err(e) = db::index_file(conn, rel, size) {
+        eprintln!("warning: failed to index {}: {}", rel, e);
+    }
+}
+
+/// Size and free space, in bytes, of the filesystem holding `path`.
+pub fn disk_space(path: &Path) -> std::io::Result<(u64, u64)> {
+    let output = std::process::Command::new("df")
+        .args(["--output=size,avail", "-B1"])
+        .arg(path)
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stdout
+        .lines()
+        .nth(1)
+        .unwrap_or("")
+        .split_whitespace()
+        .map(|f| f.parse::<u64>().ok());
+    match (fields.next().flatten(), fields.next().flatten()) {
+        (Some(total), Some(avail)) => Ok((total, avail)),
+        _ => Err(std::io::Error::other("unexpected df output")),
+    }
+}
+
+/// Refuse an upload of `size` bytes that is over the configured limit, or
+/// that wouldn't fit next to the uploads already in progress. Both the
+/// staging area and the share have to hold it.
+fn check_room(conn: &Connection, size: u64) -> Result<(), UploadError> {
+    let max = crate::config::load().max_upload_size;
+    if size > max {
+        return Err(UploadError::TooLarge(format!(
+            "uploads are limited to {}",
+            crate::app::format_bytes(max)
+        )));
+    }
+    let needed = db::partial_bytes_outstanding(conn)?.saturating_add(size);
+    for dir in [crate::settings::get().data_dir.clone(), crate::settings::share_root().to_path_buf()] {
+        match disk_space(&dir) {
+            Ok((_, avail)) if avail < needed => {
+                return Err(UploadError::TooLarge("not enough free space on the server".to_string()));
+            }
+            Ok(_) => {}
+            // Don't turn every upload away because df is missing.
+            Err(e) => eprintln!("warning: cannot check free space in {}: {}", dir.display(), e),
+        }
+    }
+    Ok(())
+}
+
+/// Register a new resumable upload and create its empty staging file.
+pub fn begin(
+    conn: &Connection,
+    upload_path: &str,
+    file_name: &str,
+    total_size: u64,
+) -> Result<PartialUpload, UploadError> {
+    if !is_valid_file_name(file_name) {
+        return Err(UploadError::Invalid("invalid file name".to_string()));
+    }
+    let base = crate::settings::share_root().to_path_buf();
+    let dir = crate::app::resolve_safe_path(&base, upload_path)
+        .map_err(|_| UploadError::Invalid("invalid upload path".to_string()))?;
+    if !dir.is_dir() {
+        return Err(UploadError::Invalid("upload path is not a directory".to_string()));
+    }
+    check_room(conn, total_size)?;
+
+    let mut buf = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut buf);
+    let id = hex::encode(buf);
+
+    std::fs::create_dir_all(crate::settings::data_path(PARTIAL_DIR))?;
+    std::fs::File::create(partial_path(&id))?;
+
+    let now = now_secs();
+    db::create_partial(conn, &id, upload_path, file_name, total_size, now)?;
+    Ok(PartialUpload {
+        id,
+        upload_path: upload_path.to_string(),
+        file_name: file_name.to_string(),
+        total_size,
+        received: 0,
+        updated_at: now,
+        result: None,
+    })
+}
+
+/// Look up a partial upload, reconciling the acknowledged offset with the
+/// staging file (a dropped connection can leave bytes the DB never saw).
+/// A completed upload reports all of its bytes as received.
+pub fn status(conn: &Connection, id: &str) -> Result<PartialUpload, UploadError> {
+    let mut upload = db::get_partial(conn, id)?.ok_or(UploadError::NotFound)?;
+    if upload.result.is_some() {
+        return Ok(upload);
+    }
+    let on_disk = std::fs::metadata(partial_path(id))
+        .map_err(|_| UploadError::NotFound)?
+        .len();
+    if on_disk != upload.received {
+        upload.received = on_disk;
+        db::update_partial_received(conn, id, on_disk, now_secs())?;
+    }
+    Ok(upload)
+}
+
+/// Exclusive use of a partial upload, released on drop. Two requests
+/// appending at the same offset would both pass the offset check and
+/// interleave their bytes.
+struct Claim(String);
+
+impl Claim {
+    fn take(id: &str) -> Result<Claim, UploadError> {
+        let mut busy = BUSY.lock().map_err(|_| UploadError::Busy)?;
+        if !busy.insert(id.to_string()) {
+            return Err(UploadError::Busy);
+        }
+        Ok(Claim(id.to_string()))
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if let Ok(mut busy) = BUSY.lock() {
+            busy.remove(&self.0);
+        }
+    }
+}
+
+/// Appends incoming bytes to a partial upload's staging file, hashing them
+/// on the way through. Holds the upload for itself until dropped, so keep
+/// it around while completing the upload too.
+pub struct ChunkWriter {
+    id: String,
+    file: std::fs::File,
+    hasher: Option<Sha256>,
+    pub received: u64,
+    _claim: Claim,
+}
+
+impl ChunkWriter {
+    pub fn write(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        if let Err(e) = self.file.write_all(buf) {
+            // Part of `buf` may have landed; the running hash no longer
+            // matches the file.
+            self.hasher = None;
+            return Err(e);
+        }
+        if let Some(h) = self.hasher.as_mut() {
+            h.update(buf);
+        }
+        self.received += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Record how far the upload has got and keep the running hash for the
+    /// next chunk.
+    pub fn finish(&mut self, conn: &Connection) -> Result<(), UploadError> {
+        if let (Some(h), Ok(mut hashers)) = (self.hasher.take(), HASHERS.lock()) {
+            hashers.insert(self.id.clone(), (self.received, h));
+        }
+        db::update_partial_received(conn, &self.id, self.received, now_secs())?;
+        Ok(())
+    }
+}
+
+/// Open the staging file for appending at `offset`, which must equal the
+/// number of bytes the server already holds. Fails with `Busy` while
+/// another request has the upload open, and with `Completed` once it is
+/// done.
+pub fn open_at(
+    conn: &Connection,
+    id: &str,
+    offset: u64,
+) -> Result<(PartialUpload, ChunkWriter), UploadError> {
+    let claim = Claim::take(id)?;
+    let upload = status(conn, id)?;
+    if let Some(result) = upload.result {
+        return Err(UploadError::Completed(upload.total_size, result));
+    }
+    if offset != upload.received {
+        return Err(UploadError::OffsetMismatch(upload.received));
+    }
+    let file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(partial_path(id))?;
+    let hasher = match HASHERS.lock().ok().and_then(|mut h| h.remove(id)) {
+        Some((covered, h)) if covered == offset => Some(h),
+        _ if offset == 0 => Some(Sha256::new()),
+        _ => None,
+    };
+    let writer = ChunkWriter {
+        id: id.to_string(),
+        file,
+        hasher,
+        received: offset,
+        _claim: claim,
+    };
+    Ok((upload, writer))
+}
+
+/// Move a completed partial upload into the share. Its record stays for
+/// `COMPLETED_KEPT_SECS` with the result, so a client that lost the answer
+/// to its last chunk can still learn where the file went.
+pub fn complete(conn: &Connection, upload: &PartialUpload) -> Result<Finalized, UploadError> {
+    let sha256 = match HASHERS.lock().ok().and_then(|mut h| h.remove(&upload.id)) {
+        Some((covered, h)) if covered == upload.total_size => Some(hex::encode(h.finalize())),
+        _ => None,
+    };
+    let done = finalize(
+        Some(conn),
+        &partial_path(&upload.id),
+        &upload.upload_path,
+        &upload.file_name,
+        sha256,
+    )?;
+    let result = serde_json::to_string(&done).map_err(std::io::Error::other)?;
+    db::complete_partial(conn, &upload.id, &result, now_secs())?;
+    Ok(done)
+}
+
+fn forget_hasher(id: &str) {
+    if let Ok(mut hashers) = HASHERS.lock() {
+        hashers.remove(id);
+    }
+}
+
+/// Abandon a partial upload and remove its staging file.
+pub fn abort(conn: &Connection, id: &str) -> Result<(), UploadError> {
+    let _claim = Claim::take(id)?;
+    db::get_partial(conn, id)?.ok_or(UploadError::NotFound)?;
+    let _ = std::fs::remove_file(partial_path(id));
+    forget_hasher(id);
+    db::delete_partial(conn, id)?;
+    Ok(())
+}
+
+/// Drop partial uploads that have gone quiet for longer than
+/// `STALE_AFTER_SECS`, along with their staging files, and the records of
+/// uploads completed more than `COMPLETED_KEPT_SECS` ago.
+pub fn expire_stale() {
+    let conn = match db::open() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("partial upload sweep: cannot open db: {}", e);
+            return;
+        }
+    };
+    let now = now_secs();
+    let stale = match db::list_stale_partials(
+        &conn,
+        now.saturating_sub(STALE_AFTER_SECS),
+        now.saturating_sub(COMPLETED_KEPT_SECS),
+    ) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("partial upload sweep: list failed: {}", e);
+            return;
+        }
+    };
+    let mut removed = 0u32;
+    for upload in stale {
+        match std::fs::remove_file(partial_path(&upload.id)) {
+            Ok(_) => removed += 1,
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                eprintln!("partial upload sweep: failed to remove {}: {}", upload.id, e);
+                continue;
+            }
+        }
+        forget_hasher(&upload.id);
+        if let Err(e) = db::delete_partial(&conn, &upload.id) {
+            eprintln!("partial upload sweep: failed to delete row {}: {}", upload.id, e);
+        }
+    }
+    if removed > 0 {
+        println!("partial upload sweep: removed {} stale upload(s)", removed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("shareboxx-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn free_name_counts_up_with_and_without_extension() {
+        let dir = scratch_dir("free-name");
+        assert_eq!(free_name(&dir, "a.txt"), dir.join("a.txt"));
+        std::fs::write(dir.join("a.txt"), b"").unwrap();
+        std::fs::write(dir.join("a-1.txt"), b"").unwrap();
+        assert_eq!(free_name(&dir, "a.txt"), dir.join("a-2.txt"));
+
+        std::fs::write(dir.join("README"), b"").unwrap();
+        assert_eq!(free_name(&dir, "README"), dir.join("README-1"));
+        std::fs::write(dir.join("README-1"), b"").unwrap();
+        assert_eq!(free_name(&dir, "README"), dir.join("README-2"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Start an upload of `data` into the share root under a name unique to
+    /// the calling test.
+    fn begin_upload(conn: &Connection, name: &str, data: &[u8]) -> PartialUpload {
+        begin(conn, "", name, data.len() as u64).unwrap()
+    }
+
+    /// Send `chunk` at `offset` the way the PATCH handler does.
+    fn send(conn: &Connection, id: &str, offset: u64, chunk: &[u8]) -> Result<u64, UploadError> {
+        let (_, mut writer) = open_at(conn, id, offset)?;
+        writer.write(chunk)?;
+        writer.finish(conn)?;
+        Ok(writer.received)
+    }
+
+    #[test]
+    fn wrong_offset_is_refused() {
+        let _lock = crate::settings::lock_for_tests();
+        let conn = db::open_for_tests();
+        let upload = begin_upload(&conn, "offset.bin", b"0123456789");
+        assert!(matches!(open_at(&conn, &upload.id, 5), Err(UploadError::OffsetMismatch(0))));
+        assert_eq!(send(&conn, &upload.id, 0, b"0123").unwrap(), 4);
+        assert!(matches!(open_at(&conn, &upload.id, 0), Err(UploadError::OffsetMismatch(4))));
+        assert!(matches!(open_at(&conn, &upload.id, 10), Err(UploadError::OffsetMismatch(4))));
+        abort(&conn, &upload.id).unwrap();
+    }
+
+    #[test]
+    fn resumes_from_what_reached_the_disk() {
+        let _lock = crate::settings::lock_for_tests();
+        let conn = db::open_for_tests();
+        let data = b"hello, resumable world";
+        let upload = begin_upload(&conn, "resume.txt", data);
+        assert_eq!(send(&conn, &upload.id, 0, &data[..5]).unwrap(), 5);
+
+        // The connection drops after more bytes landed but before the
+        // offset was recorded.
+        {
+            let (_, mut writer) = open_at(&conn, &upload.id, 5).unwrap();
+            writer.write(&data[5..9]).unwrap();
+        }
+        assert_eq!(db::get_partial(&conn, &upload.id).unwrap().unwrap().received, 5);
+        assert_eq!(status(&conn, &upload.id).unwrap().received, 9);
+
+        assert_eq!(send(&conn, &upload.id, 9, &data[9..]).unwrap(), data.len() as u64);
+        let done = complete(&conn, &status(&conn, &upload.id).unwrap()).unwrap();
+        let published = crate::settings::share_root().join(&done.rel_path);
+        assert_eq!(std::fs::read(&published).unwrap(), data);
+        // The hash was recomputed from disk, since the running one was lost.
+        let sha = hex::encode(Sha256::digest(data));
+        assert_eq!(db::find_by_hash(&conn, &sha).unwrap(), vec![done.rel_path.clone()]);
+        std::fs::remove_file(published).unwrap();
+    }
+
+    #[test]
+    fn one_writer_at_a_time() {
+        let _lock = crate::settings::lock_for_tests();
+        let conn = db::open_for_tests();
+        let upload = begin_upload(&conn, "claim.bin", b"abcdef");
+        let (_, writer) = open_at(&conn, &upload.id, 0).unwrap();
+        assert!(matches!(open_at(&conn, &upload.id, 0), Err(UploadError::Busy)));
+        assert!(matches!(abort(&conn, &upload.id), Err(UploadError::Busy)));
+        drop(writer);
+        assert_eq!(send(&conn, &upload.id, 0, b"abc").unwrap(), 3);
+        abort(&conn, &upload.id).unwrap();
+        assert!(matches!(status(&conn, &upload.id), Err(UploadError::NotFound)));
+        assert!(!partial_path(&upload.id).exists());
+    }
+
+    #[test]
+    fn completed_upload_answers_again() {
+        let _lock = crate::settings::lock_for_tests();
+        let conn = db::open_for_tests();
+        let data = b"final answer";
+        let upload = begin_upload(&conn, "answer.txt", data);
+        let done = {
+            let (upload, mut writer) = open_at(&conn, &upload.id, 0).unwrap();
+            writer.write(data).unwrap();
+            writer.finish(&conn).unwrap();
+            complete(&conn, &upload).unwrap()
+        };
+
+        // The client never saw that answer and sends the last chunk again.
+        let expected = serde_json::to_string(&done).unwrap();
+        match open_at(&conn, &upload.id, 0) {
+            Err(UploadError::Completed(size, result)) => {
+                assert_eq!(size, data.len() as u64);
+                assert_eq!(result, expected);
+            }
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+        let again = status(&conn, &upload.id).unwrap();
+        assert_eq!(again.received, data.len() as u64);
+        assert_eq!(again.result, Some(expected));
+        std::fs::remove_file(crate::settings::share_root().join(&done.rel_path)).unwrap();
+    }
+
+    #[test]
+    fn expire_stale_drops_rows_and_files() {
+        let _lock = crate::settings::lock_for_tests();
+        let conn = db::open_for_tests();
+        let now = now_secs();
+        let stale = begin_upload(&conn, "stale.bin", b"0123");
+        send(&conn, &stale.id, 0, b"01").unwrap();
+        db::update_partial_received(&conn, &stale.id, 2, now - STALE_AFTER_SECS - 1).unwrap();
+        let finished = begin_upload(&conn, "finished.bin", b"0123");
+        db::complete_partial(&conn, &finished.id, "{}", now - COMPLETED_KEPT_SECS - 1).unwrap();
+        let recent = begin_upload(&conn, "recent.bin", b"0123");
+        let recently_finished = begin_upload(&conn, "recently-finished.bin", b"0123");
+        db::complete_partial(&conn, &recently_finished.id, "{}", now).unwrap();
+
+        expire_stale();
+
+        for gone in [&stale, &finished] {
+            assert!(db::get_partial(&conn, &gone.id).unwrap().is_none());
+            assert!(!partial_path(&gone.id).exists());
+        }
+        assert!(db::get_partial(&conn, &recent.id).unwrap().is_some());
+        assert!(partial_path(&recent.id).exists());
+        assert!(db::get_partial(&conn, &recently_finished.id).unwrap().is_some());
+        abort(&conn, &recent.id).unwrap();
+        abort(&conn, &recently_finished.id).unwrap();
+    }
+
+    /// Stage `data` and publish it into `dir` (relative to the share root).
+    fn publish_into(conn: &Connection, dir: &str, name: &str, data: &[u8]) -> Finalized {
+        std::fs::create_dir_all(crate::settings::share_root().join(dir)).unwrap();
+        let staged = scratch_dir(&format!("staged-{}", dir.replace('/', "-"))).join(name);
+        std::fs::write(&staged, data).unwrap();
+        let done = publish(Some(conn), &staged, dir, name, None).unwrap();
+        assert!(!staged.exists());
+        done
+    }
+
+    #[test]
+    fn identical_upload_becomes_a_hard_link() {
+        use std::os::unix::fs::MetadataExt;
+        let _lock = crate::settings::lock_for_tests();
+        let conn = db::open_for_tests();
+        let root = crate::settings::share_root();
+        let data = b"dedup: the very same bytes twice";
+
+        let first = publish_into(&conn, "dedup-a", "photo.jpg", data);
+        assert_eq!(first.duplicate_of, None);
+        let second = publish_into(&conn, "dedup-b", "copy.jpg", data);
+        assert_eq!(second.rel_path, "dedup-b/copy.jpg");
+        assert_eq!(second.duplicate_of.as_deref(), Some(first.rel_path.as_str()));
+        let a = std::fs::metadata(root.join(&first.rel_path)).unwrap();
+        let b = std::fs::metadata(root.join(&second.rel_path)).unwrap();
+        assert_eq!((a.dev(), a.ino()), (b.dev(), b.ino()));
+
+        // Into the directory that already holds it: nothing new is written.
+        let third = publish_into(&conn, "dedup-a", "again.jpg", data);
+        assert_eq!(third.rel_path, first.rel_path);
+        assert_eq!(third.duplicate_of.as_deref(), Some(first.rel_path.as_str()));
+        assert!(!root.join("dedup-a/again.jpg").exists());
+
+        std::fs::remove_dir_all(root.join("dedup-a")).unwrap();
+        std::fs::remove_dir_all(root.join("dedup-b")).unwrap();
+    }
+
+    #[test]
+    fn same_size_different_content_is_kept_apart() {
+        use std::os::unix::fs::MetadataExt;
+        let _lock = crate::settings::lock_for_tests();
+        let conn = db::open_for_tests();
+        let root = crate::settings::share_root();
+
+        let first = publish_into(&conn, "dedup-size", "one.txt", b"same length, AAAA");
+        let second = publish_into(&conn, "dedup-size", "two.txt", b"same length, BBBB");
+        assert_eq!(second.duplicate_of, None);
+        assert_ne!(first.rel_path, second.rel_path);
+        let a = std::fs::metadata(root.join(&first.rel_path)).unwrap();
+        let b = std::fs::metadata(root.join(&second.rel_path)).unwrap();
+        assert_ne!(a.ino(), b.ino());
+        assert_eq!(std::fs::read(root.join(&second.rel_path)).unwrap(), b"same length, BBBB");
+
+        std::fs::remove_dir_all(root.join("dedup-size")).unwrap();
+    }
+}
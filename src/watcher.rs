@@ -0,0 +1,274 @@
+//! Keeps the database in step with the share when files change behind the
+//! server's back (rsync, a bind mount, someone with a shell).
+//!
+//! On startup the whole share is walked once: the search index is rebuilt,
+//! files nobody recorded start being tracked for expiration, and rows for
+//! files that no longer exist are dropped. After that an inotify watch on
+//! the share reconciles individual paths as they change and tells open
+//! browsers which directories to refresh.
+
+use crate::app::ssr_imports::broadcast_file_event;
+use crate::app::{FileChange, FileEvent};
+use crate::db;
+use crate::settings::share_root;
+use crate::uploads::now_secs;
+use notify::{EventKind, RecursiveMode, Watcher};
+use rusqlite::Connection;
+use std::collections::{BTreeSet, HashSet};
+use std::path::Path;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+/// How long the share has to be quiet before queued changes are applied,
+/// so a file being copied in is handled once rather than per write.
+const SETTLE: Duration = Duration::from_secs(1);
+
+/// Reconcile the whole share, then watch it for changes on a background
+/// thread for the life of the process.
+pub fn spawn() {
+    std::thread::spawn(|| {
+        reconcile_all();
+        if let Err(e) = watch() {
+            eprintln!("watcher: stopped: {}", e);
+        }
+    });
+}
+
+/// Share-relative path for a path reported by the watcher, or `None` for
+/// the server's own dot-directories (staging, quarantine, multipart temp
+/// files) and anything outside the share.
+fn share_relative(path: &Path, base: &Path) -> Option<String> {
+    let rel = path
+        .strip_prefix(base)
+        .or_else(|_| path.strip_prefix(share_root()))
+        .ok()?;
+    let rel = rel.to_str()?;
+    if rel.is_empty() || rel.split('/').any(|seg| seg.starts_with('.')) {
+        return None;
+    }
+    Some(rel.to_string())
+}
+
+fn watch() -> notify::Result<()> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(share_root(), RecursiveMode::Recursive)?;
+    let base = share_root().canonicalize()?;
+
+    let mut pending: HashSet<String> = HashSet::new();
+    loop {
+        let received = if pending.is_empty() {
+            rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            rx.recv_timeout(SETTLE)
+        };
+        match received {
+            Ok(Ok(event)) => {
+                if event.need_rescan() {
+                    pending.clear();
+                    reconcile_all();
+                    continue;
+                }
+                if matches!(event.kind, EventKind::Access(_)) {
+                    continue;
+                }
+                pending.extend(event.paths.iter().filter_map(|p| share_relative(p, &base)));
+            }
+            Ok(Err(e)) => eprintln!("watcher: {}", e),
+            Err(RecvTimeoutError::Timeout) => apply(std::mem::take(&mut pending)),
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+fn apply(paths: HashSet<String>) {
+    let conn = match db::open() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("watcher: cannot open db: {}", e);
+            return;
+        }
+    };
+    let now = now_secs();
+    // One event per directory, however many of its files changed.
+    let mut dirs = BTreeSet::new();
+    for rel in paths {
+        if let Err(e) = reconcile_path(&conn, &rel, now) {
+            eprintln!("watcher: failed to reconcile {}: {}", rel, e);
+        }
+        dirs.insert(FileEvent::for_path(&rel, FileChange::Changed).dir);
+    }
+    for dir in dirs {
+        broadcast_file_event(FileEvent::in_dir(&dir, FileChange::Changed));
+    }
+}
+
+/// Bring the rows for one share path in line with the filesystem.
+fn reconcile_path(conn: &Connection, rel: &str, now: u64) -> rusqlite::Result<()> {
+    let full = share_root().join(rel);
+    match std::fs::metadata(&full) {
+        Ok(meta) if meta.is_file() => {
+            db::track_existing(conn, rel, now, false)?;
+            db::index_file(conn, rel, meta.len())
+        }
+        Ok(meta) if meta.is_dir() => {
+            // A directory moved in whole only produces one event.
+            let entries = crate::zip_stream::collect_entries(&full).unwrap_or_default();
+            for entry in entries.iter().filter(|e| !e.name.ends_with('/')) {
+                let child = format!("{}/{}", rel, entry.name);
+                db::track_existing(conn, &child, now, false)?;
+                db::index_file(conn, &child, entry.size)?;
+            }
+            Ok(())
+        }
+        _ => {
+            db::delete_by_path(conn, rel)?;
+            db::unindex_file(conn, rel)?;
+            db::forget_under(conn, rel)
+        }
+    }
+}
+
+/// Walk the whole share and reconcile the database against it.
+pub fn reconcile_all() {
+    let entries = match crate::zip_stream::collect_entries(share_root()) {
+        Ok(e) => e,
+        Err(e) => {
+            eprintln!("watcher: cannot walk share: {}", e);
+            return;
+        }
+    };
+    let files: Vec<(String, u64)> = entries
+        .into_iter()
+        .filter(|e| !e.name.ends_with('/'))
+        .map(|e| (e.name, e.size))
+        .collect();
+
+    let result = db::open().and_then(|mut conn| {
+        db::rebuild_file_index(&mut conn, &files)?;
+
+        // Before this ran for the first time, files without a row were
+        // either approved or copied in by the admin; neither should start
+        // expiring now, so they are adopted as pinned.
+        let first_run = !db::reconciled_before(&conn)?;
+        let now = now_secs();
+        let mut adopted = 0;
+        for (rel, _) in &files {
+            if db::track_existing(&conn, rel, now, first_run)? {
+                adopted += 1;
+            }
+        }
+
+        // An empty share next to a populated table most likely means the
+        // USB disk isn't mounted yet; don't throw the table away over it.
+        let mut dropped = 0;
+        if !files.is_empty() {
+            let on_disk: HashSet<&str> = files.iter().map(|(rel, _)| rel.as_str()).collect();
+            for rel in db::tracked_paths(&conn)? {
+                if !on_disk.contains(rel.as_str()) {
+                    db::delete_by_path(&conn, &rel)?;
+                    dropped += 1;
+                }
+            }
+        }
+        db::mark_reconciled(&conn)?;
+        Ok((adopted, dropped))
+    });
+    match result {
+        Ok((adopted, dropped)) => println!(
+            "watcher: {} file(s) indexed, {} newly tracked, {} stale row(s) dropped",
+            files.len(),
+            adopted,
+            dropped
+        ),
+        Err(e) => eprintln!("watcher: reconcile failed: {}", e),
+    }
+    broadcast_file_event(FileEvent::in_dir("", FileChange::Rescan));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(rel: &str) {
+        let path = share_root().join(rel);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, rel).unwrap();
+    }
+
+    fn unpinned(conn: &Connection) -> Vec<String> {
+        let mut paths: Vec<String> = db::list_tracked(conn).unwrap().into_iter().map(|(_, rel, _)| rel).collect();
+        paths.sort();
+        paths
+    }
+
+    fn tracked(conn: &Connection) -> Vec<String> {
+        let mut paths = db::tracked_paths(conn).unwrap();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn reconcile_all_adopts_then_tracks_and_drops() {
+        let _lock = crate::settings::lock_for_tests();
+        let conn = db::open_for_tests();
+        let _ = std::fs::remove_dir_all(share_root());
+        std::fs::create_dir_all(share_root()).unwrap();
+        conn.execute_batch("DELETE FROM uploads; DELETE FROM meta;").unwrap();
+
+        // First run: what is already there is adopted and never expires.
+        write("old.txt");
+        write("docs/a.txt");
+        reconcile_all();
+        assert!(db::reconciled_before(&conn).unwrap());
+        assert_eq!(tracked(&conn), ["docs/a.txt", "old.txt"]);
+        assert!(unpinned(&conn).is_empty());
+        assert_eq!(db::indexed_paths(&conn), ["docs/a.txt", "old.txt"]);
+
+        // Later runs track newcomers like uploads.
+        write("new.txt");
+        reconcile_all();
+        assert_eq!(unpinned(&conn), ["new.txt"]);
+
+        std::fs::remove_file(share_root().join("old.txt")).unwrap();
+        reconcile_all();
+        assert_eq!(tracked(&conn), ["docs/a.txt", "new.txt"]);
+        assert_eq!(db::indexed_paths(&conn), ["docs/a.txt", "new.txt"]);
+
+        // An empty share looks like an unmounted disk: keep the rows.
+        std::fs::remove_dir_all(share_root()).unwrap();
+        std::fs::create_dir_all(share_root()).unwrap();
+        reconcile_all();
+        assert_eq!(tracked(&conn), ["docs/a.txt", "new.txt"]);
+
+        conn.execute_batch("DELETE FROM uploads;").unwrap();
+    }
+
+    #[test]
+    fn reconcile_path_follows_files_and_directories() {
+        let _lock = crate::settings::lock_for_tests();
+        let conn = db::open_for_tests();
+        let now = now_secs();
+
+        write("moved-in/a.txt");
+        write("moved-in/deeper/b.txt");
+        reconcile_path(&conn, "moved-in", now).unwrap();
+        let under = |paths: Vec<String>| -> Vec<String> {
+            paths.into_iter().filter(|p| p.starts_with("moved-in/")).collect()
+        };
+        assert_eq!(under(tracked(&conn)), ["moved-in/a.txt", "moved-in/deeper/b.txt"]);
+        assert_eq!(under(unpinned(&conn)), ["moved-in/a.txt", "moved-in/deeper/b.txt"]);
+        assert_eq!(under(db::indexed_paths(&conn)), ["moved-in/a.txt", "moved-in/deeper/b.txt"]);
+
+        std::fs::remove_file(share_root().join("moved-in/a.txt")).unwrap();
+        reconcile_path(&conn, "moved-in/a.txt", now).unwrap();
+        assert_eq!(under(tracked(&conn)), ["moved-in/deeper/b.txt"]);
+        assert_eq!(under(db::indexed_paths(&conn)), ["moved-in/deeper/b.txt"]);
+
+        // Removing the directory clears everything that was under it.
+        std::fs::remove_dir_all(share_root().join("moved-in")).unwrap();
+        reconcile_path(&conn, "moved-in", now).unwrap();
+        assert!(under(tracked(&conn)).is_empty());
+        assert!(under(db::indexed_paths(&conn)).is_empty());
+    }
+}
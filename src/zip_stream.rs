@@ -0,0 +1,463 @@
+//! Store-only ZIP writer that emits the archive front to back, so a whole
+//! directory can be streamed to the client without staging it on disk.
+//!
+//! Each entry is written with a data descriptor (the CRC is only known once
+//! the file has been read). Entries of 4 GiB or more, and archives whose
+//! offsets outgrow 32 bits, use the ZIP64 extensions.
+
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LOCAL_HEADER_SIG: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR_SIG: u32 = 0x0807_4b50;
+const CENTRAL_HEADER_SIG: u32 = 0x0201_4b50;
+const ZIP64_EOCD_SIG: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIG: u32 = 0x0706_4b50;
+const EOCD_SIG: u32 = 0x0605_4b50;
+
+/// Bit 3: sizes and CRC follow in a data descriptor. Bit 11: UTF-8 names.
+const FLAGS: u16 = 0x0808;
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+const U32_MAX: u64 = 0xFFFF_FFFF;
+
+/// A file or directory to put in the archive.
+pub struct Entry {
+    /// Archive-relative name using '/' separators; directories end in '/'.
+    pub name: String,
+    pub source: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+impl Entry {
+    fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+}
+
+/// Recursively list `dir`, skipping dot-entries, with names relative to it.
+/// Directories come before their contents; siblings are sorted by name.
+pub fn collect_entries(dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut out = Vec::new();
+    collect_into(dir, "", &mut out)?;
+    Ok(out)
+}
+
+fn collect_into(dir: &Path, prefix: &str, out: &mut Vec<Entry>) -> io::Result<()> {
+    let mut children: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| Some((e.file_name().into_string().ok()?, e)))
+        .filter(|(name, _)| !name.starts_with('.'))
+        .collect();
+    children.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (name, entry) in children {
+        let Ok(meta) = entry.metadata() else { continue };
+        let modified = meta.modified().unwrap_or(UNIX_EPOCH);
+        if meta.is_dir() {
+            let dir_name = format!("{}{}/", prefix, name);
+            out.push(Entry {
+                name: dir_name.clone(),
+                source: entry.path(),
+                size: 0,
+                modified,
+            });
+            collect_into(&entry.path(), &dir_name, out)?;
+        } else if meta.is_file() {
+            out.push(Entry {
+                name: format!("{}{}", prefix, name),
+                source: entry.path(),
+                size: meta.len(),
+                modified,
+            });
+        }
+    }
+    Ok(())
+}
+
+struct CentralRecord {
+    name: Vec<u8>,
+    crc: u32,
+    size: u64,
+    offset: u64,
+    time: u16,
+    date: u16,
+    is_dir: bool,
+}
+
+/// Writes a ZIP archive to `out` one entry at a time.
+pub struct ZipStream<W: Write> {
+    out: W,
+    offset: u64,
+    records: Vec<CentralRecord>,
+}
+
+impl<W: Write> ZipStream<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            offset: 0,
+            records: Vec::new(),
+        }
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Append `entry`, reading file contents from its source path. At most
+    /// `entry.size` bytes are stored even if the file grows meanwhile. The
+    /// source is opened before anything is written, so a file that vanished
+    /// since it was listed fails cleanly and the archive stays valid.
+    pub fn add(&mut self, entry: &Entry) -> io::Result<()> {
+        let src = if entry.is_dir() {
+            None
+        } else {
+            Some(std::fs::File::open(&entry.source)?)
+        };
+        let name = entry.name.as_bytes().to_vec();
+        let (time, date) = dos_datetime(entry.modified);
+        let zip64 = entry.size >= U32_MAX;
+        let header_offset = self.offset;
+
+        let mut h = Vec::with_capacity(30 + name.len() + 20);
+        h.extend_from_slice(&LOCAL_HEADER_SIG.to_le_bytes());
+        h.extend_from_slice(&(if zip64 { VERSION_ZIP64 } else { VERSION_DEFAULT }).to_le_bytes());
+        h.extend_from_slice(&FLAGS.to_le_bytes());
+        h.extend_from_slice(&0u16.to_le_bytes()); // stored
+        h.extend_from_slice(&time.to_le_bytes());
+        h.extend_from_slice(&date.to_le_bytes());
+        h.extend_from_slice(&0u32.to_le_bytes()); // crc, in descriptor
+        let placeholder: u32 = if zip64 { u32::MAX } else { 0 };
+        h.extend_from_slice(&placeholder.to_le_bytes());
+        h.extend_from_slice(&placeholder.to_le_bytes());
+        h.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        h.extend_from_slice(&(if zip64 { 20u16 } else { 0 }).to_le_bytes());
+        h.extend_from_slice(&name);
+        if zip64 {
+            h.extend_from_slice(&1u16.to_le_bytes());
+            h.extend_from_slice(&16u16.to_le_bytes());
+            h.extend_from_slice(&0u64.to_le_bytes());
+            h.extend_from_slice(&0u64.to_le_bytes());
+        }
+        self.put(&h)?;
+
+        let mut crc = crc32fast::Hasher::new();
+        let mut written = 0u64;
+        if let Some(src) = src {
+            let mut src = src.take(entry.size);
+            let mut buf = vec![0u8; 64 * 1024];
+            loop {
+                let n = src.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                crc.update(&buf[..n]);
+                self.put(&buf[..n])?;
+                written += n as u64;
+            }
+        }
+        let crc = crc.finalize();
+
+        let mut d = Vec::with_capacity(24);
+        d.extend_from_slice(&DATA_DESCRIPTOR_SIG.to_le_bytes());
+        d.extend_from_slice(&crc.to_le_bytes());
+        if zip64 {
+            d.extend_from_slice(&written.to_le_bytes());
+            d.extend_from_slice(&written.to_le_bytes());
+        } else {
+            d.extend_from_slice(&(written as u32).to_le_bytes());
+            d.extend_from_slice(&(written as u32).to_le_bytes());
+        }
+        self.put(&d)?;
+
+        self.records.push(CentralRecord {
+            name,
+            crc,
+            size: written,
+            offset: header_offset,
+            time,
+            date,
+            is_dir: entry.is_dir(),
+        });
+        Ok(())
+    }
+
+    /// Write the central directory and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let cd_start = self.offset;
+        let records = std::mem::take(&mut self.records);
+        for r in &records {
+            let big_size = r.size >= U32_MAX;
+            let big_offset = r.offset >= U32_MAX;
+            let mut extra = Vec::new();
+            if big_size {
+                extra.extend_from_slice(&r.size.to_le_bytes());
+                extra.extend_from_slice(&r.size.to_le_bytes());
+            }
+            if big_offset {
+                extra.extend_from_slice(&r.offset.to_le_bytes());
+            }
+            let version = if extra.is_empty() { VERSION_DEFAULT } else { VERSION_ZIP64 };
+            let size32 = if big_size { u32::MAX } else { r.size as u32 };
+            let offset32 = if big_offset { u32::MAX } else { r.offset as u32 };
+            // MS-DOS directory attribute, so extractors create the folder.
+            let external_attrs: u32 = if r.is_dir { 0x10 } else { 0 };
+
+            let mut h = Vec::with_capacity(46 + r.name.len() + 4 + extra.len());
+            h.extend_from_slice(&CENTRAL_HEADER_SIG.to_le_bytes());
+            h.extend_from_slice(&version.to_le_bytes()); // made by
+            h.extend_from_slice(&version.to_le_bytes()); // needed
+            h.extend_from_slice(&FLAGS.to_le_bytes());
+            h.extend_from_slice(&0u16.to_le_bytes());
+            h.extend_from_slice(&r.time.to_le_bytes());
+            h.extend_from_slice(&r.date.to_le_bytes());
+            h.extend_from_slice(&r.crc.to_le_bytes());
+            h.extend_from_slice(&size32.to_le_bytes());
+            h.extend_from_slice(&size32.to_le_bytes());
+            h.extend_from_slice(&(r.name.len() as u16).to_le_bytes());
+            let extra_len = if extra.is_empty() { 0 } else { 4 + extra.len() as u16 };
+            h.extend_from_slice(&extra_len.to_le_bytes());
+            h.extend_from_slice(&0u16.to_le_bytes()); // comment
+            h.extend_from_slice(&0u16.to_le_bytes()); // disk
+            h.extend_from_slice(&0u16.to_le_bytes()); // internal attrs
+            h.extend_from_slice(&external_attrs.to_le_bytes());
+            h.extend_from_slice(&offset32.to_le_bytes());
+            h.extend_from_slice(&r.name);
+            if !extra.is_empty() {
+                h.extend_from_slice(&1u16.to_le_bytes());
+                h.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+                h.extend_from_slice(&extra);
+            }
+            self.put(&h)?;
+        }
+        let cd_end = self.offset;
+        let cd_size = cd_end - cd_start;
+        let count = records.len() as u64;
+
+        let needs_zip64 = count >= 0xFFFF || cd_start >= U32_MAX || cd_size >= U32_MAX;
+        if needs_zip64 {
+            let mut z = Vec::with_capacity(56 + 20);
+            z.extend_from_slice(&ZIP64_EOCD_SIG.to_le_bytes());
+            z.extend_from_slice(&44u64.to_le_bytes()); // size of the rest
+            z.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            z.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            z.extend_from_slice(&0u32.to_le_bytes());
+            z.extend_from_slice(&0u32.to_le_bytes());
+            z.extend_from_slice(&count.to_le_bytes());
+            z.extend_from_slice(&count.to_le_bytes());
+            z.extend_from_slice(&cd_size.to_le_bytes());
+            z.extend_from_slice(&cd_start.to_le_bytes());
+
+            z.extend_from_slice(&ZIP64_LOCATOR_SIG.to_le_bytes());
+            z.extend_from_slice(&0u32.to_le_bytes());
+            z.extend_from_slice(&cd_end.to_le_bytes());
+            z.extend_from_slice(&1u32.to_le_bytes());
+            self.put(&z)?;
+        }
+
+        let count16 = if needs_zip64 { 0xFFFF } else { count as u16 };
+        let cd_size32 = if needs_zip64 { u32::MAX } else { cd_size as u32 };
+        let cd_start32 = if needs_zip64 { u32::MAX } else { cd_start as u32 };
+        let mut e = Vec::with_capacity(22);
+        e.extend_from_slice(&EOCD_SIG.to_le_bytes());
+        e.extend_from_slice(&0u16.to_le_bytes());
+        e.extend_from_slice(&0u16.to_le_bytes());
+        e.extend_from_slice(&count16.to_le_bytes());
+        e.extend_from_slice(&count16.to_le_bytes());
+        e.extend_from_slice(&cd_size32.to_le_bytes());
+        e.extend_from_slice(&cd_start32.to_le_bytes());
+        e.extend_from_slice(&0u16.to_le_bytes());
+        self.put(&e)?;
+
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// MS-DOS (time, date) for `t`, in UTC. Clamped to the format's 1980 epoch.
+fn dos_datetime(t: SystemTime) -> (u16, u16) {
+    let secs = t
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+        .max(315_532_800); // 1980-01-01
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil-from-days (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let time = ((rem / 3600) << 11) | (((rem % 3600) / 60) << 5) | ((rem % 60) / 2);
+    let date = (((year - 1980).min(127) as u64) << 9) | ((month as u64) << 5) | day as u64;
+    (time as u16, date as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(b: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(b[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(b: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(b[at..at + 4].try_into().unwrap())
+    }
+
+    fn u64_at(b: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(b[at..at + 8].try_into().unwrap())
+    }
+
+    struct Parsed {
+        name: String,
+        crc: u32,
+        size: u64,
+        data: Vec<u8>,
+    }
+
+    /// Read `zip` back the way an extractor does: from the end of central
+    /// directory record, through the ZIP64 record when the counts overflow,
+    /// to each central header and the local header and data it points at.
+    /// Returns the entries and whether ZIP64 end records were used.
+    fn parse(zip: &[u8]) -> (Vec<Parsed>, bool) {
+        let eocd = zip.len() - 22;
+        assert_eq!(u32_at(zip, eocd), EOCD_SIG);
+        assert_eq!(u16_at(zip, eocd + 20), 0, "no archive comment");
+        let mut count = u16_at(zip, eocd + 10) as u64;
+        let mut cd_size = u32_at(zip, eocd + 12) as u64;
+        let mut cd_start = u32_at(zip, eocd + 16) as u64;
+        let zip64 = count == 0xFFFF || cd_size == U32_MAX || cd_start == U32_MAX;
+        if zip64 {
+            let locator = eocd - 20;
+            assert_eq!(u32_at(zip, locator), ZIP64_LOCATOR_SIG);
+            let record = u64_at(zip, locator + 8) as usize;
+            assert_eq!(record, locator - 56);
+            assert_eq!(u32_at(zip, record), ZIP64_EOCD_SIG);
+            assert_eq!(u64_at(zip, record + 4), 44);
+            assert_eq!(u64_at(zip, record + 24), u64_at(zip, record + 32));
+            count = u64_at(zip, record + 32);
+            cd_size = u64_at(zip, record + 40);
+            cd_start = u64_at(zip, record + 48);
+            assert_eq!(cd_start + cd_size, record as u64);
+        } else {
+            assert_eq!(cd_start + cd_size, eocd as u64);
+        }
+
+        let mut out = Vec::new();
+        let mut at = cd_start as usize;
+        for _ in 0..count {
+            assert_eq!(u32_at(zip, at), CENTRAL_HEADER_SIG);
+            assert_eq!(u16_at(zip, at + 8), FLAGS);
+            assert_eq!(u16_at(zip, at + 10), 0, "stored");
+            let crc = u32_at(zip, at + 16);
+            let mut size = u32_at(zip, at + 20) as u64;
+            assert_eq!(size, u32_at(zip, at + 24) as u64);
+            let name_len = u16_at(zip, at + 28) as usize;
+            let extra_len = u16_at(zip, at + 30) as usize;
+            let comment_len = u16_at(zip, at + 32) as usize;
+            let mut offset = u32_at(zip, at + 42) as u64;
+            let name = String::from_utf8(zip[at + 46..at + 46 + name_len].to_vec()).unwrap();
+            let mut extra = &zip[at + 46 + name_len..at + 46 + name_len + extra_len];
+            if extra_len > 0 {
+                assert_eq!(u16_at(extra, 0), 1);
+                extra = &extra[4..];
+                if size == U32_MAX {
+                    size = u64_at(extra, 0);
+                    extra = &extra[16..];
+                }
+                if offset == U32_MAX {
+                    offset = u64_at(extra, 0);
+                }
+            }
+            at += 46 + name_len + extra_len + comment_len;
+
+            let local = offset as usize;
+            assert_eq!(u32_at(zip, local), LOCAL_HEADER_SIG);
+            let local_name_len = u16_at(zip, local + 26) as usize;
+            let local_extra_len = u16_at(zip, local + 28) as usize;
+            assert_eq!(&zip[local + 30..local + 30 + local_name_len], name.as_bytes());
+            let start = local + 30 + local_name_len + local_extra_len;
+            let data = zip[start..start + size as usize].to_vec();
+            let descriptor = start + size as usize;
+            assert_eq!(u32_at(zip, descriptor), DATA_DESCRIPTOR_SIG);
+            assert_eq!(u32_at(zip, descriptor + 4), crc);
+            out.push(Parsed { name, crc, size, data });
+        }
+        assert_eq!(at as u64, cd_start + cd_size);
+        (out, zip64)
+    }
+
+    #[test]
+    fn archive_reads_back_with_names_sizes_and_crcs() {
+        let dir = std::env::temp_dir().join(format!("shareboxx-zip-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("sub dir")).unwrap();
+        let big: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        std::fs::write(dir.join("a.txt"), b"hello").unwrap();
+        std::fs::write(dir.join("empty"), b"").unwrap();
+        std::fs::write(dir.join("sub dir").join("ünï.bin"), &big).unwrap();
+        std::fs::write(dir.join(".hidden"), b"secret").unwrap();
+
+        let mut zip = ZipStream::new(Vec::new());
+        for entry in collect_entries(&dir).unwrap() {
+            zip.add(&entry).unwrap();
+        }
+        let (entries, zip64) = parse(&zip.finish().unwrap());
+        assert!(!zip64);
+
+        let expected: [(&str, &[u8]); 4] = [
+            ("a.txt", b"hello"),
+            ("empty", b""),
+            ("sub dir/", b""),
+            ("sub dir/ünï.bin", &big),
+        ];
+        assert_eq!(entries.len(), expected.len());
+        for (entry, (name, data)) in entries.iter().zip(expected) {
+            assert_eq!(entry.name, name);
+            assert_eq!(entry.size, data.len() as u64);
+            assert_eq!(entry.crc, crc32fast::hash(data));
+            assert_eq!(entry.data, data);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn entry_count_overflow_uses_zip64_end_records() {
+        let dir = std::env::temp_dir().join(format!("shareboxx-zip64-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("x");
+        std::fs::write(&source, b"x").unwrap();
+
+        let count = 0xFFFF;
+        let mut zip = ZipStream::new(Vec::new());
+        for i in 0..count {
+            zip.add(&Entry {
+                name: format!("{}.txt", i),
+                source: source.clone(),
+                size: 1,
+                modified: UNIX_EPOCH,
+            })
+            .unwrap();
+        }
+        let (entries, zip64) = parse(&zip.finish().unwrap());
+        assert!(zip64);
+        assert_eq!(entries.len(), count);
+        assert_eq!(entries[count - 1].name, format!("{}.txt", count - 1));
+        assert!(entries.iter().all(|e| e.size == 1 && e.crc == crc32fast::hash(b"x") && e.data == b"x"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}